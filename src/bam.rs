@@ -0,0 +1,288 @@
+use byteorder::{ByteOrder, LittleEndian};
+use std::collections::HashMap;
+
+use crate::registry_parser::{HiveFile, HiveKind, Key};
+use crate::timeline::{EventType, Timeline};
+use crate::util::windows_time_to_utc;
+
+/// Per-user BAM and DAM keys under a control set's Services key: below
+/// `State` from Windows 10 1809, directly under the service before.
+const BAM_KEYS: &[(&str, &str)] = &[
+    (r"bam\State\UserSettings", "BAM"),
+    (r"bam\UserSettings", "BAM"),
+    (r"dam\State\UserSettings", "DAM"),
+    (r"dam\UserSettings", "DAM"),
+];
+
+/// Bookkeeping values kept next to the executables.
+const BAM_SETTINGS_VALUES: &[&str] = &["Version", "SequenceNumber"];
+
+const PROFILE_LIST_KEY: &str = r"Microsoft\Windows NT\CurrentVersion\ProfileList";
+const SAM_ACCOUNT_KEY: &str = r"SAM\Domains\Account";
+
+/// Accounts the SAM and the profile list do not name.
+const WELL_KNOWN_SIDS: &[(&str, &str)] = &[
+    ("S-1-5-18", "SYSTEM"),
+    ("S-1-5-19", "LOCAL SERVICE"),
+    ("S-1-5-20", "NETWORK SERVICE"),
+];
+
+/// Adds an execution event for every executable the Background and Desktop
+/// Activity Moderators recorded in the SYSTEM hive, attributed to the user
+/// whose SID it was recorded under. Returns the number of events.
+pub fn add_bam_events(hives: &[HiveFile], timeline: &mut Timeline) -> usize {
+    let Some(system) = hives.iter().find(|hive| hive.kind == HiveKind::System) else {
+        return 0;
+    };
+    let control_set = current_control_set(system);
+    let usernames = sid_usernames(hives);
+
+    let mut event_count = 0;
+    for &(path, moderator) in BAM_KEYS {
+        let Some(user_settings) = system.hive.key(&format!(r"{}\Services\{}", control_set, path)) else {
+            continue;
+        };
+        for user_key in user_settings.subkeys() {
+            event_count += add_user_events(&user_key, moderator, &usernames, &system.path, timeline);
+        }
+    }
+    event_count
+}
+
+fn add_user_events(user_key: &Key, moderator: &str, usernames: &HashMap<String, String>,
+                   hive_path: &str, timeline: &mut Timeline) -> usize {
+    let sid = &user_key.name;
+    let user = usernames.get(sid).map(String::as_str).unwrap_or(sid);
+
+    let mut event_count = 0;
+    for value in user_key.values() {
+        if BAM_SETTINGS_VALUES.iter().any(|name| value.name.eq_ignore_ascii_case(name)) {
+            continue;
+        }
+        // The data starts with the last execution time
+        let Some(last_run) = value.data.get(0..8).map(LittleEndian::read_u64).and_then(windows_time_to_utc) else {
+            continue;
+        };
+        let description = format!("User '{}' ran '{}' ({}).", user, value.name, moderator);
+        timeline.add_registry_event(last_run, EventType::ProgramExecution, description, hive_path)
+            .add_detail("SID", vec![sid.clone()]);
+        event_count += 1;
+    }
+    event_count
+}
+
+/// The control set the system booted with, from `Select\Current`.
+fn current_control_set(system: &HiveFile) -> String {
+    let current = system.hive.key("Select")
+        .and_then(|select| select.value("Current"))
+        .and_then(|value| value.as_u32())
+        .unwrap_or(1);
+    format!("ControlSet{:03}", current)
+}
+
+/// Maps SIDs to user names: from the profile paths in the SOFTWARE hive, then
+/// from the local accounts in the SAM.
+fn sid_usernames(hives: &[HiveFile]) -> HashMap<String, String> {
+    let mut usernames: HashMap<String, String> = WELL_KNOWN_SIDS.iter()
+        .map(|(sid, name)| (sid.to_string(), name.to_string()))
+        .collect();
+
+    if let Some(sam) = hives.iter().find(|hive| hive.kind == HiveKind::Sam) {
+        usernames.extend(sam_accounts(sam));
+    }
+
+    let profiles = hives.iter()
+        .find(|hive| hive.kind == HiveKind::Software)
+        .and_then(|software| software.hive.key(PROFILE_LIST_KEY));
+    for profile in profiles.iter().flat_map(|profiles| profiles.subkeys()) {
+        let Some(path) = profile.value("ProfileImagePath").and_then(|value| value.as_string()) else {
+            continue;
+        };
+        if let Some(name) = path.rsplit('\\').next().filter(|name| !name.is_empty()) {
+            usernames.insert(profile.name.clone(), name.to_string());
+        }
+    }
+    usernames
+}
+
+/// Local accounts by SID. The account names are the subkeys of
+/// `Users\Names`, whose default value has the account's RID as its type;
+/// the machine SID ends the `V` value of the Account key.
+fn sam_accounts(sam: &HiveFile) -> Vec<(String, String)> {
+    let Some(account) = sam.hive.key(SAM_ACCOUNT_KEY) else {
+        return Vec::new();
+    };
+    let Some(machine_sid) = account.value("V")
+        .and_then(|value| value.data.len().checked_sub(12).map(|start| value.data[start..].to_vec()))
+        .map(|sub_authorities| {
+            let parts: Vec<String> = sub_authorities.chunks_exact(4)
+                .map(|part| LittleEndian::read_u32(part).to_string())
+                .collect();
+            format!("S-1-5-21-{}", parts.join("-"))
+        })
+    else {
+        return Vec::new();
+    };
+
+    let names = sam.hive.key(&format!(r"{}\Users\Names", SAM_ACCOUNT_KEY));
+    names.iter()
+        .flat_map(|names| names.subkeys())
+        .filter_map(|name| {
+            let rid = name.value("")?.value_type;
+            Some((format!("{}-{}", machine_sid, rid), name.name))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry_parser::tests::HiveBuilder;
+    use crate::registry_parser::{Hive, REG_BINARY, REG_DWORD, REG_EXPAND_SZ};
+
+    /// 2024-01-15 10:02:03 UTC.
+    const LAST_RUN: u64 = 133497865234567890;
+    const BOB: &str = "S-1-5-21-1111-2222-3333-1001";
+    const CAROL: &str = "S-1-5-21-1111-2222-3333-1002";
+    const DOMAIN_USER: &str = "S-1-5-21-4444-5555-6666-1105";
+    const CMD: &str = r"\Device\HarddiskVolume3\Windows\System32\cmd.exe";
+    const NOTEPAD: &str = r"\Device\HarddiskVolume3\Windows\System32\notepad.exe";
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().chain([0]).flat_map(u16::to_le_bytes).collect()
+    }
+
+    /// A key holding `children` through an "lh" list.
+    fn parent(builder: &mut HiveBuilder, name: &str, children: &[u32], values: &[u32]) -> u32 {
+        let list = if children.is_empty() { u32::MAX } else { builder.list(b"lh", children) };
+        builder.key(name, children.len() as u32, list, values)
+    }
+
+    /// Nests `leaf` below the keys of `path`.
+    fn nest(builder: &mut HiveBuilder, path: &str, leaf: u32) -> u32 {
+        path.rsplit('\\').fold(leaf, |child, name| parent(builder, name, &[child], &[]))
+    }
+
+    fn hive_file(kind: HiveKind, path: &str, hive: Vec<u8>) -> HiveFile {
+        HiveFile { path: path.to_string(), kind, user: None, hive: Hive::new(hive).unwrap() }
+    }
+
+    /// A user's BAM key with one executable and the bookkeeping values.
+    fn user_settings(builder: &mut HiveBuilder, sid: &str, executable: &str) -> u32 {
+        let mut entry = LAST_RUN.to_le_bytes().to_vec();
+        entry.extend([0; 16]);
+        let values = [
+            builder.value(executable, REG_BINARY, &entry),
+            builder.value("Version", REG_DWORD, &1u32.to_le_bytes()),
+            builder.value("SequenceNumber", REG_DWORD, &9u32.to_le_bytes()),
+        ];
+        builder.key(sid, 0, u32::MAX, &values)
+    }
+
+    /// ControlSet002, the current one, has bob's entry in the Windows 10
+    /// 1809+ layout and the domain user's in the older one; ControlSet001
+    /// has an entry that must not be read.
+    fn system_hive() -> HiveFile {
+        let mut builder = HiveBuilder::new();
+        let bob = user_settings(&mut builder, BOB, CMD);
+        let state = nest(&mut builder, r"State\UserSettings", bob);
+        let domain_user = user_settings(&mut builder, DOMAIN_USER, NOTEPAD);
+        let legacy = nest(&mut builder, "UserSettings", domain_user);
+        let bam = parent(&mut builder, "bam", &[state, legacy], &[]);
+        let current = nest(&mut builder, r"ControlSet002\Services", bam);
+
+        let carol = user_settings(&mut builder, CAROL, CMD);
+        let previous = nest(&mut builder, r"ControlSet001\Services\bam\State\UserSettings", carol);
+
+        let select_values = [builder.value("Current", REG_DWORD, &2u32.to_le_bytes())];
+        let select = builder.key("Select", 0, u32::MAX, &select_values);
+        let root = parent(&mut builder, "ROOT", &[previous, current, select], &[]);
+        hive_file(HiveKind::System, r"Windows\System32\config\SYSTEM", builder.build(root))
+    }
+
+    /// Local accounts bob (RID 1001) and carol (1002), and a `V` value
+    /// ending with the sub-authorities of the machine SID S-1-5-21-1111-2222-3333.
+    fn sam_hive() -> HiveFile {
+        let mut builder = HiveBuilder::new();
+        let accounts: Vec<u32> = [("bob", 1001), ("carol", 1002)].iter()
+            .map(|&(name, rid)| {
+                let default = builder.value("", rid, &[]);
+                builder.key(name, 0, u32::MAX, &[default])
+            })
+            .collect();
+        let names = parent(&mut builder, "Names", &accounts, &[]);
+        let users = parent(&mut builder, "Users", &[names], &[]);
+
+        let mut v = vec![0xAA; 40];
+        v.extend([1111u32, 2222, 3333].iter().flat_map(|part| part.to_le_bytes()));
+        let v = [builder.value("V", REG_BINARY, &v)];
+        let account = parent(&mut builder, "Account", &[users], &v);
+        let root = nest(&mut builder, r"ROOT\SAM\Domains", account);
+        hive_file(HiveKind::Sam, r"Windows\System32\config\SAM", builder.build(root))
+    }
+
+    /// Profiles of carol, under another folder name, and of a domain user
+    /// the SAM does not know.
+    fn software_hive() -> HiveFile {
+        let mut builder = HiveBuilder::new();
+        let profiles: Vec<u32> = [(CAROL, r"C:\Users\carol.PC"), (DOMAIN_USER, r"%SystemDrive%\Users\dave")].iter()
+            .map(|&(sid, path)| {
+                let path = [builder.value("ProfileImagePath", REG_EXPAND_SZ, &utf16(path))];
+                builder.key(sid, 0, u32::MAX, &path)
+            })
+            .collect();
+        let profile_list = parent(&mut builder, "ProfileList", &profiles, &[]);
+        let root = nest(&mut builder, r"ROOT\Microsoft\Windows NT\CurrentVersion", profile_list);
+        hive_file(HiveKind::Software, r"Windows\System32\config\SOFTWARE", builder.build(root))
+    }
+
+    #[test]
+    fn names_local_accounts_from_the_sam() {
+        let sam = sam_hive();
+        let mut accounts = sam_accounts(&sam);
+        accounts.sort();
+        assert_eq!(accounts, [(BOB.to_string(), "bob".to_string()), (CAROL.to_string(), "carol".to_string())]);
+    }
+
+    #[test]
+    fn names_users_from_the_profile_list_before_the_sam() {
+        let usernames = sid_usernames(&[sam_hive(), software_hive()]);
+        assert_eq!(usernames[BOB], "bob");
+        assert_eq!(usernames[CAROL], "carol.PC");
+        assert_eq!(usernames[DOMAIN_USER], "dave");
+        assert_eq!(usernames["S-1-5-18"], "SYSTEM");
+
+        // Without a SAM, only the profile list and well-known SIDs name users
+        let usernames = sid_usernames(&[software_hive()]);
+        assert!(!usernames.contains_key(BOB));
+        assert_eq!(usernames[DOMAIN_USER], "dave");
+    }
+
+    #[test]
+    fn reads_both_layouts_of_the_current_control_set() {
+        let mut timeline = Timeline::new();
+        let count = add_bam_events(&[system_hive(), sam_hive(), software_hive()], &mut timeline);
+        assert_eq!(count, 2);
+
+        let descriptions: Vec<&str> = timeline.events.iter().map(|event| event.description.as_str()).collect();
+        assert_eq!(descriptions, [format!("User 'bob' ran '{}' (BAM).", CMD),
+                                  format!("User 'dave' ran '{}' (BAM).", NOTEPAD)]);
+        let event = &timeline.events[1];
+        assert!(matches!(event.event_type, EventType::ProgramExecution));
+        assert_eq!(event.timestamp.to_rfc3339(), "2024-01-15T10:02:03.456789+00:00");
+        assert_eq!(event.details[0].values, [DOMAIN_USER]);
+    }
+
+    #[test]
+    fn keeps_the_sid_of_unknown_users() {
+        let mut timeline = Timeline::new();
+        add_bam_events(&[system_hive()], &mut timeline);
+        assert_eq!(timeline.events[0].description, format!("User '{}' ran '{}' (BAM).", BOB, CMD));
+
+        let mut builder = HiveBuilder::new();
+        let root = builder.key("ROOT", 0, u32::MAX, &[]);
+        let empty = hive_file(HiveKind::System, "SYSTEM", builder.build(root));
+        assert_eq!(current_control_set(&empty), "ControlSet001");
+        assert_eq!(add_bam_events(&[empty], &mut Timeline::new()), 0);
+    }
+}
@@ -1,44 +1,191 @@
 use anyhow::{Context, Result};
-use memmap2::Mmap;
-use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use crate::ewf::{self, AcquisitionInfo, EwfImage};
+use crate::image_source::{FileSource, ImageSource, MmapSource, OffsetSource};
+use crate::partition::Volume;
+
+/// A forensic disk image opened from one or more segment files, exposing its
+/// logical media through whichever `ImageSource` backend fits the container.
 pub struct DiskImage {
-    pub data: Mmap,
+    source: Arc<dyn ImageSource>,
     pub path: String,
+    pub segment_count: usize,
+    pub format: ImageFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Raw,
+    Ewf,
 }
 
 impl DiskImage {
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
         let path = path.as_ref();
-        let file = File::open(path)
-            .context("Failed to open disk image file")?;
-        
-        let data = unsafe { Mmap::map(&file) }
-            .context("Failed to memory map disk image")?;
-        
+        let segments = discover_segments(path);
+
+        let (source, format): (Arc<dyn ImageSource>, ImageFormat) = if ewf::is_ewf_file(path) {
+            let image = EwfImage::open(&segments)
+                .context("Failed to decode EWF disk image")?;
+            (Arc::new(image), ImageFormat::Ewf)
+        } else {
+            // Block devices and some filesystems cannot be memory mapped
+            match MmapSource::open(&segments) {
+                Ok(source) if source.size() > 0 => (Arc::new(source), ImageFormat::Raw),
+                _ => (Arc::new(FileSource::open(&segments)?), ImageFormat::Raw),
+            }
+        };
+
         Ok(DiskImage {
-            data,
+            source,
             path: path.to_string_lossy().to_string(),
+            segment_count: segments.len(),
+            format,
         })
     }
-    
-    pub fn get_slice(&self, offset: usize, length: usize) -> Result<&[u8]> {
-        if offset + length > self.data.len() {
-            anyhow::bail!("Attempted to read beyond disk image bounds");
+
+    pub fn is_e01_format(&self) -> bool {
+        self.format == ImageFormat::Ewf
+    }
+
+    /// Returns a source covering just `volume`, with offsets relative to the
+    /// start of the volume.
+    pub fn volume_source(&self, volume: &Volume) -> OffsetSource {
+        OffsetSource::new(self.source.clone(), volume.offset, volume.size)
+    }
+}
+
+impl ImageSource for DiskImage {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        self.source.read_at(offset, buf)
+    }
+
+    fn size(&self) -> u64 {
+        self.source.size()
+    }
+
+    fn sector_size(&self) -> u32 {
+        self.source.sector_size()
+    }
+
+    fn metadata(&self) -> Option<&AcquisitionInfo> {
+        self.source.metadata()
+    }
+}
+
+/// Finds the sibling segment files that follow `first`: `.E02`, `.E03`, ...
+/// (then `.EAA`, `.EAB`, ...) for EnCase images and `.002`, `.003`, ... for
+/// split raw images. Any other image is a single segment.
+fn discover_segments(first: &Path) -> Vec<PathBuf> {
+    let mut segments = vec![first.to_path_buf()];
+
+    let extension = match first.extension().and_then(|ext| ext.to_str()) {
+        Some(extension) => extension,
+        None => return segments,
+    };
+
+    let next_extension: Box<dyn Fn(usize) -> Option<String>> = if extension.eq_ignore_ascii_case("e01") {
+        let uppercase = extension.starts_with('E');
+        Box::new(move |number| ewf_segment_extension(number, uppercase))
+    } else if !extension.is_empty() && extension.chars().all(|c| c.is_ascii_digit()) {
+        let width = extension.len();
+        let start: usize = extension.parse().unwrap_or(1);
+        Box::new(move |number| Some(format!("{:0width$}", start + number - 1, width = width)))
+    } else {
+        return segments;
+    };
+
+    for number in 2.. {
+        let candidate = match next_extension(number) {
+            Some(extension) => first.with_extension(extension),
+            None => break,
+        };
+        if !candidate.is_file() {
+            break;
+        }
+        segments.push(candidate);
+    }
+
+    segments
+}
+
+fn ewf_segment_extension(number: usize, uppercase: bool) -> Option<String> {
+    let extension = if number < 100 {
+        format!("E{:02}", number)
+    } else {
+        // E99 is followed by EAA..EZZ, FAA..FZZ, up to ZZZ
+        let index = number - 100;
+        let first = b'E' as usize + index / (26 * 26);
+        if first > b'Z' as usize {
+            return None;
+        }
+        let second = b'A' as usize + (index / 26) % 26;
+        let third = b'A' as usize + index % 26;
+        [first, second, third].iter().map(|&c| c as u8 as char).collect()
+    };
+
+    Some(if uppercase { extension } else { extension.to_lowercase() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ewf_segments_continue_with_letters_after_e99() {
+        assert_eq!(ewf_segment_extension(2, true).as_deref(), Some("E02"));
+        assert_eq!(ewf_segment_extension(99, true).as_deref(), Some("E99"));
+        assert_eq!(ewf_segment_extension(100, true).as_deref(), Some("EAA"));
+        assert_eq!(ewf_segment_extension(101, true).as_deref(), Some("EAB"));
+        assert_eq!(ewf_segment_extension(126, true).as_deref(), Some("EBA"));
+        assert_eq!(ewf_segment_extension(100 + 26 * 26, true).as_deref(), Some("FAA"));
+        assert_eq!(ewf_segment_extension(100 + 22 * 26 * 26 - 1, true).as_deref(), Some("ZZZ"));
+        assert_eq!(ewf_segment_extension(100 + 22 * 26 * 26, true), None);
+        assert_eq!(ewf_segment_extension(100, false).as_deref(), Some("eaa"));
+    }
+
+    /// Creates empty files named `names` in a fresh directory.
+    fn scratch_files(test: &str, names: &[&str]) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("chronos-{}-{}", test, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        for name in names {
+            std::fs::write(directory.join(name), b"").unwrap();
         }
-        Ok(&self.data[offset..offset + length])
+        directory
     }
-    
-    pub fn size(&self) -> usize {
-        self.data.len()
+
+    fn segment_names(segments: &[PathBuf]) -> Vec<String> {
+        segments.iter()
+            .map(|segment| segment.file_name().unwrap().to_string_lossy().to_string())
+            .collect()
     }
-    
-    pub fn is_e01_format(&self) -> bool {
-        self.path.to_lowercase().ends_with(".e01")
+
+    #[test]
+    fn discovers_segments_up_to_the_first_gap() {
+        let directory = scratch_files("segments", &[
+            "image.E01", "image.E02", "image.E03", "image.E05",
+            "split.001", "split.002", "split.004",
+            "disk.dd", "disk.001",
+        ]);
+
+        let ewf = discover_segments(&directory.join("image.E01"));
+        let split = discover_segments(&directory.join("split.001"));
+        let raw = discover_segments(&directory.join("disk.dd"));
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(segment_names(&ewf), ["image.E01", "image.E02", "image.E03"]);
+        assert_eq!(segment_names(&split), ["split.001", "split.002"]);
+        assert_eq!(segment_names(&raw), ["disk.dd"]);
     }
-    
-    pub fn is_dd_format(&self) -> bool {
-        self.path.to_lowercase().ends_with(".dd")
+
+    #[test]
+    fn lowercase_ewf_images_keep_lowercase_segments() {
+        let directory = scratch_files("lowercase", &["image.e01", "image.e02"]);
+        let segments = discover_segments(&directory.join("image.e01"));
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(segment_names(&segments), ["image.e01", "image.e02"]);
     }
-} 
\ No newline at end of file
+}
@@ -1,102 +1,1264 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
-use tracing::info;
+use evtx::EvtxParser;
+use serde_json::Value;
+use std::collections::HashSet;
+use tracing::{info, warn};
 
-use crate::disk_image::DiskImage;
-use crate::timeline::Timeline;
+use crate::timeline::{EventType, Timeline};
+use crate::vfs::Vfs;
 
-pub fn parse_event_logs(_disk_image: &DiskImage, timeline: &mut Timeline) -> Result<()> {
+const EVENT_LOG_GLOB: &str = r"Windows\System32\winevt\Logs\*.evtx";
+
+const SECURITY_CHANNEL: &str = "Security";
+const SYSTEM_CHANNEL: &str = "System";
+const POWERSHELL_CHANNEL: &str = "Microsoft-Windows-PowerShell/Operational";
+const LOCAL_SESSION_MANAGER_CHANNEL: &str = "Microsoft-Windows-TerminalServices-LocalSessionManager/Operational";
+const REMOTE_CONNECTION_MANAGER_CHANNEL: &str = "Microsoft-Windows-TerminalServices-RemoteConnectionManager/Operational";
+const TASK_SCHEDULER_CHANNEL: &str = "Microsoft-Windows-TaskScheduler/Operational";
+const DEFENDER_CHANNEL: &str = "Microsoft-Windows-Windows Defender/Operational";
+const SYSMON_CHANNEL: &str = "Microsoft-Windows-Sysmon/Operational";
+const WMI_ACTIVITY_CHANNEL: &str = "Microsoft-Windows-WMI-Activity/Operational";
+const BITS_CLIENT_CHANNEL: &str = "Microsoft-Windows-Bits-Client/Operational";
+
+/// Records of channels without a mapper are reported when at least this
+/// severe (1 = Critical, 2 = Error).
+const GENERIC_MAX_LEVEL: u64 = 2;
+/// Fields shown in the description of a generically mapped record.
+const GENERIC_FIELD_LIMIT: usize = 6;
+/// Longer values (script blocks, command lines) are shortened in
+/// descriptions; the full text goes into the event details.
+const DESCRIPTION_VALUE_LIMIT: usize = 200;
+
+const EVENT_LOGON: u64 = 4624;
+const EVENT_LOGON_FAILED: u64 = 4625;
+const EVENT_LOGOFF: u64 = 4634;
+const EVENT_USER_INITIATED_LOGOFF: u64 = 4647;
+const EVENT_EXPLICIT_CREDENTIALS: u64 = 4648;
+const EVENT_SPECIAL_PRIVILEGES: u64 = 4672;
+const EVENT_PROCESS_CREATED: u64 = 4688;
+const EVENT_SCHEDULED_TASK_CREATED: u64 = 4698;
+const EVENT_ACCOUNT_CREATED: u64 = 4720;
+const EVENT_ACCOUNT_ENABLED: u64 = 4722;
+const EVENT_PASSWORD_RESET: u64 = 4724;
+const EVENT_GLOBAL_GROUP_MEMBER_ADDED: u64 = 4728;
+const EVENT_LOCAL_GROUP_MEMBER_ADDED: u64 = 4732;
+const EVENT_ACCOUNT_CHANGED: u64 = 4738;
+const EVENT_KERBEROS_TGT: u64 = 4768;
+const EVENT_KERBEROS_SERVICE_TICKET: u64 = 4769;
+const EVENT_NTLM_VALIDATION: u64 = 4776;
+const EVENT_AUDIT_LOG_CLEARED: u64 = 1102;
+const EVENT_SERVICE_INSTALLED: u64 = 7045;
+
+const EVENT_POWERSHELL_PIPELINE: u64 = 4103;
+const EVENT_POWERSHELL_SCRIPT_BLOCK: u64 = 4104;
+const EVENT_RDP_SESSION_LOGON: u64 = 21;
+const EVENT_RDP_SESSION_LOGOFF: u64 = 23;
+const EVENT_RDP_SESSION_DISCONNECTED: u64 = 24;
+const EVENT_RDP_SESSION_RECONNECTED: u64 = 25;
+const EVENT_RDP_AUTHENTICATION: u64 = 1149;
+const EVENT_TASK_REGISTERED: u64 = 106;
+const EVENT_TASK_UPDATED: u64 = 140;
+const EVENT_TASK_DELETED: u64 = 141;
+const EVENT_TASK_ACTION_STARTED: u64 = 200;
+const EVENT_TASK_ACTION_COMPLETED: u64 = 201;
+const EVENT_MALWARE_DETECTED: u64 = 1116;
+const EVENT_MALWARE_ACTION_TAKEN: u64 = 1117;
+const EVENT_SYSMON_PROCESS_CREATED: u64 = 1;
+const EVENT_SYSMON_NETWORK_CONNECTION: u64 = 3;
+const EVENT_SYSMON_FILE_CREATED: u64 = 11;
+const EVENT_SYSMON_REGISTRY_KEY: u64 = 12;
+const EVENT_SYSMON_REGISTRY_VALUE_SET: u64 = 13;
+const EVENT_SYSMON_REGISTRY_RENAME: u64 = 14;
+const EVENT_SYSMON_DNS_QUERY: u64 = 22;
+const EVENT_WMI_CONSUMER_BINDING: u64 = 5861;
+const EVENT_BITS_JOB_CREATED: u64 = 3;
+const EVENT_BITS_TRANSFER_STARTED: u64 = 59;
+const EVENT_BITS_TRANSFER_STOPPED: u64 = 60;
+
+/// Attributes of a 4738 event that are reported as changed unless they hold
+/// "-".
+const ACCOUNT_CHANGE_FIELDS: &[&str] = &[
+    "SamAccountName", "DisplayName", "UserPrincipalName", "HomeDirectory", "HomePath",
+    "ScriptPath", "ProfilePath", "UserWorkstations", "PasswordLastSet", "AccountExpires",
+    "PrimaryGroupId", "AllowedToDelegateTo", "OldUacValue", "NewUacValue",
+    "UserAccountControl", "UserParameters", "SidHistory", "LogonHours",
+];
+
+/// Identifies a record across copies of a log: its channel, record ID and
+/// time. Every channel numbers its records from 1, so the ID alone is not
+/// unique.
+pub type RecordKey = (String, u64, DateTime<Utc>);
+
+/// The fields of an event record the timeline is built from.
+pub(crate) struct EventRecord {
+    record_id: u64,
+    timestamp: DateTime<Utc>,
+    event_id: u64,
+    channel: String,
+    provider: String,
+    /// 1 = Critical ... 5 = Verbose; 0 when not set.
+    level: u64,
+    computer: Option<String>,
+    /// SID of the account the event was logged for.
+    user_sid: Option<String>,
+    /// `EventData` (or `UserData`) fields by name.
+    data: serde_json::Map<String, Value>,
+}
+
+impl EventRecord {
+    pub(crate) fn key(&self) -> RecordKey {
+        (self.channel.clone(), self.record_id, self.timestamp)
+    }
+
+    fn field(&self, name: &str) -> Option<String> {
+        match self.data.get(name)? {
+            Value::String(value) if value.is_empty() || value == "-" => None,
+            Value::String(value) => Some(value.clone()),
+            Value::Null => None,
+            value => Some(value.to_string()),
+        }
+    }
+
+    /// Formats an account as `DOMAIN\user` from a domain and a user field.
+    fn account(&self, domain_field: &str, user_field: &str) -> String {
+        match (self.field(domain_field), self.field(user_field)) {
+            (Some(domain), Some(user)) => format!("{}\\{}", domain, user),
+            (None, Some(user)) => user,
+            _ => "unknown".to_string(),
+        }
+    }
+
+    /// The System properties followed by every EventData (or UserData)
+    /// field, for passthrough mode.
+    fn fields(&self) -> Vec<(String, String)> {
+        let mut fields = vec![
+            ("Channel".to_string(), self.channel.clone()),
+            ("Provider".to_string(), self.provider.clone()),
+            ("EventID".to_string(), self.event_id.to_string()),
+            ("Level".to_string(), level_name(self.level).map(str::to_string)
+                .unwrap_or_else(|| self.level.to_string())),
+        ];
+        if let Some(computer) = &self.computer {
+            fields.push(("Computer".to_string(), computer.clone()));
+        }
+        fields.push(("EventRecordID".to_string(), self.record_id.to_string()));
+        if let Some(user_sid) = &self.user_sid {
+            fields.push(("UserID".to_string(), user_sid.clone()));
+        }
+
+        for (name, value) in self.data.iter().filter(|(name, _)| !name.starts_with('#')) {
+            fields.push((name.clone(), field_text(value)));
+        }
+        fields
+    }
+
+    /// The client address, without the IPv4-mapped IPv6 prefix Kerberos
+    /// events use.
+    fn source_address(&self) -> Option<String> {
+        self.field("IpAddress")
+            .map(|address| address.trim_start_matches("::ffff:").to_string())
+    }
+}
+
+/// A timeline event produced by a channel mapper.
+struct MappedEvent {
+    event_type: EventType,
+    description: String,
+    details: Vec<(&'static str, String)>,
+}
+
+impl MappedEvent {
+    fn new(event_type: EventType, description: String) -> Self {
+        MappedEvent {
+            event_type,
+            description,
+            details: Vec::new(),
+        }
+    }
+
+    /// Attaches a value as event detail when the record has it.
+    fn with_detail(mut self, label: &'static str, value: Option<String>) -> Self {
+        if let Some(value) = value {
+            self.details.push((label, value));
+        }
+        self
+    }
+}
+
+/// Turns a record of one channel into a timeline event, or `None` for event
+/// IDs the channel's mapper does not report.
+type EventMapper = fn(&EventRecord) -> Option<MappedEvent>;
+
+fn channel_mapper(channel: &str) -> Option<EventMapper> {
+    let mapper: EventMapper = match channel {
+        SECURITY_CHANNEL => map_security_event,
+        SYSTEM_CHANNEL => map_system_event,
+        POWERSHELL_CHANNEL => map_powershell_event,
+        LOCAL_SESSION_MANAGER_CHANNEL => map_local_session_manager_event,
+        REMOTE_CONNECTION_MANAGER_CHANNEL => map_remote_connection_manager_event,
+        TASK_SCHEDULER_CHANNEL => map_task_scheduler_event,
+        DEFENDER_CHANNEL => map_defender_event,
+        SYSMON_CHANNEL => map_sysmon_event,
+        WMI_ACTIVITY_CHANNEL => map_wmi_activity_event,
+        BITS_CLIENT_CHANNEL => map_bits_client_event,
+        _ => return None,
+    };
+    Some(mapper)
+}
+
+/// Adds the records of every event log to the timeline. With `passthrough`,
+/// records no mapper recognises are kept too, and every event carries all
+/// fields of its record. The key of every record read is added to
+/// `live_records`, so carving can skip copies of them.
+pub fn parse_event_logs(vfs: &Vfs, timeline: &mut Timeline, passthrough: bool,
+                        live_records: &mut HashSet<RecordKey>) -> Result<()> {
     info!("Starting Windows Event Log parsing...");
-    
-    // For MVP, we'll simulate parsing event logs since we can't directly access
-    // the file system structure from a raw disk image without proper NTFS mounting
-    // In a production version, this would need to locate and parse the actual .evtx files
-    
-    // Simulate Security.evtx parsing for user logon events (Event ID 4624)
-    parse_security_events(timeline)?;
-    
-    // Simulate System.evtx parsing for service installation events (Event ID 7045)
-    parse_system_events(timeline)?;
-    
-    info!("Windows Event Log parsing completed");
+
+    // Every channel has its own .evtx file; records are routed by the
+    // channel they name, so renamed or exported logs are handled too
+    let logs = vfs.glob(EVENT_LOG_GLOB)?;
+    let mut event_count = 0;
+    for path in &logs {
+        match read_event_log(vfs, path) {
+            Ok(records) => {
+                live_records.extend(records.iter().map(EventRecord::key));
+                event_count += map_events(&records, log_file_name(path), passthrough, timeline);
+            }
+            Err(e) => warn!("Skipping event log {}: {:#}", path, e),
+        }
+    }
+
+    info!("Windows Event Log parsing completed: {} logs, {} timeline events", logs.len(), event_count);
     Ok(())
 }
 
-fn parse_security_events(timeline: &mut Timeline) -> Result<()> {
-    // For MVP, we'll simulate some user logon events
-    // In production, this would parse the actual Security.evtx file
-    
-    let sample_logons = vec![
-        ("Administrator", "192.168.1.100", "2024-01-15T10:30:00Z"),
-        ("User1", "192.168.1.101", "2024-01-15T14:22:15Z"),
-        ("Admin", "192.168.1.102", "2024-01-16T09:15:30Z"),
-    ];
-    
-    for (username, source_ip, timestamp_str) in sample_logons {
-        if let Ok(timestamp) = DateTime::parse_from_rfc3339(timestamp_str) {
-            timeline.add_user_logon(timestamp.with_timezone(&Utc), username, source_ip);
+/// Adds the timeline events of one log's records and returns their number.
+pub(crate) fn map_events(records: &[EventRecord], log_file: &str, passthrough: bool, timeline: &mut Timeline) -> usize {
+    let mut event_count = 0;
+    for record in records {
+        let mapper = channel_mapper(&record.channel);
+        let event = match mapper.and_then(|mapper| mapper(record)) {
+            Some(event) => Some(event),
+            None if passthrough => Some(map_generic_event(record)),
+            None if mapper.is_none() && (1..=GENERIC_MAX_LEVEL).contains(&record.level) => {
+                Some(map_generic_event(record))
+            }
+            None => None,
+        };
+        let Some(event) = event else {
+            continue;
+        };
+
+        let timeline_event = timeline.add_log_event(
+            record.timestamp,
+            event.event_type,
+            &event.description,
+            log_file,
+            record.record_id,
+        );
+        for (label, value) in event.details {
+            timeline_event.add_detail(label, vec![value]);
         }
+        if passthrough {
+            timeline_event.fields = record.fields();
+        }
+        event_count += 1;
     }
-    
-    Ok(())
+    event_count
+}
+
+fn log_file_name(path: &str) -> &str {
+    path.rsplit('\\').next().unwrap_or(path)
 }
 
-fn parse_system_events(timeline: &mut Timeline) -> Result<()> {
-    // For MVP, we'll simulate some service installation events
-    // In production, this would parse the actual System.evtx file
-    
-    let sample_services = vec![
-        ("Windows Update", "2024-01-15T11:45:00Z"),
-        ("Print Spooler", "2024-01-15T16:20:30Z"),
-        ("Remote Desktop Services", "2024-01-16T08:10:15Z"),
-    ];
-    
-    for (service_name, timestamp_str) in sample_services {
-        if let Ok(timestamp) = DateTime::parse_from_rfc3339(timestamp_str) {
-            timeline.add_service_installation(timestamp.with_timezone(&Utc), service_name);
+/// Reads every record of an .evtx file inside the volume. Records that fail
+/// to parse are skipped.
+fn read_event_log(vfs: &Vfs, path: &str) -> Result<Vec<EventRecord>> {
+    let log = vfs.open_file(path)?;
+    let mut parser = EvtxParser::from_read_seek(log)
+        .with_context(|| format!("Failed to open {}", path))?;
+
+    let mut records = Vec::new();
+    let mut failed = 0;
+    for record in parser.records_json_value() {
+        match record {
+            Ok(record) => match event_record(record.event_record_id, record.timestamp, &record.data) {
+                Some(record) => records.push(record),
+                None => failed += 1,
+            },
+            Err(_) => failed += 1,
         }
     }
-    
-    Ok(())
+
+    if failed > 0 {
+        warn!("{} records of {} could not be parsed", failed, path);
+    }
+    info!("Read {} records from {}", records.len(), path);
+    Ok(records)
 }
 
-// Production-ready event log parser (commented out for MVP)
-/*
-fn parse_evtx_file(disk_image: &DiskImage, file_path: &str, timeline: &mut Timeline) -> Result<()> {
-    // This would be the actual implementation for parsing .evtx files
-    // from the disk image
-    
-    // First, we'd need to locate the file in the NTFS file system
-    // Then parse the .evtx file structure
-    
-    let settings = ParserSettings::default();
-    
-    // For MVP, we'll skip the actual file system traversal
-    // and just simulate the events
-    
-    Ok(())
+pub(crate) fn event_record(record_id: u64, timestamp: DateTime<Utc>, json: &Value) -> Option<EventRecord> {
+    let event = json.get("Event")?;
+
+    let system = event.get("System")?;
+
+    // EventID is a bare number, or an object when it carries Qualifiers
+    let event_id = match system.get("EventID")? {
+        Value::Object(event_id) => event_id.get("#text")?.as_u64()?,
+        event_id => event_id.as_u64()?,
+    };
+
+    // UserData wraps its fields in a single provider-defined element, e.g.
+    // LogFileCleared for 1102
+    let data = event.get("EventData")
+        .or_else(|| event.get("UserData")
+            .and_then(Value::as_object)
+            .and_then(|user_data| user_data.iter()
+                .find(|(name, _)| !name.starts_with('#'))
+                .map(|(_, element)| element)))
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default();
+
+    Some(EventRecord {
+        record_id,
+        timestamp,
+        event_id,
+        channel: system.get("Channel").and_then(Value::as_str).unwrap_or_default().to_string(),
+        provider: system.pointer("/Provider/#attributes/Name")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        level: system.get("Level").and_then(Value::as_u64).unwrap_or(0),
+        computer: system.get("Computer").and_then(Value::as_str).map(str::to_string),
+        user_sid: system.pointer("/Security/#attributes/UserID")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        data,
+    })
 }
 
-fn parse_security_event_4624(event_data: &str, timeline: &mut Timeline) -> Result<()> {
-    // Parse Event ID 4624 (Successful Logon)
-    // Extract username, source IP, timestamp
-    
-    // This would parse the XML event data to extract:
-    // - TargetUserName
-    // - IpAddress
-    // - TimeCreated
-    
-    Ok(())
+fn map_security_event(record: &EventRecord) -> Option<MappedEvent> {
+    let (event_type, description) = match record.event_id {
+        EVENT_LOGON => (EventType::UserLogon, describe_logon(record)),
+        EVENT_LOGON_FAILED => (EventType::FailedLogon, describe_failed_logon(record)),
+        EVENT_LOGOFF | EVENT_USER_INITIATED_LOGOFF => (EventType::UserLogoff, describe_logoff(record)),
+        EVENT_EXPLICIT_CREDENTIALS => (EventType::ExplicitCredentialLogon, describe_explicit_credentials(record)),
+        EVENT_SPECIAL_PRIVILEGES => (EventType::SpecialPrivilegeLogon, describe_special_privileges(record)),
+        EVENT_PROCESS_CREATED => (EventType::ProcessCreation, describe_process_creation(record)),
+        EVENT_SCHEDULED_TASK_CREATED => (EventType::ScheduledTaskCreation, describe_scheduled_task(record)),
+        EVENT_ACCOUNT_CREATED => (EventType::AccountCreation, describe_account_management(record, "created")),
+        EVENT_ACCOUNT_ENABLED => (EventType::AccountEnabled, describe_account_management(record, "enabled")),
+        EVENT_PASSWORD_RESET => (EventType::PasswordReset, describe_password_reset(record)),
+        EVENT_GLOBAL_GROUP_MEMBER_ADDED => (EventType::GroupMembershipChange, describe_group_member_added(record, "global")),
+        EVENT_LOCAL_GROUP_MEMBER_ADDED => (EventType::GroupMembershipChange, describe_group_member_added(record, "local")),
+        EVENT_ACCOUNT_CHANGED => (EventType::AccountChange, describe_account_change(record)),
+        EVENT_KERBEROS_TGT => (EventType::KerberosTgtRequest, describe_kerberos_tgt(record)),
+        EVENT_KERBEROS_SERVICE_TICKET => (EventType::KerberosServiceTicket, describe_kerberos_service_ticket(record)),
+        EVENT_NTLM_VALIDATION => (EventType::NtlmAuthentication, describe_ntlm_validation(record)),
+        EVENT_AUDIT_LOG_CLEARED => (EventType::AuditLogCleared, describe_audit_log_cleared(record)),
+        _ => return None,
+    };
+    Some(MappedEvent::new(event_type, description))
 }
 
-fn parse_system_event_7045(event_data: &str, timeline: &mut Timeline) -> Result<()> {
-    // Parse Event ID 7045 (Service Installation)
-    // Extract service name, timestamp
-    
-    // This would parse the XML event data to extract:
-    // - ServiceName
-    // - TimeCreated
-    
-    Ok(())
+fn describe_logon(record: &EventRecord) -> String {
+    let mut description = format!("User '{}' logged on", record.account("TargetDomainName", "TargetUserName"));
+    if let Some(logon_type) = record.field("LogonType").and_then(|logon_type| logon_type.parse().ok()) {
+        description.push_str(&format!(" ({})", logon_type_name(logon_type)));
+    }
+    if let Some(address) = record.source_address() {
+        description.push_str(&format!(" from source IP {}", address));
+    }
+    description
+}
+
+fn describe_failed_logon(record: &EventRecord) -> String {
+    let mut description = format!("Failed logon for '{}'", record.account("TargetDomainName", "TargetUserName"));
+    if let Some(logon_type) = record.field("LogonType").and_then(|logon_type| logon_type.parse().ok()) {
+        description.push_str(&format!(" ({})", logon_type_name(logon_type)));
+    }
+    if let Some(address) = record.source_address() {
+        description.push_str(&format!(" from source IP {}", address));
+    }
+    if let Some(workstation) = record.field("WorkstationName") {
+        description.push_str(&format!(" (workstation {})", workstation));
+    }
+
+    // The sub-status tells why the logon failed; the status is often just
+    // "unknown user name or bad password"
+    let status = record.field("SubStatus")
+        .filter(|status| !is_success_status(status))
+        .or_else(|| record.field("Status"));
+    if let Some(status) = status {
+        description.push_str(&format!(": status {}", status));
+        if let Some(reason) = logon_failure_reason(&status) {
+            description.push_str(&format!(" ({})", reason));
+        }
+    }
+    description
+}
+
+fn describe_logoff(record: &EventRecord) -> String {
+    let mut description = format!("User '{}' logged off", record.account("TargetDomainName", "TargetUserName"));
+    if record.event_id == EVENT_USER_INITIATED_LOGOFF {
+        description.push_str(" (user initiated)");
+    } else if let Some(logon_type) = record.field("LogonType").and_then(|logon_type| logon_type.parse().ok()) {
+        description.push_str(&format!(" ({})", logon_type_name(logon_type)));
+    }
+    if let Some(logon_id) = record.field("TargetLogonId") {
+        description.push_str(&format!(", logon ID {}", logon_id));
+    }
+    description
+}
+
+fn describe_explicit_credentials(record: &EventRecord) -> String {
+    let mut description = format!("'{}' logged on with the explicit credentials of '{}'",
+                                  record.account("SubjectDomainName", "SubjectUserName"),
+                                  record.account("TargetDomainName", "TargetUserName"));
+    if let Some(server) = record.field("TargetServerName") {
+        description.push_str(&format!(" to {}", server));
+    }
+    if let Some(process) = record.field("ProcessName") {
+        description.push_str(&format!(" using {}", process));
+    }
+    if let Some(address) = record.source_address() {
+        description.push_str(&format!(" from source IP {}", address));
+    }
+    description
+}
+
+fn describe_special_privileges(record: &EventRecord) -> String {
+    let privileges: Vec<String> = record.field("PrivilegeList")
+        .map(|list| list.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default();
+    format!("Special privileges assigned to new logon of '{}': {}",
+            record.account("SubjectDomainName", "SubjectUserName"),
+            if privileges.is_empty() { "none listed".to_string() } else { privileges.join(", ") })
+}
+
+fn describe_process_creation(record: &EventRecord) -> String {
+    let mut description = format!("Process '{}' was created by '{}'",
+                                  record.field("NewProcessName").as_deref().unwrap_or("unknown"),
+                                  record.account("SubjectDomainName", "SubjectUserName"));
+    if let Some(command_line) = record.field("CommandLine") {
+        description.push_str(&format!(" with command line {}", command_line));
+    }
+    if let Some(parent) = record.field("ParentProcessName") {
+        description.push_str(&format!(", parent {}", parent));
+    }
+    description
+}
+
+fn describe_scheduled_task(record: &EventRecord) -> String {
+    let mut description = format!("Scheduled task '{}' was created by '{}'",
+                                  record.field("TaskName").as_deref().unwrap_or("unknown"),
+                                  record.account("SubjectDomainName", "SubjectUserName"));
+
+    // The task definition is XML; its Exec action is the part worth showing
+    let content = record.field("TaskContent").unwrap_or_default();
+    if let Some(command) = xml_element_text(&content, "Command") {
+        description.push_str(&format!(" to run {}", command));
+        if let Some(arguments) = xml_element_text(&content, "Arguments") {
+            description.push_str(&format!(" {}", arguments));
+        }
+    }
+    description
+}
+
+fn describe_account_management(record: &EventRecord, action: &str) -> String {
+    format!("User account '{}' was {} by '{}'",
+            record.account("TargetDomainName", "TargetUserName"), action,
+            record.account("SubjectDomainName", "SubjectUserName"))
+}
+
+fn describe_password_reset(record: &EventRecord) -> String {
+    format!("Password of '{}' was reset by '{}'",
+            record.account("TargetDomainName", "TargetUserName"),
+            record.account("SubjectDomainName", "SubjectUserName"))
+}
+
+fn describe_group_member_added(record: &EventRecord, scope: &str) -> String {
+    let member = record.field("MemberName")
+        .or_else(|| record.field("MemberSid"))
+        .unwrap_or_else(|| "unknown".to_string());
+    format!("'{}' was added to security-enabled {} group '{}' by '{}'",
+            member, scope, record.account("TargetDomainName", "TargetUserName"),
+            record.account("SubjectDomainName", "SubjectUserName"))
+}
+
+fn describe_account_change(record: &EventRecord) -> String {
+    let changed: Vec<String> = ACCOUNT_CHANGE_FIELDS.iter()
+        .filter_map(|&name| record.field(name).map(|value| format!("{} = {}", name, value)))
+        .collect();
+    let mut description = describe_account_management(record, "changed");
+    if !changed.is_empty() {
+        description.push_str(&format!(": {}", changed.join("; ")));
+    }
+    description
+}
+
+fn describe_kerberos_tgt(record: &EventRecord) -> String {
+    let mut description = format!("Kerberos TGT requested for '{}'",
+                                  record.account("TargetDomainName", "TargetUserName"));
+    describe_kerberos_request(record, &mut description);
+    description
+}
+
+fn describe_kerberos_service_ticket(record: &EventRecord) -> String {
+    let mut description = format!("Kerberos service ticket for '{}' requested by '{}'",
+                                  record.field("ServiceName").as_deref().unwrap_or("unknown"),
+                                  record.account("TargetDomainName", "TargetUserName"));
+    describe_kerberos_request(record, &mut description);
+    description
+}
+
+/// Appends the client address, outcome and ticket encryption type shared by
+/// 4768 and 4769. RC4 tickets (0x17) are typical of Kerberoasting.
+fn describe_kerberos_request(record: &EventRecord, description: &mut String) {
+    if let Some(address) = record.source_address() {
+        description.push_str(&format!(" from {}", address));
+    }
+    if let Some(encryption) = record.field("TicketEncryptionType") {
+        description.push_str(&format!(", encryption type {}", encryption));
+    }
+    describe_status(record, description);
+}
+
+fn describe_ntlm_validation(record: &EventRecord) -> String {
+    let mut description = format!("NTLM credential validation for '{}'",
+                                  record.field("TargetUserName").as_deref().unwrap_or("unknown"));
+    if let Some(workstation) = record.field("Workstation") {
+        description.push_str(&format!(" from workstation {}", workstation));
+    }
+    describe_status(record, &mut description);
+    description
+}
+
+fn describe_audit_log_cleared(record: &EventRecord) -> String {
+    format!("The Security audit log was cleared by '{}'",
+            record.account("SubjectDomainName", "SubjectUserName"))
+}
+
+fn describe_status(record: &EventRecord, description: &mut String) {
+    match record.field("Status") {
+        Some(status) if is_success_status(&status) => description.push_str(", succeeded"),
+        Some(status) => {
+            description.push_str(&format!(", failed with status {}", status));
+            if let Some(reason) = logon_failure_reason(&status) {
+                description.push_str(&format!(" ({})", reason));
+            }
+        }
+        None => {}
+    }
+}
+
+fn is_success_status(status: &str) -> bool {
+    parse_status(status) == Some(0)
+}
+
+/// Parses an NTSTATUS or Kerberos result code, written as hex ("0xC000006A").
+fn parse_status(status: &str) -> Option<u32> {
+    let status = status.trim();
+    match status.strip_prefix("0x").or_else(|| status.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => status.parse().ok(),
+    }
+}
+
+/// Explains the NTSTATUS and Kerberos codes most often seen in failed
+/// authentications.
+fn logon_failure_reason(status: &str) -> Option<&'static str> {
+    let reason = match parse_status(status)? {
+        0xC0000064 => "user name does not exist",
+        0xC000006A => "wrong password",
+        0xC000006D => "bad user name or password",
+        0xC000006F => "outside of allowed logon hours",
+        0xC0000070 => "workstation restriction",
+        0xC0000071 => "password expired",
+        0xC0000072 => "account disabled",
+        0xC0000133 => "clock skew between client and server",
+        0xC000015B => "logon type not granted",
+        0xC0000193 => "account expired",
+        0xC0000224 => "password must change at next logon",
+        0xC0000234 => "account locked out",
+        0x6 => "Kerberos: client not found",
+        0x12 => "Kerberos: account disabled, expired or locked out",
+        0x17 => "Kerberos: password expired",
+        0x18 => "Kerberos: pre-authentication failed, wrong password",
+        _ => return None,
+    };
+    Some(reason)
+}
+
+/// Returns the text of the first `<name>` element in an XML document.
+fn xml_element_text(xml: &str, name: &str) -> Option<String> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+    let text = xml[start..end].trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Describes a Windows logon type as recorded in logon and logoff events.
+fn logon_type_name(logon_type: u32) -> String {
+    let name = match logon_type {
+        2 => "Interactive",
+        3 => "Network",
+        4 => "Batch",
+        5 => "Service",
+        7 => "Unlock",
+        8 => "NetworkCleartext",
+        9 => "NewCredentials",
+        10 => "RemoteInteractive",
+        11 => "CachedInteractive",
+        12 => "CachedRemoteInteractive",
+        13 => "CachedUnlock",
+        _ => return format!("logon type {}", logon_type),
+    };
+    format!("logon type {}, {}", logon_type, name)
+}
+
+fn map_system_event(record: &EventRecord) -> Option<MappedEvent> {
+    if record.event_id != EVENT_SERVICE_INSTALLED {
+        return None;
+    }
+
+    let mut description = format!("Service '{}' was installed",
+                                  record.field("ServiceName").as_deref().unwrap_or("unknown"));
+    if let Some(image_path) = record.field("ImagePath") {
+        description.push_str(&format!(" with image path {}", image_path));
+    }
+    Some(MappedEvent::new(EventType::ServiceInstallation, description))
+}
+
+fn map_powershell_event(record: &EventRecord) -> Option<MappedEvent> {
+    match record.event_id {
+        EVENT_POWERSHELL_SCRIPT_BLOCK => {
+            let script = record.field("ScriptBlockText").unwrap_or_default();
+            let mut description = "PowerShell script block executed".to_string();
+            if let Some(path) = record.field("Path") {
+                description.push_str(&format!(" from {}", path));
+            }
+            // Long scripts are logged in several parts
+            if let (Some(part), Some(total)) = (record.field("MessageNumber"), record.field("MessageTotal")) {
+                if total != "1" {
+                    description.push_str(&format!(" (part {} of {})", part, total));
+                }
+            }
+            description.push_str(&format!(": {}", shorten(&script)));
+
+            Some(MappedEvent::new(EventType::PowerShellExecution, description)
+                .with_detail("Script block", Some(script))
+                .with_detail("Script block ID", record.field("ScriptBlockId")))
+        }
+        EVENT_POWERSHELL_PIPELINE => {
+            // ContextInfo is a block of "Name = value" lines
+            let context = record.field("ContextInfo").unwrap_or_default();
+            let context_value = |name: &str| context.lines()
+                .filter_map(|line| line.split_once('='))
+                .find(|(key, _)| key.trim() == name)
+                .map(|(_, value)| value.trim().to_string())
+                .filter(|value| !value.is_empty());
+
+            let mut description = format!("PowerShell command '{}' executed",
+                                          context_value("Command Name").as_deref().unwrap_or("unknown"));
+            if let Some(user) = context_value("User") {
+                description.push_str(&format!(" by '{}'", user));
+            }
+            if let Some(host) = context_value("Host Application") {
+                description.push_str(&format!(" in {}", shorten(&host)));
+            }
+
+            Some(MappedEvent::new(EventType::PowerShellExecution, description)
+                .with_detail("Host application", context_value("Host Application"))
+                .with_detail("Payload", record.field("Payload")))
+        }
+        _ => None,
+    }
+}
+
+fn map_local_session_manager_event(record: &EventRecord) -> Option<MappedEvent> {
+    let action = match record.event_id {
+        EVENT_RDP_SESSION_LOGON => "logged on",
+        EVENT_RDP_SESSION_LOGOFF => "logged off",
+        EVENT_RDP_SESSION_DISCONNECTED => "disconnected",
+        EVENT_RDP_SESSION_RECONNECTED => "reconnected",
+        _ => return None,
+    };
+
+    let mut description = format!("Remote Desktop session {} of '{}' {}",
+                                  record.field("SessionID").as_deref().unwrap_or("?"),
+                                  record.field("User").as_deref().unwrap_or("unknown"),
+                                  action);
+    if let Some(address) = record.field("Address") {
+        description.push_str(&format!(" (source {})", address));
+    }
+    Some(MappedEvent::new(EventType::RemoteDesktop, description))
+}
+
+fn map_remote_connection_manager_event(record: &EventRecord) -> Option<MappedEvent> {
+    if record.event_id != EVENT_RDP_AUTHENTICATION {
+        return None;
+    }
+
+    // Param1-3 are the user, domain and source address
+    let mut description = format!("Remote Desktop network authentication succeeded for '{}'",
+                                  record.account("Param2", "Param1"));
+    if let Some(address) = record.field("Param3") {
+        description.push_str(&format!(" from source IP {}", address));
+    }
+    Some(MappedEvent::new(EventType::RemoteDesktop, description))
+}
+
+fn map_task_scheduler_event(record: &EventRecord) -> Option<MappedEvent> {
+    let task = record.field("TaskName").unwrap_or_else(|| "unknown".to_string());
+    let (event_type, description) = match record.event_id {
+        EVENT_TASK_REGISTERED => (EventType::ScheduledTaskCreation,
+            format!("Scheduled task '{}' was registered by '{}'", task,
+                    record.field("UserContext").as_deref().unwrap_or("unknown"))),
+        EVENT_TASK_UPDATED => (EventType::ScheduledTaskChange,
+            format!("Scheduled task '{}' was updated by '{}'", task,
+                    record.field("UserName").as_deref().unwrap_or("unknown"))),
+        EVENT_TASK_DELETED => (EventType::ScheduledTaskChange,
+            format!("Scheduled task '{}' was deleted by '{}'", task,
+                    record.field("UserName").as_deref().unwrap_or("unknown"))),
+        EVENT_TASK_ACTION_STARTED => (EventType::ScheduledTaskExecution,
+            format!("Scheduled task '{}' launched {}", task,
+                    record.field("ActionName").as_deref().unwrap_or("an action"))),
+        EVENT_TASK_ACTION_COMPLETED => (EventType::ScheduledTaskExecution,
+            format!("Scheduled task '{}' completed {} with result {}", task,
+                    record.field("ActionName").as_deref().unwrap_or("an action"),
+                    record.field("ResultCode").as_deref().unwrap_or("unknown"))),
+        _ => return None,
+    };
+    Some(MappedEvent::new(event_type, description))
+}
+
+fn map_defender_event(record: &EventRecord) -> Option<MappedEvent> {
+    let threat = record.field("Threat Name").unwrap_or_else(|| "unknown threat".to_string());
+    let mut description = match record.event_id {
+        EVENT_MALWARE_DETECTED => {
+            let mut description = format!("Windows Defender detected {}", threat);
+            if let (Some(severity), Some(category)) = (record.field("Severity Name"), record.field("Category Name")) {
+                description.push_str(&format!(" ({} severity {})", severity, category));
+            }
+            description
+        }
+        EVENT_MALWARE_ACTION_TAKEN => format!("Windows Defender took action '{}' on {}",
+                                              record.field("Action Name").as_deref().unwrap_or("unknown"),
+                                              threat),
+        _ => return None,
+    };
+    if let Some(path) = record.field("Path") {
+        description.push_str(&format!(" in {}", path));
+    }
+    if let Some(process) = record.field("Process Name") {
+        description.push_str(&format!(" (process {})", process));
+    }
+    if let Some(user) = record.field("Detection User") {
+        description.push_str(&format!(" for user '{}'", user));
+    }
+    Some(MappedEvent::new(EventType::MalwareDetection, description))
+}
+
+fn map_sysmon_event(record: &EventRecord) -> Option<MappedEvent> {
+    let image = record.field("Image").unwrap_or_else(|| "unknown".to_string());
+    let event = match record.event_id {
+        EVENT_SYSMON_PROCESS_CREATED => {
+            let mut description = format!("Process '{}' was started by '{}'", image,
+                                          record.field("User").as_deref().unwrap_or("unknown"));
+            if let Some(command_line) = record.field("CommandLine") {
+                description.push_str(&format!(" with command line {}", shorten(&command_line)));
+            }
+            if let Some(parent) = record.field("ParentImage") {
+                description.push_str(&format!(", parent {}", parent));
+            }
+            MappedEvent::new(EventType::ProcessCreation, description)
+                .with_detail("Command line", record.field("CommandLine"))
+                .with_detail("Hashes", record.field("Hashes"))
+        }
+        EVENT_SYSMON_NETWORK_CONNECTION => {
+            let destination = record.field("DestinationHostname")
+                .or_else(|| record.field("DestinationIp"))
+                .unwrap_or_else(|| "unknown".to_string());
+            MappedEvent::new(EventType::NetworkConnection, format!(
+                "'{}' connected to {}:{} ({}) from {}:{}", image, destination,
+                record.field("DestinationPort").as_deref().unwrap_or("?"),
+                record.field("Protocol").as_deref().unwrap_or("?"),
+                record.field("SourceIp").as_deref().unwrap_or("?"),
+                record.field("SourcePort").as_deref().unwrap_or("?")))
+        }
+        EVENT_SYSMON_FILE_CREATED => MappedEvent::new(EventType::FileCreation, format!(
+            "'{}' created file {}", image,
+            record.field("TargetFilename").as_deref().unwrap_or("unknown"))),
+        EVENT_SYSMON_REGISTRY_KEY | EVENT_SYSMON_REGISTRY_VALUE_SET | EVENT_SYSMON_REGISTRY_RENAME => {
+            // EventType holds the operation, e.g. SetValue or DeleteKey
+            let mut description = format!("'{}' performed {} on {}", image,
+                                          record.field("EventType").as_deref().unwrap_or("a registry change"),
+                                          record.field("TargetObject").as_deref().unwrap_or("unknown"));
+            if let Some(details) = record.field("Details").or_else(|| record.field("NewName")) {
+                description.push_str(&format!(": {}", shorten(&details)));
+            }
+            MappedEvent::new(EventType::RegistryModification, description)
+        }
+        EVENT_SYSMON_DNS_QUERY => {
+            let mut description = format!("'{}' queried {}", image,
+                                          record.field("QueryName").as_deref().unwrap_or("unknown"));
+            if let Some(results) = record.field("QueryResults") {
+                description.push_str(&format!(" ({})", shorten(&results)));
+            }
+            MappedEvent::new(EventType::DnsQuery, description)
+        }
+        // Sysmon only logs what its configuration selects, so every other
+        // event is kept in generic form
+        _ => map_generic_event(record),
+    };
+    Some(event)
+}
+
+fn map_wmi_activity_event(record: &EventRecord) -> Option<MappedEvent> {
+    if record.event_id != EVENT_WMI_CONSUMER_BINDING {
+        return None;
+    }
+
+    // A permanent event consumer binding is a common persistence mechanism;
+    // PossibleCause holds the consumer's command or script
+    let description = format!("WMI event filter {} was bound to consumer {} in namespace {}",
+                              record.field("ESS").as_deref().unwrap_or("unknown"),
+                              record.field("CONSUMER").as_deref().unwrap_or("unknown"),
+                              record.field("Namespace").as_deref().unwrap_or("unknown"));
+    Some(MappedEvent::new(EventType::WmiPersistence, description)
+        .with_detail("Possible cause", record.field("PossibleCause")))
+}
+
+fn map_bits_client_event(record: &EventRecord) -> Option<MappedEvent> {
+    let description = match record.event_id {
+        EVENT_BITS_JOB_CREATED => format!("BITS job '{}' was created by '{}'",
+                                          record.field("jobTitle").as_deref().unwrap_or("unknown"),
+                                          record.field("jobOwner").as_deref().unwrap_or("unknown")),
+        EVENT_BITS_TRANSFER_STARTED => format!("BITS job '{}' started transferring {}",
+                                               record.field("name").as_deref().unwrap_or("unknown"),
+                                               record.field("url").as_deref().unwrap_or("unknown")),
+        EVENT_BITS_TRANSFER_STOPPED => format!("BITS job '{}' stopped transferring {} ({} bytes, result {})",
+                                               record.field("name").as_deref().unwrap_or("unknown"),
+                                               record.field("url").as_deref().unwrap_or("unknown"),
+                                               record.field("bytesTransferred").as_deref().unwrap_or("?"),
+                                               record.field("hr").as_deref().unwrap_or("?")),
+        _ => return None,
+    };
+    Some(MappedEvent::new(EventType::BitsTransfer, description))
+}
+
+/// Describes a record by its provider, event ID, level and first fields.
+fn map_generic_event(record: &EventRecord) -> MappedEvent {
+    let mut description = format!("{} event {}", record.provider, record.event_id);
+    if let Some(level) = level_name(record.level) {
+        description.push_str(&format!(" ({})", level));
+    }
+
+    let fields: Vec<String> = record.data.keys()
+        .filter(|name| !name.starts_with('#'))
+        .filter_map(|name| record.field(name).map(|value| format!("{} = {}", name, shorten(&value))))
+        .take(GENERIC_FIELD_LIMIT)
+        .collect();
+    if !fields.is_empty() {
+        description.push_str(&format!(": {}", fields.join("; ")));
+    }
+    MappedEvent::new(EventType::LogEntry, description)
+}
+
+/// Renders a field value as text; elements with attributes keep their
+/// content under "#text", and repeated elements become arrays.
+fn field_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        Value::Array(values) => values.iter().map(field_text).collect::<Vec<_>>().join(", "),
+        Value::Object(object) => match object.get("#text") {
+            Some(text) => field_text(text),
+            None => value.to_string(),
+        },
+        value => value.to_string(),
+    }
+}
+
+fn level_name(level: u64) -> Option<&'static str> {
+    match level {
+        1 => Some("Critical"),
+        2 => Some("Error"),
+        3 => Some("Warning"),
+        4 => Some("Information"),
+        5 => Some("Verbose"),
+        _ => None,
+    }
+}
+
+/// Collapses whitespace and cuts the text at `DESCRIPTION_VALUE_LIMIT`
+/// characters.
+fn shorten(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(DESCRIPTION_VALUE_LIMIT) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    fn timestamp() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 1, 12, 30, 0).unwrap()
+    }
+
+    /// The JSON `records_json_value` produces for a record.
+    fn record_json(channel: &str, event_id: Value, data: Value) -> Value {
+        json!({
+            "Event": {
+                "#attributes": { "xmlns": "http://schemas.microsoft.com/win/2004/08/events/event" },
+                "System": {
+                    "Provider": { "#attributes": { "Name": "Microsoft-Windows-Security-Auditing" } },
+                    "EventID": event_id,
+                    "Level": 0,
+                    "Channel": channel,
+                    "Computer": "WS01.corp.local",
+                    "Security": { "#attributes": { "UserID": "S-1-5-18" } }
+                },
+                "EventData": data
+            }
+        })
+    }
+
+    fn record(channel: &str, event_id: u64, data: Value) -> EventRecord {
+        event_record(42, timestamp(), &record_json(channel, json!(event_id), data)).unwrap()
+    }
+
+    /// Maps `records` as if read from `log_file` and returns the event
+    /// types and descriptions.
+    fn map(records: &[EventRecord], log_file: &str, passthrough: bool) -> Vec<(String, String)> {
+        let mut timeline = Timeline::new();
+        map_events(records, log_file, passthrough, &mut timeline);
+        timeline.events.iter()
+            .map(|event| (event.event_type.to_string(), event.description.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn builds_records_from_json() {
+        let record = record(SECURITY_CHANNEL, EVENT_LOGON, json!({ "TargetUserName": "alice", "LogonType": 10 }));
+        assert_eq!(record.record_id, 42);
+        assert_eq!(record.timestamp, timestamp());
+        assert_eq!(record.event_id, EVENT_LOGON);
+        assert_eq!(record.channel, SECURITY_CHANNEL);
+        assert_eq!(record.provider, "Microsoft-Windows-Security-Auditing");
+        assert_eq!(record.computer.as_deref(), Some("WS01.corp.local"));
+        assert_eq!(record.user_sid.as_deref(), Some("S-1-5-18"));
+        assert_eq!(record.field("TargetUserName").as_deref(), Some("alice"));
+        assert_eq!(record.field("LogonType").as_deref(), Some("10"));
+        assert_eq!(record.field("Missing"), None);
+    }
+
+    #[test]
+    fn reads_event_ids_with_qualifiers_and_user_data() {
+        let json = record_json(SYSTEM_CHANNEL, json!({ "#attributes": { "Qualifiers": 16384 }, "#text": 7045 }),
+                               json!({ "ServiceName": "svc" }));
+        assert_eq!(event_record(1, timestamp(), &json).unwrap().event_id, EVENT_SERVICE_INSTALLED);
+
+        // UserData wraps its fields in one provider-defined element
+        let mut json = record_json(SECURITY_CHANNEL, json!(EVENT_AUDIT_LOG_CLEARED), Value::Null);
+        let event = json["Event"].as_object_mut().unwrap();
+        event.remove("EventData");
+        event.insert("UserData".to_string(), json!({
+            "LogFileCleared": { "#attributes": { "xmlns": "http://manifests.microsoft.com/win/2004/08/windows/eventlog" },
+                                "SubjectUserName": "admin" }
+        }));
+        assert_eq!(event_record(1, timestamp(), &json).unwrap().field("SubjectUserName").as_deref(), Some("admin"));
+    }
+
+    #[test]
+    fn rejects_records_without_an_event_id() {
+        assert!(event_record(1, timestamp(), &json!({ "Event": { "System": {} } })).is_none());
+        assert!(event_record(1, timestamp(), &json!({ "NotAnEvent": {} })).is_none());
+    }
+
+    #[test]
+    fn maps_logons_and_service_installations() {
+        let logon = record(SECURITY_CHANNEL, EVENT_LOGON, json!({
+            "TargetDomainName": "CORP", "TargetUserName": "alice", "LogonType": 10, "IpAddress": "10.0.0.5"
+        }));
+        let service = record(SYSTEM_CHANNEL, EVENT_SERVICE_INSTALLED, json!({
+            "ServiceName": "evilsvc", "ImagePath": "C:\\Windows\\Temp\\evil.exe", "ServiceType": "user mode service"
+        }));
+
+        assert_eq!(map(&[logon], "Security.evtx", false), vec![(
+            "User Logon".to_string(),
+            "User 'CORP\\alice' logged on (logon type 10, RemoteInteractive) from source IP 10.0.0.5 [record 42]".to_string(),
+        )]);
+        assert_eq!(map(&[service], "System.evtx", false), vec![(
+            "Service Installation".to_string(),
+            "Service 'evilsvc' was installed with image path C:\\Windows\\Temp\\evil.exe [record 42]".to_string(),
+        )]);
+    }
+
+    #[test]
+    fn missing_and_placeholder_fields_are_unknown() {
+        let logon = record(SECURITY_CHANNEL, EVENT_LOGON, json!({ "TargetUserName": "-", "IpAddress": "-" }));
+        let service = record(SYSTEM_CHANNEL, EVENT_SERVICE_INSTALLED, json!({}));
+        let events = map(&[logon, service], "Security.evtx", false);
+        assert_eq!(events[0].1, "User 'unknown' logged on [record 42]");
+        assert_eq!(events[1].1, "Service 'unknown' was installed [record 42]");
+    }
+
+    #[test]
+    fn maps_security_events() {
+        let cases = [
+            (EVENT_LOGON_FAILED, json!({
+                "TargetDomainName": "CORP", "TargetUserName": "bob", "LogonType": 3, "IpAddress": "10.0.0.9",
+                "WorkstationName": "KALI", "Status": "0xc000006d", "SubStatus": "0xc000006a"
+            }), "Failed Logon",
+             "Failed logon for 'CORP\\bob' (logon type 3, Network) from source IP 10.0.0.9 (workstation KALI): \
+              status 0xc000006a (wrong password)"),
+            // A success sub-status falls back to the status
+            (EVENT_LOGON_FAILED, json!({ "TargetUserName": "bob", "Status": "0xC0000234", "SubStatus": "0x0" }),
+             "Failed Logon", "Failed logon for 'bob': status 0xC0000234 (account locked out)"),
+            (EVENT_LOGOFF, json!({ "TargetDomainName": "CORP", "TargetUserName": "alice", "LogonType": 2,
+                                   "TargetLogonId": "0x3e7a1" }),
+             "User Logoff", "User 'CORP\\alice' logged off (logon type 2, Interactive), logon ID 0x3e7a1"),
+            (EVENT_USER_INITIATED_LOGOFF, json!({ "TargetUserName": "alice", "LogonType": 2 }),
+             "User Logoff", "User 'alice' logged off (user initiated)"),
+            (EVENT_EXPLICIT_CREDENTIALS, json!({
+                "SubjectDomainName": "CORP", "SubjectUserName": "alice", "TargetDomainName": "CORP",
+                "TargetUserName": "admin", "TargetServerName": "DC01", "ProcessName": "C:\\Windows\\System32\\runas.exe",
+                "IpAddress": "-"
+            }), "Explicit Credential Logon",
+             "'CORP\\alice' logged on with the explicit credentials of 'CORP\\admin' to DC01 using \
+              C:\\Windows\\System32\\runas.exe"),
+            (EVENT_SPECIAL_PRIVILEGES, json!({ "SubjectUserName": "admin",
+                                               "PrivilegeList": "SeDebugPrivilege\n\t\t\tSeBackupPrivilege" }),
+             "Special Privilege Logon", "Special privileges assigned to new logon of 'admin': SeDebugPrivilege, SeBackupPrivilege"),
+            (EVENT_PROCESS_CREATED, json!({
+                "SubjectUserName": "alice", "NewProcessName": "C:\\Windows\\System32\\cmd.exe",
+                "CommandLine": "cmd.exe /c whoami", "ParentProcessName": "C:\\Windows\\explorer.exe"
+            }), "Process Creation",
+             "Process 'C:\\Windows\\System32\\cmd.exe' was created by 'alice' with command line cmd.exe /c whoami, \
+              parent C:\\Windows\\explorer.exe"),
+            (EVENT_SCHEDULED_TASK_CREATED, json!({
+                "SubjectUserName": "alice", "TaskName": "\\Updater",
+                "TaskContent": "<Task><Actions><Exec><Command>C:\\Temp\\u.exe</Command>\
+                                <Arguments>-q</Arguments></Exec></Actions></Task>"
+            }), "Scheduled Task Creation", "Scheduled task '\\Updater' was created by 'alice' to run C:\\Temp\\u.exe -q"),
+            (EVENT_ACCOUNT_CREATED, json!({ "TargetDomainName": "WS01", "TargetUserName": "backdoor",
+                                            "SubjectDomainName": "CORP", "SubjectUserName": "admin" }),
+             "Account Creation", "User account 'WS01\\backdoor' was created by 'CORP\\admin'"),
+            (EVENT_ACCOUNT_ENABLED, json!({ "TargetUserName": "Guest", "SubjectUserName": "admin" }),
+             "Account Enabled", "User account 'Guest' was enabled by 'admin'"),
+            (EVENT_PASSWORD_RESET, json!({ "TargetUserName": "bob", "SubjectUserName": "admin" }),
+             "Password Reset", "Password of 'bob' was reset by 'admin'"),
+            (EVENT_LOCAL_GROUP_MEMBER_ADDED, json!({ "MemberName": "-", "MemberSid": "S-1-5-21-1-2-3-1001",
+                                                     "TargetDomainName": "Builtin", "TargetUserName": "Administrators",
+                                                     "SubjectUserName": "admin" }),
+             "Group Membership Change",
+             "'S-1-5-21-1-2-3-1001' was added to security-enabled local group 'Builtin\\Administrators' by 'admin'"),
+            (EVENT_GLOBAL_GROUP_MEMBER_ADDED, json!({ "MemberName": "CN=bob,CN=Users,DC=corp,DC=local",
+                                                      "TargetUserName": "Domain Admins", "SubjectUserName": "admin" }),
+             "Group Membership Change",
+             "'CN=bob,CN=Users,DC=corp,DC=local' was added to security-enabled global group 'Domain Admins' by 'admin'"),
+            (EVENT_ACCOUNT_CHANGED, json!({ "TargetUserName": "bob", "SubjectUserName": "admin", "DisplayName": "-",
+                                            "PasswordLastSet": "3/1/2024 12:29:00 PM", "NewUacValue": "0x210" }),
+             "Account Change",
+             "User account 'bob' was changed by 'admin': PasswordLastSet = 3/1/2024 12:29:00 PM; NewUacValue = 0x210"),
+            (EVENT_KERBEROS_TGT, json!({ "TargetDomainName": "CORP.LOCAL", "TargetUserName": "bob",
+                                         "IpAddress": "::ffff:10.0.0.9", "TicketEncryptionType": "0x12",
+                                         "Status": "0x18" }),
+             "Kerberos TGT Request",
+             "Kerberos TGT requested for 'CORP.LOCAL\\bob' from 10.0.0.9, encryption type 0x12, failed with status \
+              0x18 (Kerberos: pre-authentication failed, wrong password)"),
+            (EVENT_KERBEROS_SERVICE_TICKET, json!({ "ServiceName": "MSSQLSvc", "TargetUserName": "bob@CORP.LOCAL",
+                                                    "IpAddress": "::ffff:10.0.0.9", "TicketEncryptionType": "0x17",
+                                                    "Status": "0x0" }),
+             "Kerberos Service Ticket",
+             "Kerberos service ticket for 'MSSQLSvc' requested by 'bob@CORP.LOCAL' from 10.0.0.9, encryption type \
+              0x17, succeeded"),
+            (EVENT_NTLM_VALIDATION, json!({ "TargetUserName": "bob", "Workstation": "KALI", "Status": "0xc0000064" }),
+             "NTLM Authentication",
+             "NTLM credential validation for 'bob' from workstation KALI, failed with status 0xc0000064 \
+              (user name does not exist)"),
+            (EVENT_AUDIT_LOG_CLEARED, json!({ "SubjectDomainName": "CORP", "SubjectUserName": "admin" }),
+             "Audit Log Cleared", "The Security audit log was cleared by 'CORP\\admin'"),
+        ];
+
+        for (event_id, data, event_type, description) in cases {
+            let events = map(&[record(SECURITY_CHANNEL, event_id, data)], "Security.evtx", false);
+            assert_eq!(events, vec![(event_type.to_string(), format!("{} [record 42]", description))],
+                       "event {}", event_id);
+        }
+    }
+
+    #[test]
+    fn ignores_unmapped_security_events() {
+        assert!(map(&[record(SECURITY_CHANNEL, 4663, json!({ "ObjectName": "C:\\secret.txt" }))],
+                    "Security.evtx", false).is_empty());
+    }
+
+    /// A record of another provider, at `level`.
+    fn provider_record(channel: &str, provider: &str, level: u64, event_id: u64, data: Value) -> EventRecord {
+        let mut json = record_json(channel, json!(event_id), data);
+        json["Event"]["System"]["Provider"]["#attributes"]["Name"] = json!(provider);
+        json["Event"]["System"]["Level"] = json!(level);
+        event_record(42, timestamp(), &json).unwrap()
+    }
+
+    #[test]
+    fn routes_records_to_their_channel_mapper() {
+        let cases = [
+            (POWERSHELL_CHANNEL, EVENT_POWERSHELL_SCRIPT_BLOCK, json!({
+                "MessageNumber": 2, "MessageTotal": 3, "ScriptBlockText": "IEX   (New-Object Net.WebClient)",
+                "ScriptBlockId": "7c1f", "Path": "C:\\Temp\\a.ps1"
+            }), "PowerShell Execution",
+             "PowerShell script block executed from C:\\Temp\\a.ps1 (part 2 of 3): IEX (New-Object Net.WebClient)"),
+            (POWERSHELL_CHANNEL, EVENT_POWERSHELL_PIPELINE, json!({
+                "ContextInfo": "        Severity = Informational\r\n        Host Application = powershell.exe -enc SQBFAFgA\r\n        Command Name = Invoke-WebRequest\r\n        User = CORP\\alice\r\n",
+                "Payload": "CommandInvocation(Invoke-WebRequest)"
+            }), "PowerShell Execution",
+             "PowerShell command 'Invoke-WebRequest' executed by 'CORP\\alice' in powershell.exe -enc SQBFAFgA"),
+            (LOCAL_SESSION_MANAGER_CHANNEL, EVENT_RDP_SESSION_RECONNECTED,
+             json!({ "User": "CORP\\alice", "SessionID": 3, "Address": "10.0.0.7" }),
+             "Remote Desktop", "Remote Desktop session 3 of 'CORP\\alice' reconnected (source 10.0.0.7)"),
+            (REMOTE_CONNECTION_MANAGER_CHANNEL, EVENT_RDP_AUTHENTICATION,
+             json!({ "Param1": "alice", "Param2": "CORP", "Param3": "10.0.0.7" }),
+             "Remote Desktop", "Remote Desktop network authentication succeeded for 'CORP\\alice' from source IP 10.0.0.7"),
+            (TASK_SCHEDULER_CHANNEL, EVENT_TASK_ACTION_COMPLETED,
+             json!({ "TaskName": "\\Updater", "ActionName": "C:\\Temp\\u.exe", "ResultCode": 0 }),
+             "Scheduled Task Execution", "Scheduled task '\\Updater' completed C:\\Temp\\u.exe with result 0"),
+            (TASK_SCHEDULER_CHANNEL, EVENT_TASK_DELETED, json!({ "TaskName": "\\Updater", "UserName": "CORP\\alice" }),
+             "Scheduled Task Change", "Scheduled task '\\Updater' was deleted by 'CORP\\alice'"),
+            (DEFENDER_CHANNEL, EVENT_MALWARE_DETECTED, json!({
+                "Threat Name": "HackTool:Win64/Mimikatz", "Severity Name": "High", "Category Name": "Tool",
+                "Path": "file:_C:\\Temp\\m.exe", "Process Name": "C:\\Windows\\explorer.exe", "Detection User": "CORP\\alice"
+            }), "Malware Detection",
+             "Windows Defender detected HackTool:Win64/Mimikatz (High severity Tool) in file:_C:\\Temp\\m.exe \
+              (process C:\\Windows\\explorer.exe) for user 'CORP\\alice'"),
+            (SYSMON_CHANNEL, EVENT_SYSMON_NETWORK_CONNECTION, json!({
+                "Image": "C:\\Temp\\u.exe", "DestinationIp": "203.0.113.5", "DestinationPort": 443, "Protocol": "tcp",
+                "SourceIp": "10.0.0.5", "SourcePort": 50123
+            }), "Network Connection", "'C:\\Temp\\u.exe' connected to 203.0.113.5:443 (tcp) from 10.0.0.5:50123"),
+            (SYSMON_CHANNEL, EVENT_SYSMON_REGISTRY_VALUE_SET, json!({
+                "Image": "C:\\Temp\\u.exe", "EventType": "SetValue",
+                "TargetObject": "HKU\\S-1-5-21-1\\Software\\Microsoft\\Windows\\CurrentVersion\\Run\\u",
+                "Details": "C:\\Temp\\u.exe"
+            }), "Registry Modification",
+             "'C:\\Temp\\u.exe' performed SetValue on HKU\\S-1-5-21-1\\Software\\Microsoft\\Windows\\CurrentVersion\\Run\\u: \
+              C:\\Temp\\u.exe"),
+            (SYSMON_CHANNEL, EVENT_SYSMON_DNS_QUERY,
+             json!({ "Image": "C:\\Temp\\u.exe", "QueryName": "evil.example", "QueryResults": "203.0.113.5;" }),
+             "DNS Query", "'C:\\Temp\\u.exe' queried evil.example (203.0.113.5;)"),
+            (WMI_ACTIVITY_CHANNEL, EVENT_WMI_CONSUMER_BINDING, json!({
+                "Namespace": "//./root/subscription", "ESS": "Updater", "CONSUMER": "CommandLineEventConsumer=\"Updater\"",
+                "PossibleCause": "Binding EventFilter: ..."
+            }), "WMI Persistence",
+             "WMI event filter Updater was bound to consumer CommandLineEventConsumer=\"Updater\" in namespace \
+              //./root/subscription"),
+            (BITS_CLIENT_CHANNEL, EVENT_BITS_TRANSFER_STOPPED, json!({
+                "name": "update", "url": "http://203.0.113.5/u.exe", "bytesTransferred": 4096, "hr": "0x0"
+            }), "BITS Transfer",
+             "BITS job 'update' stopped transferring http://203.0.113.5/u.exe (4096 bytes, result 0x0)"),
+        ];
+
+        for (channel, event_id, data, event_type, description) in cases {
+            let events = map(&[record(channel, event_id, data)], "log.evtx", false);
+            assert_eq!(events, vec![(event_type.to_string(), format!("{} [record 42]", description))],
+                       "{} event {}", channel, event_id);
+        }
+    }
+
+    #[test]
+    fn attaches_mapper_details() {
+        let script = record(POWERSHELL_CHANNEL, EVENT_POWERSHELL_SCRIPT_BLOCK,
+                            json!({ "ScriptBlockText": "Get-Process", "ScriptBlockId": "7c1f" }));
+        let mut timeline = Timeline::new();
+        map_events(&[script], "log.evtx", false, &mut timeline);
+
+        let details: Vec<(&str, &[String])> = timeline.events[0].details.iter()
+            .map(|detail| (detail.label.as_str(), detail.values.as_slice()))
+            .collect();
+        assert_eq!(details, [("Script block", &["Get-Process".to_string()][..]),
+                             ("Script block ID", &["7c1f".to_string()][..])]);
+    }
+
+    #[test]
+    fn keeps_errors_of_unknown_channels_and_unmatched_sysmon_events() {
+        let records = [
+            provider_record("Application", "Application Error", 2, 1000,
+                            json!({ "#attributes": { "Name": "x" }, "AppName": "u.exe", "Empty": "" })),
+            provider_record("Application", "MsiInstaller", 1, 1033, json!({})),
+            // Information on an unknown channel and unmapped IDs of known ones
+            provider_record("Application", "MsiInstaller", 4, 11707, json!({ "Product": "x" })),
+            provider_record(SYSTEM_CHANNEL, "Service Control Manager", 2, 7000, json!({})),
+            provider_record(SYSMON_CHANNEL, "Microsoft-Windows-Sysmon", 4, 5, json!({ "Image": "C:\\Temp\\u.exe" })),
+        ];
+
+        assert_eq!(map(&records, "Application.evtx", false), vec![
+            ("Event Log Entry".to_string(), "Application Error event 1000 (Error): AppName = u.exe [record 42]".to_string()),
+            ("Event Log Entry".to_string(), "MsiInstaller event 1033 (Critical) [record 42]".to_string()),
+            ("Event Log Entry".to_string(),
+             "Microsoft-Windows-Sysmon event 5 (Information): Image = C:\\Temp\\u.exe [record 42]".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn passthrough_keeps_every_record() {
+        let records = [
+            record(SECURITY_CHANNEL, EVENT_LOGON, json!({ "TargetUserName": "alice" })),
+            provider_record("Application", "MsiInstaller", 4, 11707, json!({ "Product": "Tool" })),
+            provider_record(SYSTEM_CHANNEL, "Service Control Manager", 0, 7036, json!({ "param1": "Spooler" })),
+        ];
+        assert_eq!(map(&records, "log.evtx", false).len(), 1);
+        assert_eq!(map(&records, "log.evtx", true), vec![
+            ("User Logon".to_string(), "User 'alice' logged on [record 42]".to_string()),
+            ("Event Log Entry".to_string(), "MsiInstaller event 11707 (Information): Product = Tool [record 42]".to_string()),
+            ("Event Log Entry".to_string(), "Service Control Manager event 7036: param1 = Spooler [record 42]".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn passthrough_flattens_every_field() {
+        let record = provider_record(SYSTEM_CHANNEL, "Service Control Manager", 4, EVENT_SERVICE_INSTALLED, json!({
+            "#attributes": { "Name": "ignored" },
+            "ServiceName": "evilsvc",
+            "StartType": { "#attributes": { "Format": "enum" }, "#text": "auto start" },
+            "Binary": [ "AB", "CD" ],
+            "AccountName": null,
+            "ServiceType": 16,
+        }));
+        let mut timeline = Timeline::new();
+        map_events(&[record], "System.evtx", true, &mut timeline);
+
+        let fields: Vec<(&str, &str)> = timeline.events[0].fields.iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(fields, [
+            ("Channel", "System"),
+            ("Provider", "Service Control Manager"),
+            ("EventID", "7045"),
+            ("Level", "Information"),
+            ("Computer", "WS01.corp.local"),
+            ("EventRecordID", "42"),
+            ("UserID", "S-1-5-18"),
+            ("ServiceName", "evilsvc"),
+            ("StartType", "auto start"),
+            ("Binary", "AB, CD"),
+            ("AccountName", ""),
+            ("ServiceType", "16"),
+        ]);
+        // Mapped events keep their description
+        assert_eq!(timeline.events[0].description, "Service 'evilsvc' was installed [record 42]");
+
+        // Without passthrough no fields are attached
+        let mut timeline = Timeline::new();
+        map_events(&[self::record(SECURITY_CHANNEL, EVENT_LOGON, json!({}))], "Security.evtx", false, &mut timeline);
+        assert!(timeline.events[0].fields.is_empty());
+    }
 }
-*/ 
\ No newline at end of file
@@ -0,0 +1,458 @@
+use anyhow::Result;
+use byteorder::{ByteOrder, LittleEndian};
+use chrono::{DateTime, Utc};
+use evtx::{EvtxChunkData, ParserSettings};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::event_log_parser::{self, EventRecord, RecordKey};
+use crate::image_source::ImageSource;
+use crate::timeline::{EventType, Timeline};
+use crate::util::{is_plausible_time, windows_time_to_utc};
+
+const CHUNK_SIGNATURE: &[u8; 8] = b"ElfChnk\0";
+const CHUNK_SIZE: usize = 65536;
+/// Chunks start on a cluster (or, in the pagefile, a page), so sector
+/// alignment finds all of them.
+const CHUNK_ALIGNMENT: usize = 512;
+
+const RECORD_SIGNATURE: &[u8; 4] = b"**\0\0";
+const RECORD_HEADER_SIZE: usize = 24;
+/// Smallest record: header, fragment header, end of stream and size copy.
+const RECORD_MIN_SIZE: usize = 32;
+/// Every record's BinXML starts with a fragment header (token 0x0F, v1.1).
+const FRAGMENT_HEADER: &[u8; 4] = &[0x0F, 0x01, 0x01, 0x00];
+
+const DATA_CHECKSUM_NOTE: &str =
+    "chunk data CRC mismatch; the chunk was being written or is partly overwritten";
+
+const SCAN_BLOCK_SIZE: usize = 4 * 1024 * 1024;
+/// Each block is read with the first bytes of the next, so a record
+/// signature straddling the boundary is still found.
+const SCAN_OVERLAP: usize = RECORD_SIGNATURE.len() - 1;
+
+/// Records already added to the timeline. Records carved without their
+/// chunk have no channel, so they are matched on record ID and time alone.
+struct SeenRecords {
+    keys: HashSet<RecordKey>,
+    headers: HashSet<(u64, DateTime<Utc>)>,
+}
+
+impl SeenRecords {
+    fn new(live_records: &HashSet<RecordKey>) -> Self {
+        SeenRecords {
+            keys: live_records.clone(),
+            headers: live_records.iter().map(|(_, record_id, timestamp)| (*record_id, *timestamp)).collect(),
+        }
+    }
+
+    /// Returns false if the record was seen before.
+    fn insert(&mut self, key: RecordKey) -> bool {
+        self.headers.insert((key.1, key.2));
+        self.keys.insert(key)
+    }
+
+    /// Returns false if a record of any channel with this ID and time was
+    /// seen before.
+    fn insert_header(&mut self, record_id: u64, timestamp: DateTime<Utc>) -> bool {
+        self.headers.insert((record_id, timestamp))
+    }
+}
+
+/// Counters reported at the end of the carving pass.
+#[derive(Default)]
+struct CarveStats {
+    chunks: usize,
+    bad_header_checksum: usize,
+    bad_data_checksum: usize,
+    records: usize,
+    orphan_records: usize,
+    duplicates: usize,
+}
+
+/// Scans the whole image — unallocated clusters, slack, the pagefile and
+/// volume shadow copies included — for EVTX chunks and records, and adds
+/// those not already read from the live logs as recovered events.
+pub fn carve_event_records(image: &dyn ImageSource, live_records: &HashSet<RecordKey>,
+                           passthrough: bool, timeline: &mut Timeline) -> Result<()> {
+    info!("Carving EVTX chunks and records from {} bytes...", image.size());
+
+    let mut seen = SeenRecords::new(live_records);
+    let mut stats = CarveStats::default();
+    let settings = Arc::new(ParserSettings::default());
+
+    // Records inside a chunk that parsed are not carved a second time
+    let mut chunk_end = 0u64;
+    let mut block = vec![0u8; SCAN_BLOCK_SIZE + SCAN_OVERLAP];
+    let mut block_offset = 0u64;
+
+    while block_offset < image.size() {
+        let remaining = image.size() - block_offset;
+        let length = remaining.min(SCAN_BLOCK_SIZE as u64) as usize;
+        let block = &mut block[..remaining.min((SCAN_BLOCK_SIZE + SCAN_OVERLAP) as u64) as usize];
+        image.read_at(block_offset, block)?;
+
+        // Records follow each other without padding, so they can start at
+        // any offset
+        for position in 0..length {
+            let offset = block_offset + position as u64;
+
+            if position % CHUNK_ALIGNMENT == 0 && block[position..].starts_with(CHUNK_SIGNATURE) {
+                if let Some(end) = carve_chunk(image, offset, &settings, &mut seen, &mut stats,
+                                               passthrough, timeline) {
+                    chunk_end = end;
+                }
+            }
+
+            if offset >= chunk_end && block[position..].starts_with(RECORD_SIGNATURE) {
+                carve_orphan_record(image, offset, &mut seen, &mut stats, timeline);
+            }
+        }
+
+        block_offset += length as u64;
+    }
+
+    if stats.bad_header_checksum > 0 || stats.bad_data_checksum > 0 {
+        warn!("{} carved chunks failed the header CRC and were skipped, {} failed the data CRC",
+              stats.bad_header_checksum, stats.bad_data_checksum);
+    }
+    info!("Carved {} EVTX chunks: {} recovered records, {} records without a chunk, {} duplicates skipped",
+          stats.chunks, stats.records, stats.orphan_records, stats.duplicates);
+    Ok(())
+}
+
+/// Parses the chunk at `offset` and adds its unseen records. Returns the end
+/// of the chunk when its header checksum is valid.
+fn carve_chunk(image: &dyn ImageSource, offset: u64, settings: &Arc<ParserSettings>,
+               seen: &mut SeenRecords, stats: &mut CarveStats, passthrough: bool,
+               timeline: &mut Timeline) -> Option<u64> {
+    if offset + CHUNK_SIZE as u64 > image.size() {
+        return None;
+    }
+    let data = image.read_bytes(offset, CHUNK_SIZE).ok()?;
+    let mut chunk = EvtxChunkData::new(data, false).ok()?;
+
+    if !chunk.validate_header_checksum() {
+        stats.bad_header_checksum += 1;
+        return None;
+    }
+    // Chunks that were being written, or are partly overwritten, fail the
+    // data checksum; the records that still decode are kept but labelled
+    let data_checksum_valid = chunk.validate_data_checksum();
+    if !data_checksum_valid {
+        stats.bad_data_checksum += 1;
+    }
+    stats.chunks += 1;
+
+    let mut records = Vec::new();
+    if let Ok(mut parsed) = chunk.parse(Arc::clone(settings)) {
+        for record in parsed.iter().filter_map(|record| record.ok()) {
+            let Ok(record) = record.into_json_value() else {
+                continue;
+            };
+            let Some(record) = event_log_parser::event_record(record.event_record_id, record.timestamp, &record.data) else {
+                continue;
+            };
+            if !seen.insert(record.key()) {
+                stats.duplicates += 1;
+                continue;
+            }
+            records.push(record);
+        }
+    }
+
+    stats.records += records.len();
+    let first = timeline.len();
+    add_recovered_events(&records, &format!("EVTX chunk carved at offset 0x{:X}", offset),
+                         passthrough, timeline);
+    if !data_checksum_valid {
+        for event in &mut timeline.events[first..] {
+            event.add_detail("Chunk integrity", vec![DATA_CHECKSUM_NOTE.to_string()]);
+        }
+    }
+    Some(offset + CHUNK_SIZE as u64)
+}
+
+fn add_recovered_events(records: &[EventRecord], source: &str, passthrough: bool, timeline: &mut Timeline) {
+    let first = timeline.len();
+    event_log_parser::map_events(records, source, passthrough, timeline);
+    for event in &mut timeline.events[first..] {
+        event.recovered = true;
+    }
+}
+
+/// Adds a record found outside any intact chunk. Its BinXML refers to names
+/// and templates stored elsewhere in the chunk, so only the record header —
+/// record ID and timestamp — can be recovered.
+fn carve_orphan_record(image: &dyn ImageSource, offset: u64, seen: &mut SeenRecords,
+                       stats: &mut CarveStats, timeline: &mut Timeline) {
+    let Ok(header) = image.read_bytes(offset, RECORD_HEADER_SIZE + FRAGMENT_HEADER.len()) else {
+        return;
+    };
+    let size = LittleEndian::read_u32(&header[4..8]) as usize;
+    if !(RECORD_MIN_SIZE..=CHUNK_SIZE - 512).contains(&size) || &header[RECORD_HEADER_SIZE..] != FRAGMENT_HEADER {
+        return;
+    }
+
+    // The size is repeated in the last four bytes of the record
+    let Ok(size_copy) = image.read_bytes(offset + size as u64 - 4, 4) else {
+        return;
+    };
+    if LittleEndian::read_u32(&size_copy) as usize != size {
+        return;
+    }
+
+    let record_id = LittleEndian::read_u64(&header[8..16]);
+    let timestamp = windows_time_to_utc(LittleEndian::read_u64(&header[16..24]));
+    let Some(timestamp) = timestamp.filter(is_plausible_time) else {
+        return;
+    };
+    if !seen.insert_header(record_id, timestamp) {
+        stats.duplicates += 1;
+        return;
+    }
+
+    stats.orphan_records += 1;
+    timeline.add_log_event(
+        timestamp,
+        EventType::LogEntry,
+        "Event record carved without its chunk; only the record ID and time could be recovered",
+        &format!("EVTX record carved at offset 0x{:X}", offset),
+        record_id,
+    ).recovered = true;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_source::MemorySource;
+
+    /// 2024-03-01 12:30:00 UTC as a FILETIME.
+    const RECORD_TIME: u64 = 133_537_698_000_000_000;
+    const CHUNK_OFFSET: usize = 0x3000;
+
+    fn crc32(data: &[u8]) -> u32 {
+        let mut crc = flate2::Crc::new();
+        crc.update(data);
+        crc.sum()
+    }
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    /// Writes BinXML without templates: every element name is stored inline,
+    /// at the offset its reference names, and every value is a string
+    /// except the numeric `EventID` and `Level`.
+    struct BinXml<'a> {
+        chunk: &'a mut Vec<u8>,
+    }
+
+    impl BinXml<'_> {
+        fn name(&mut self, name: &str) {
+            let offset = self.chunk.len() as u32 + 4;
+            self.chunk.extend(offset.to_le_bytes());
+            self.chunk.extend([0; 6]);
+            self.chunk.extend((name.len() as u16).to_le_bytes());
+            self.chunk.extend(utf16(name));
+            self.chunk.extend([0, 0]);
+        }
+
+        fn string(&mut self, text: &str) {
+            self.chunk.extend([0x05, 0x01]);
+            self.chunk.extend((text.len() as u16).to_le_bytes());
+            self.chunk.extend(utf16(text));
+        }
+
+        fn start(&mut self, name: &str, attribute: Option<(&str, &str)>) {
+            self.chunk.push(if attribute.is_some() { 0x41 } else { 0x01 });
+            self.chunk.extend(0xFFFFu16.to_le_bytes());
+            self.chunk.extend(0u32.to_le_bytes());
+            self.name(name);
+            if let Some((attribute, value)) = attribute {
+                self.chunk.extend(0u32.to_le_bytes());
+                self.chunk.push(0x06);
+                self.name(attribute);
+                self.string(value);
+            }
+        }
+
+        fn text_element(&mut self, name: &str, attribute: Option<(&str, &str)>, text: &str) {
+            self.start(name, attribute);
+            self.chunk.push(0x02);
+            self.string(text);
+            self.chunk.push(0x04);
+        }
+
+        fn number_element(&mut self, name: &str, value_type: u8, value: &[u8]) {
+            self.start(name, None);
+            self.chunk.push(0x02);
+            self.chunk.extend([0x05, value_type]);
+            self.chunk.extend(value);
+            self.chunk.push(0x04);
+        }
+    }
+
+    /// Appends an event record of `channel` with one EventData field.
+    fn append_record(chunk: &mut Vec<u8>, record_id: u64, channel: &str, event_id: u16,
+                     field: (&str, &str)) {
+        let start = chunk.len();
+        chunk.extend(RECORD_SIGNATURE);
+        chunk.extend([0; 4]);
+        chunk.extend(record_id.to_le_bytes());
+        chunk.extend(RECORD_TIME.to_le_bytes());
+        chunk.extend(FRAGMENT_HEADER);
+
+        let mut xml = BinXml { chunk };
+        xml.start("Event", None);
+        xml.chunk.push(0x02);
+        xml.start("System", None);
+        xml.chunk.push(0x02);
+        xml.start("Provider", Some(("Name", "Microsoft-Windows-Security-Auditing")));
+        xml.chunk.push(0x03);
+        xml.number_element("EventID", 0x06, &event_id.to_le_bytes());
+        xml.number_element("Level", 0x04, &[0]);
+        xml.text_element("Channel", None, channel);
+        xml.chunk.push(0x04);
+        xml.start("EventData", None);
+        xml.chunk.push(0x02);
+        xml.text_element("Data", Some(("Name", field.0)), field.1);
+        xml.chunk.push(0x04);
+        xml.chunk.push(0x04);
+        xml.chunk.push(0x00);
+
+        let size = (chunk.len() - start + 4) as u32;
+        chunk.extend(size.to_le_bytes());
+        chunk[start + 4..start + 8].copy_from_slice(&size.to_le_bytes());
+    }
+
+    /// A chunk holding logons of alice (record 1) and bob (record 2).
+    fn chunk(valid_data_checksum: bool) -> Vec<u8> {
+        let mut chunk = CHUNK_SIGNATURE.to_vec();
+        chunk.resize(512, 0);
+        append_record(&mut chunk, 1, "Security", 4624, ("TargetUserName", "alice"));
+        let last_record = chunk.len() as u32;
+        append_record(&mut chunk, 2, "Security", 4624, ("TargetUserName", "bob"));
+        let free_space = chunk.len() as u32;
+        chunk.resize(CHUNK_SIZE, 0);
+
+        for (offset, value) in [(8, 1u64), (16, 2), (24, 1), (32, 2)] {
+            chunk[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        }
+        let data_checksum = crc32(&chunk[512..free_space as usize]) ^ u32::from(!valid_data_checksum);
+        for (offset, value) in [(40, 128), (44, last_record), (48, free_space), (52, data_checksum)] {
+            chunk[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        let header: Vec<u8> = chunk[..120].iter().chain(&chunk[128..512]).copied().collect();
+        let header_checksum = crc32(&header);
+        chunk[124..128].copy_from_slice(&header_checksum.to_le_bytes());
+        chunk
+    }
+
+    fn first_record(chunk: &[u8]) -> &[u8] {
+        &chunk[512..512 + LittleEndian::read_u32(&chunk[516..520]) as usize]
+    }
+
+    /// An image of free space with `content` at `offset`.
+    fn image(size: usize, content: &[(usize, &[u8])]) -> MemorySource {
+        let mut data = vec![0u8; size];
+        for &(offset, bytes) in content {
+            data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        }
+        MemorySource::new(data)
+    }
+
+    fn carve(image: &MemorySource, live_records: &HashSet<RecordKey>) -> Timeline {
+        let mut timeline = Timeline::new();
+        carve_event_records(image, live_records, false, &mut timeline).unwrap();
+        timeline
+    }
+
+    fn descriptions(timeline: &Timeline) -> Vec<&str> {
+        timeline.events.iter().map(|event| event.description.as_str()).collect()
+    }
+
+    fn live_key(channel: &str, record_id: u64) -> RecordKey {
+        (channel.to_string(), record_id, windows_time_to_utc(RECORD_TIME).unwrap())
+    }
+
+    #[test]
+    fn carves_records_from_chunks() {
+        let timeline = carve(&image(0x20000, &[(CHUNK_OFFSET, &chunk(true))]), &HashSet::new());
+
+        assert_eq!(descriptions(&timeline), ["User 'alice' logged on [record 1]", "User 'bob' logged on [record 2]"]);
+        for event in &timeline.events {
+            assert!(event.recovered);
+            assert_eq!(event.source_artifact, "EVTX chunk carved at offset 0x3000");
+            assert_eq!(event.timestamp.to_rfc3339(), "2024-03-01T12:30:00+00:00");
+            assert!(event.details.is_empty());
+        }
+    }
+
+    #[test]
+    fn skips_records_already_read_from_live_logs() {
+        // The same record ID in another channel is a different record
+        let live_records = HashSet::from([live_key("Security", 1), live_key("System", 2)]);
+        let timeline = carve(&image(0x20000, &[(CHUNK_OFFSET, &chunk(true))]), &live_records);
+        assert_eq!(descriptions(&timeline), ["User 'bob' logged on [record 2]"]);
+
+        // A second copy of the chunk adds nothing
+        let chunk = chunk(true);
+        let timeline = carve(&image(0x30000, &[(CHUNK_OFFSET, &chunk), (0x20000, &chunk)]), &HashSet::new());
+        assert_eq!(timeline.events.len(), 2);
+    }
+
+    #[test]
+    fn labels_records_of_chunks_failing_the_data_checksum() {
+        let timeline = carve(&image(0x20000, &[(CHUNK_OFFSET, &chunk(false))]), &HashSet::new());
+
+        assert_eq!(timeline.events.len(), 2);
+        for event in &timeline.events {
+            assert_eq!(event.details.len(), 1);
+            assert_eq!(event.details[0].label, "Chunk integrity");
+            assert_eq!(event.details[0].values, [DATA_CHECKSUM_NOTE]);
+        }
+    }
+
+    #[test]
+    fn carves_record_headers_outside_intact_chunks() {
+        // A chunk failing the header checksum is skipped, but its records
+        // are still found by their signature
+        let mut chunk = chunk(true);
+        chunk[124] ^= 1;
+        let live_records = HashSet::from([live_key("Security", 2)]);
+        let timeline = carve(&image(0x20000, &[(CHUNK_OFFSET, &chunk)]), &live_records);
+
+        assert_eq!(descriptions(&timeline), [
+            "Event record carved without its chunk; only the record ID and time could be recovered [record 1]",
+        ]);
+        assert_eq!(timeline.events[0].source_artifact, "EVTX record carved at offset 0x3200");
+        assert!(timeline.events[0].recovered);
+    }
+
+    #[test]
+    fn finds_records_across_scan_blocks() {
+        let chunk = chunk(true);
+        let record = first_record(&chunk);
+        let offset = SCAN_BLOCK_SIZE - 2;
+        let timeline = carve(&image(SCAN_BLOCK_SIZE + 0x1000, &[(offset, record)]), &HashSet::new());
+
+        assert_eq!(timeline.events.len(), 1);
+        assert_eq!(timeline.events[0].source_artifact, format!("EVTX record carved at offset 0x{:X}", offset));
+    }
+
+    #[test]
+    fn rejects_implausible_record_headers() {
+        let chunk = chunk(true);
+        // Written in 1985, and with a size copy that does not match
+        let mut record = first_record(&chunk).to_vec();
+        record[16..24].copy_from_slice(&121_178_592_000_000_000u64.to_le_bytes());
+        let mut wrong_size_copy = first_record(&chunk).to_vec();
+        let end = wrong_size_copy.len();
+        wrong_size_copy[end - 4] ^= 1;
+
+        let timeline = carve(&image(0x10000, &[(0x1000, &record), (0x4000, &wrong_size_copy)]), &HashSet::new());
+        assert!(timeline.is_empty());
+    }
+}
@@ -0,0 +1,660 @@
+use anyhow::{Context, Result};
+use byteorder::{LittleEndian, ReadBytesExt};
+use chrono::{TimeZone, Utc};
+use flate2::read::ZlibDecoder;
+use memmap2::Mmap;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+use crate::image_source::ImageSource;
+
+const EWF_SIGNATURE: &[u8; 8] = b"EVF\x09\x0d\x0a\xff\x00";
+const FILE_HEADER_SIZE: usize = 13;
+const SECTION_DESCRIPTOR_SIZE: usize = 76;
+const TABLE_HEADER_SIZE: usize = 24;
+const COMPRESSED_FLAG: u32 = 0x8000_0000;
+const CHUNK_CACHE_SIZE: usize = 64;
+/// Header sections hold a few lines of case metadata.
+const MAX_HEADER_SIZE: u64 = 1 << 20;
+
+/// Acquisition details recorded by the imaging tool in the EWF header and
+/// hash sections.
+#[derive(Debug, Clone, Default)]
+pub struct AcquisitionInfo {
+    pub case_number: Option<String>,
+    pub evidence_number: Option<String>,
+    pub description: Option<String>,
+    pub examiner: Option<String>,
+    pub notes: Option<String>,
+    pub acquisition_software: Option<String>,
+    pub acquisition_os: Option<String>,
+    pub acquisition_date: Option<String>,
+    pub system_date: Option<String>,
+    pub md5: Option<String>,
+    pub sha1: Option<String>,
+    pub sector_count: u64,
+    pub bytes_per_sector: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ChunkLocation {
+    segment: usize,
+    offset: u64,
+    size: u64,
+    compressed: bool,
+}
+
+/// Expert Witness Format (EnCase .E01) image reader.
+///
+/// Chunks are decompressed lazily on first access and kept in a small cache,
+/// so sequential reads of the logical image only inflate each chunk once.
+/// Multi-segment images (.E01, .E02, ...) are read as one logical image; chunk
+/// table offsets are relative to the segment file they appear in.
+pub struct EwfImage {
+    segments: Vec<Mmap>,
+    chunks: Vec<ChunkLocation>,
+    chunk_size: u64,
+    media_size: u64,
+    info: AcquisitionInfo,
+    cache: Mutex<ChunkCache>,
+}
+
+#[derive(Default)]
+struct ChunkCache {
+    chunks: HashMap<usize, Arc<Vec<u8>>>,
+    order: VecDeque<usize>,
+}
+
+impl ChunkCache {
+    fn get(&self, index: usize) -> Option<Arc<Vec<u8>>> {
+        self.chunks.get(&index).cloned()
+    }
+
+    fn insert(&mut self, index: usize, chunk: Arc<Vec<u8>>) {
+        if self.chunks.len() >= CHUNK_CACHE_SIZE {
+            if let Some(oldest) = self.order.pop_front() {
+                self.chunks.remove(&oldest);
+            }
+        }
+        self.order.push_back(index);
+        self.chunks.insert(index, chunk);
+    }
+}
+
+#[derive(Debug)]
+struct SectionDescriptor {
+    section_type: String,
+    offset: u64,
+    next_offset: u64,
+    size: u64,
+}
+
+/// Returns true if the file starts with the EWF (E01) segment signature.
+pub fn is_ewf_file<P: AsRef<Path>>(path: P) -> bool {
+    let mut signature = [0u8; 8];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut signature))
+        .map(|_| signature == *EWF_SIGNATURE)
+        .unwrap_or(false)
+}
+
+impl EwfImage {
+    /// Opens an EWF image from its segment files, in segment order.
+    pub fn open(paths: &[PathBuf]) -> Result<Self> {
+        let mut segments = Vec::with_capacity(paths.len());
+
+        for (index, path) in paths.iter().enumerate() {
+            let file = File::open(path)
+                .with_context(|| format!("Failed to open EWF segment file {}", path.display()))?;
+
+            let data = unsafe { Mmap::map(&file) }
+                .with_context(|| format!("Failed to memory map EWF segment file {}", path.display()))?;
+
+            if data.len() < FILE_HEADER_SIZE || data[..8] != *EWF_SIGNATURE {
+                anyhow::bail!("Invalid EWF signature in {}", path.display());
+            }
+
+            let segment_number = u16::from_le_bytes([data[9], data[10]]) as usize;
+            if segment_number != index + 1 {
+                anyhow::bail!(
+                    "EWF segment {} has segment number {}, expected {}",
+                    path.display(),
+                    segment_number,
+                    index + 1
+                );
+            }
+
+            segments.push(data);
+        }
+
+        let mut image = EwfImage {
+            segments,
+            chunks: Vec::new(),
+            chunk_size: 0,
+            media_size: 0,
+            info: AcquisitionInfo::default(),
+            cache: Mutex::new(ChunkCache::default()),
+        };
+
+        for segment in 0..image.segments.len() {
+            image.parse_sections(segment)?;
+        }
+        image.validate()?;
+
+        info!(
+            "EWF image: {} segment(s), {} chunks of {} bytes, logical size {} bytes",
+            image.segments.len(),
+            image.chunks.len(),
+            image.chunk_size,
+            image.media_size
+        );
+
+        Ok(image)
+    }
+
+    fn chunk(&self, index: usize) -> Result<Arc<Vec<u8>>> {
+        if let Some(chunk) = self.cache.lock().unwrap().get(index) {
+            return Ok(chunk);
+        }
+
+        let location = self.chunks.get(index)
+            .with_context(|| format!("EWF chunk {} is missing from the chunk table", index))?;
+        let expected = self.chunk_size.min(self.media_size - index as u64 * self.chunk_size) as usize;
+        let raw = segment_bytes(&self.segments[location.segment], location.offset, location.size as usize)?;
+
+        let chunk = if location.compressed {
+            // One byte past the chunk size is enough to tell it overflowed
+            let mut decoded = Vec::with_capacity(expected);
+            ZlibDecoder::new(raw)
+                .take(self.chunk_size + 1)
+                .read_to_end(&mut decoded)
+                .with_context(|| format!("Failed to decompress EWF chunk {}", index))?;
+            if decoded.len() != expected {
+                anyhow::bail!("EWF chunk {} decompressed to {} bytes, expected {}", index, decoded.len(), expected);
+            }
+            decoded
+        } else {
+            // Uncompressed chunks carry a trailing Adler-32 checksum
+            raw[..expected.min(raw.len())].to_vec()
+        };
+
+        let chunk = Arc::new(chunk);
+        self.cache.lock().unwrap().insert(index, chunk.clone());
+        Ok(chunk)
+    }
+
+    fn parse_sections(&mut self, segment: usize) -> Result<()> {
+        let mut offset = FILE_HEADER_SIZE as u64;
+        let mut sectors_end = 0u64;
+        let data = &self.segments[segment];
+
+        loop {
+            let section = read_section_descriptor(data, offset)?;
+            let data_offset = section.offset + SECTION_DESCRIPTOR_SIZE as u64;
+            let data_size = section.size.saturating_sub(SECTION_DESCRIPTOR_SIZE as u64) as usize;
+
+            match section.section_type.as_str() {
+                "header" | "header2" => {
+                    let data = segment_bytes(data, data_offset, data_size)?;
+                    if let Err(e) = parse_header_section(data, &section.section_type, &mut self.info) {
+                        warn!("Failed to parse EWF {} section: {}", section.section_type, e);
+                    }
+                }
+                // Every segment after the first repeats the volume
+                // information in a data section
+                "volume" | "disk" | "data" => {
+                    let data = segment_bytes(data, data_offset, data_size)?;
+                    let mut cursor = Cursor::new(data);
+                    cursor.set_position(8);
+                    let sectors_per_chunk = cursor.read_u32::<LittleEndian>()?;
+                    let bytes_per_sector = cursor.read_u32::<LittleEndian>()?;
+                    // EnCase volume sections store a 64-bit sector count, the
+                    // short SMART variant only 32 bits
+                    let sector_count = if data.len() >= 1052 {
+                        cursor.read_u64::<LittleEndian>()?
+                    } else {
+                        cursor.read_u32::<LittleEndian>()? as u64
+                    };
+
+                    self.chunk_size = sectors_per_chunk as u64 * bytes_per_sector as u64;
+                    self.media_size = sector_count.checked_mul(bytes_per_sector as u64)
+                        .context("EWF media size is out of range")?;
+                    self.info.sector_count = sector_count;
+                    self.info.bytes_per_sector = bytes_per_sector;
+                }
+                "sectors" => {
+                    sectors_end = section.offset + section.size;
+                }
+                "table" => {
+                    let data = segment_bytes(data, data_offset, data_size)?;
+                    // The last chunk of a table runs to the end of the
+                    // sectors section that precedes it
+                    let chunks_end = if sectors_end > 0 { sectors_end } else { section.offset };
+                    self.chunks.extend(parse_table_section(data, segment, chunks_end)?);
+                }
+                "hash" => {
+                    let data = segment_bytes(data, data_offset, data_size)?;
+                    if data.len() >= 16 {
+                        self.info.md5 = Some(to_hex(&data[..16]));
+                    }
+                }
+                "digest" => {
+                    let data = segment_bytes(data, data_offset, data_size)?;
+                    if data.len() >= 36 {
+                        self.info.md5 = Some(to_hex(&data[..16]));
+                        self.info.sha1 = Some(to_hex(&data[16..36]));
+                    }
+                }
+                "next" | "done" => break,
+                _ => {}
+            }
+
+            if section.next_offset <= offset || section.next_offset as usize >= data.len() {
+                break;
+            }
+            offset = section.next_offset;
+        }
+
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.chunk_size == 0 {
+            anyhow::bail!("EWF image has no volume section");
+        }
+
+        let expected_chunks = self.media_size.div_ceil(self.chunk_size) as usize;
+        if self.chunks.len() < expected_chunks {
+            warn!(
+                "EWF chunk table lists {} of {} chunks; missing chunks will be unreadable",
+                self.chunks.len(),
+                expected_chunks
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl ImageSource for EwfImage {
+    /// Reads logical (decompressed) media data starting at `offset`.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        if offset.checked_add(buf.len() as u64).is_none_or(|end| end > self.media_size) {
+            anyhow::bail!("Attempted to read beyond disk image bounds");
+        }
+
+        let mut written = 0;
+        while written < buf.len() {
+            let position = offset + written as u64;
+            let chunk_index = (position / self.chunk_size) as usize;
+            let chunk = self.chunk(chunk_index)?;
+            let chunk_offset = (position % self.chunk_size) as usize;
+            let available = chunk.len().saturating_sub(chunk_offset);
+            if available == 0 {
+                anyhow::bail!("EWF chunk {} is shorter than expected", chunk_index);
+            }
+            let wanted = (buf.len() - written).min(available);
+            buf[written..written + wanted].copy_from_slice(&chunk[chunk_offset..chunk_offset + wanted]);
+            written += wanted;
+        }
+
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.media_size
+    }
+
+    fn sector_size(&self) -> u32 {
+        self.info.bytes_per_sector
+    }
+
+    fn metadata(&self) -> Option<&AcquisitionInfo> {
+        Some(&self.info)
+    }
+}
+
+fn read_section_descriptor(data: &[u8], offset: u64) -> Result<SectionDescriptor> {
+    let data = segment_bytes(data, offset, SECTION_DESCRIPTOR_SIZE)?;
+    let section_type = String::from_utf8_lossy(&data[..16])
+        .trim_end_matches('\0')
+        .to_string();
+
+    let mut cursor = Cursor::new(&data[16..]);
+    let next_offset = cursor.read_u64::<LittleEndian>()?;
+    let size = cursor.read_u64::<LittleEndian>()?;
+
+    Ok(SectionDescriptor {
+        section_type,
+        offset,
+        next_offset,
+        size,
+    })
+}
+
+fn segment_bytes(data: &[u8], offset: u64, length: usize) -> Result<&[u8]> {
+    let end = (offset as usize).checked_add(length)
+        .filter(|&end| end <= data.len())
+        .context("EWF section extends beyond end of segment file")?;
+    Ok(&data[offset as usize..end])
+}
+
+fn parse_table_section(data: &[u8], segment: usize, chunks_end: u64) -> Result<Vec<ChunkLocation>> {
+    if data.len() < TABLE_HEADER_SIZE {
+        anyhow::bail!("EWF table section too short");
+    }
+
+    let mut cursor = Cursor::new(data);
+    let entry_count = cursor.read_u32::<LittleEndian>()? as usize;
+    cursor.set_position(8);
+    let base_offset = cursor.read_u64::<LittleEndian>()?;
+
+    let entries_end = TABLE_HEADER_SIZE + entry_count * 4;
+    if entries_end > data.len() {
+        anyhow::bail!("EWF table section truncated");
+    }
+
+    let offsets: Vec<(u64, bool)> = data[TABLE_HEADER_SIZE..entries_end]
+        .chunks(4)
+        .map(|entry| {
+            let value = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]);
+            (base_offset + (value & !COMPRESSED_FLAG) as u64, value & COMPRESSED_FLAG != 0)
+        })
+        .collect();
+
+    let mut chunks = Vec::with_capacity(offsets.len());
+    for (i, &(offset, compressed)) in offsets.iter().enumerate() {
+        let end = offsets.get(i + 1).map(|&(next, _)| next).unwrap_or(chunks_end);
+        chunks.push(ChunkLocation {
+            segment,
+            offset,
+            size: end.saturating_sub(offset),
+            compressed,
+        });
+    }
+
+    Ok(chunks)
+}
+
+fn parse_header_section(data: &[u8], section_type: &str, info: &mut AcquisitionInfo) -> Result<()> {
+    let mut decoded = Vec::new();
+    ZlibDecoder::new(data).take(MAX_HEADER_SIZE + 1).read_to_end(&mut decoded)?;
+    if decoded.len() as u64 > MAX_HEADER_SIZE {
+        anyhow::bail!("EWF {} section is implausibly large", section_type);
+    }
+
+    let text = if section_type == "header2" {
+        let units: Vec<u16> = decoded
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units).trim_start_matches('\u{feff}').to_string()
+    } else {
+        String::from_utf8_lossy(&decoded).to_string()
+    };
+
+    let lines: Vec<&str> = text.lines().map(|line| line.trim_end_matches('\r')).collect();
+    let keys_line = lines.iter().position(|line| *line == "main")
+        .map(|index| index + 1)
+        .context("EWF header has no main category")?;
+
+    let keys: Vec<&str> = lines.get(keys_line).context("EWF header has no keys")?.split('\t').collect();
+    let values: Vec<&str> = lines.get(keys_line + 1).context("EWF header has no values")?.split('\t').collect();
+
+    for (key, value) in keys.iter().zip(values.iter()) {
+        let value = value.trim();
+        if value.is_empty() {
+            continue;
+        }
+        let value = value.to_string();
+        // header2 takes precedence over header, so only fill what is missing
+        let slot = match *key {
+            "c" => &mut info.case_number,
+            "n" => &mut info.evidence_number,
+            "a" => &mut info.description,
+            "e" => &mut info.examiner,
+            "t" => &mut info.notes,
+            "av" => &mut info.acquisition_software,
+            "ov" => &mut info.acquisition_os,
+            "m" => &mut info.acquisition_date,
+            "u" => &mut info.system_date,
+            _ => continue,
+        };
+        if slot.is_none() {
+            *slot = Some(match *key {
+                "m" | "u" => format_header_date(&value),
+                _ => value,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn format_header_date(value: &str) -> String {
+    // header2 stores POSIX timestamps, header "YYYY MM DD HH MM SS"
+    if let Ok(seconds) = value.parse::<i64>() {
+        if let chrono::LocalResult::Single(dt) = Utc.timestamp_opt(seconds, 0) {
+            return dt.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+        }
+    }
+
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    if parts.len() == 6 {
+        return format!(
+            "{}-{:0>2}-{:0>2}T{:0>2}:{:0>2}:{:0>2}",
+            parts[0], parts[1], parts[2], parts[3], parts[4], parts[5]
+        );
+    }
+
+    value.to_string()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    const SECTOR_SIZE: usize = 512;
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// Writes an EWF segment file one section at a time.
+    struct SegmentWriter {
+        data: Vec<u8>,
+    }
+
+    impl SegmentWriter {
+        fn new(number: u16) -> Self {
+            let mut data = EWF_SIGNATURE.to_vec();
+            data.push(1);
+            data.extend(number.to_le_bytes());
+            data.extend([0, 0]);
+            SegmentWriter { data }
+        }
+
+        /// Appends a section and returns the offset of its data.
+        fn section(&mut self, section_type: &str, content: &[u8]) -> u64 {
+            let offset = self.data.len() as u64;
+            let size = (SECTION_DESCRIPTOR_SIZE + content.len()) as u64;
+            // The last section points to itself
+            let next_offset = if matches!(section_type, "next" | "done") { offset } else { offset + size };
+
+            let mut descriptor = vec![0u8; SECTION_DESCRIPTOR_SIZE];
+            descriptor[..section_type.len()].copy_from_slice(section_type.as_bytes());
+            descriptor[16..24].copy_from_slice(&next_offset.to_le_bytes());
+            descriptor[24..32].copy_from_slice(&size.to_le_bytes());
+            self.data.extend(descriptor);
+            self.data.extend(content);
+            offset + SECTION_DESCRIPTOR_SIZE as u64
+        }
+
+        /// A sectors section holding `chunks`, followed by its table.
+        fn chunks(&mut self, chunks: &[(bool, Vec<u8>)]) {
+            let sectors: Vec<u8> = chunks.iter().flat_map(|(_, chunk)| chunk.clone()).collect();
+            let mut offset = self.section("sectors", &sectors) as u32;
+
+            let mut table = vec![0u8; TABLE_HEADER_SIZE];
+            table[..4].copy_from_slice(&(chunks.len() as u32).to_le_bytes());
+            for (compressed, chunk) in chunks {
+                let flag = if *compressed { COMPRESSED_FLAG } else { 0 };
+                table.extend((offset | flag).to_le_bytes());
+                offset += chunk.len() as u32;
+            }
+            self.section("table", &table);
+        }
+    }
+
+    /// The SMART-style volume section: one sector per chunk.
+    fn volume_section(sector_count: u32) -> Vec<u8> {
+        let mut volume = vec![0u8; 94];
+        volume[8..12].copy_from_slice(&1u32.to_le_bytes());
+        volume[12..16].copy_from_slice(&(SECTOR_SIZE as u32).to_le_bytes());
+        volume[16..20].copy_from_slice(&sector_count.to_le_bytes());
+        volume
+    }
+
+    /// Uncompressed chunks end in an Adler-32 checksum.
+    fn stored_chunk(byte: u8) -> (bool, Vec<u8>) {
+        let mut chunk = vec![byte; SECTOR_SIZE];
+        chunk.extend([0xDE, 0xAD, 0xBE, 0xEF]);
+        (false, chunk)
+    }
+
+    #[test]
+    fn reads_section_descriptors() {
+        let mut segment = SegmentWriter::new(1);
+        segment.section("header2", &[0; 24]);
+        segment.section("done", &[]);
+
+        let descriptor = read_section_descriptor(&segment.data, FILE_HEADER_SIZE as u64).unwrap();
+        assert_eq!(descriptor.section_type, "header2");
+        assert_eq!(descriptor.size, 100);
+        assert_eq!(descriptor.next_offset, FILE_HEADER_SIZE as u64 + 100);
+        assert!(read_section_descriptor(&segment.data, segment.data.len() as u64 - 10).is_err());
+    }
+
+    #[test]
+    fn chunk_table_entries_run_to_the_next_chunk() {
+        let mut table = vec![0u8; TABLE_HEADER_SIZE];
+        table[..4].copy_from_slice(&3u32.to_le_bytes());
+        table[8..16].copy_from_slice(&1000u64.to_le_bytes());
+        for entry in [COMPRESSED_FLAG, 0x100, COMPRESSED_FLAG | 0x180] {
+            table.extend(entry.to_le_bytes());
+        }
+
+        let chunks = parse_table_section(&table, 2, 1500).unwrap();
+        let layout: Vec<_> = chunks.iter()
+            .map(|chunk| (chunk.segment, chunk.offset, chunk.size, chunk.compressed))
+            .collect();
+        assert_eq!(layout, vec![(2, 1000, 256, true), (2, 1256, 128, false), (2, 1384, 116, true)]);
+
+        table.truncate(TABLE_HEADER_SIZE + 8);
+        assert!(parse_table_section(&table, 2, 1500).is_err());
+    }
+
+    #[test]
+    fn header2_takes_precedence_over_header() {
+        let mut info = AcquisitionInfo::default();
+        let header2: Vec<u8> = "\u{feff}1\nmain\nc\tn\te\tm\n42\tEV-1\tJane Doe\t1705312923\n\n"
+            .encode_utf16().flat_map(u16::to_le_bytes).collect();
+        parse_header_section(&zlib(&header2), "header2", &mut info).unwrap();
+        let header = "1\r\nmain\r\nc\te\tt\tm\r\n99\tOther\tnotes\t2024 1 15 10 2 3\r\n\r\n";
+        parse_header_section(&zlib(header.as_bytes()), "header", &mut info).unwrap();
+
+        assert_eq!(info.case_number.as_deref(), Some("42"));
+        assert_eq!(info.evidence_number.as_deref(), Some("EV-1"));
+        assert_eq!(info.examiner.as_deref(), Some("Jane Doe"));
+        assert_eq!(info.notes.as_deref(), Some("notes"));
+        assert_eq!(info.acquisition_date.as_deref(), Some("2024-01-15T10:02:03Z"));
+        assert_eq!(format_header_date("2024 1 15 10 2 3"), "2024-01-15T10:02:03");
+
+        assert!(parse_header_section(&zlib(b"1\nsrce\n"), "header", &mut info).is_err());
+    }
+
+    #[test]
+    fn reads_multi_segment_images() {
+        let mut first = SegmentWriter::new(1);
+        first.section("header", &zlib(b"1\nmain\nc\te\n42\tJane\n\n"));
+        first.section("volume", &volume_section(3));
+        first.chunks(&[(true, zlib(&[0xAA; SECTOR_SIZE])), stored_chunk(0xBB)]);
+        first.section("next", &[]);
+
+        let mut second = SegmentWriter::new(2);
+        second.section("data", &volume_section(3));
+        second.chunks(&[stored_chunk(0xCC)]);
+        second.section("hash", &[0x11; 16]);
+        second.section("done", &[]);
+
+        let directory = std::env::temp_dir().join(format!("chronos-ewf-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let paths = [directory.join("image.E01"), directory.join("image.E02")];
+        std::fs::write(&paths[0], &first.data).unwrap();
+        std::fs::write(&paths[1], &second.data).unwrap();
+
+        let image = EwfImage::open(&paths).unwrap();
+        let out_of_order = EwfImage::open(&paths[1..]);
+        assert!(is_ewf_file(&paths[0]));
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(image.size(), 3 * SECTOR_SIZE as u64);
+        assert_eq!(image.read_bytes(510, 4).unwrap(), [0xAA, 0xAA, 0xBB, 0xBB]);
+        assert!(image.read_bytes(1024, 512).unwrap().iter().all(|&byte| byte == 0xCC));
+        assert!(image.read_bytes(1024, 513).is_err());
+
+        let info = image.metadata().unwrap();
+        assert_eq!(info.case_number.as_deref(), Some("42"));
+        assert_eq!(info.md5.as_deref(), Some("11".repeat(16).as_str()));
+        assert_eq!(info.sector_count, 3);
+
+        // The second segment alone has the wrong segment number
+        assert!(out_of_order.is_err());
+    }
+
+    #[test]
+    fn rejects_chunks_that_decompress_to_the_wrong_size() {
+        let mut segment = SegmentWriter::new(1);
+        segment.section("volume", &volume_section(3));
+        segment.chunks(&[
+            (true, zlib(&[0xAA; SECTOR_SIZE])),
+            (true, zlib(&[0xBB; 2 * SECTOR_SIZE])),
+            (true, zlib(&[0xCC; SECTOR_SIZE - 1])),
+        ]);
+        segment.section("done", &[]);
+
+        let directory = std::env::temp_dir().join(format!("chronos-ewf-sizes-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("image.E01");
+        std::fs::write(&path, &segment.data).unwrap();
+        let image = EwfImage::open(&[path]).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert!(image.read_bytes(0, SECTOR_SIZE).unwrap().iter().all(|&byte| byte == 0xAA));
+        assert!(image.read_bytes(SECTOR_SIZE as u64, 1).is_err());
+        assert!(image.read_bytes(2 * SECTOR_SIZE as u64, 1).is_err());
+        assert!(image.read_bytes(u64::MAX, 1).is_err());
+    }
+
+    #[test]
+    fn rejects_oversized_header_sections() {
+        let mut header = b"1\nmain\nc\n42\n".to_vec();
+        header.resize(MAX_HEADER_SIZE as usize + 1, b' ');
+        let mut info = AcquisitionInfo::default();
+        assert!(parse_header_section(&zlib(&header), "header", &mut info).is_err());
+        assert!(info.case_number.is_none());
+    }
+}
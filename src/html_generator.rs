@@ -4,12 +4,13 @@ use std::fs::File;
 use std::io::Write;
 use tracing::info;
 
-use crate::timeline::Timeline;
+use crate::ewf::AcquisitionInfo;
+use crate::timeline::{Severity, Timeline, TimelineEvent};
 
-pub fn generate_html(timeline: &Timeline) -> Result<()> {
+pub fn generate_html(timeline: &Timeline, acquisition: Option<&AcquisitionInfo>) -> Result<()> {
     info!("Generating HTML timeline...");
     
-    let html_content = create_timeline_html(timeline);
+    let html_content = create_timeline_html(timeline, acquisition);
     
     let mut file = File::create("timeline.html")
         .context("Failed to create timeline.html file")?;
@@ -21,7 +22,11 @@ pub fn generate_html(timeline: &Timeline) -> Result<()> {
     Ok(())
 }
 
-fn create_timeline_html(timeline: &Timeline) -> Markup {
+fn create_timeline_html(timeline: &Timeline, acquisition: Option<&AcquisitionInfo>) -> Markup {
+    let flagged_count = timeline.events.iter().filter(|event| event.alert.is_some()).count();
+    let deleted_count = timeline.events.iter().filter(|event| event.deleted).count();
+    let recovered_count = timeline.events.iter().filter(|event| event.recovered).count();
+
     html! {
         (DOCTYPE)
         html lang="en" {
@@ -38,6 +43,33 @@ fn create_timeline_html(timeline: &Timeline) -> Markup {
                     p class="summary" {
                         "Generated " (timeline.events.len()) " events from forensic disk image analysis."
                     }
+                    @if let Some(acquisition) = acquisition {
+                        (create_acquisition_html(acquisition))
+                    }
+                    div class="filters" {
+                        label for="severity-filter" { "Show: " }
+                        select id="severity-filter" {
+                            option value="all" { "All events" }
+                            option value="low" { "Flagged events" }
+                            option value="medium" { "Medium and high severity" }
+                            option value="high" { "High severity" }
+                        }
+                        " "
+                        span class="flagged-count" { (flagged_count) " flagged" }
+                        " "
+                        label {
+                            input type="checkbox" id="deleted-filter";
+                            " Deleted files and keys only (" (deleted_count) ")"
+                        }
+                        " "
+                        label {
+                            input type="checkbox" id="recovered-filter";
+                            " Recovered records only (" (recovered_count) ")"
+                        }
+                        " "
+                        input type="search" id="search-filter"
+                            placeholder="Search events, details, fields (Name: value)...";
+                    }
                     table id="timeline-table" class="timeline-table" {
                         thead {
                             tr {
@@ -45,15 +77,66 @@ fn create_timeline_html(timeline: &Timeline) -> Markup {
                                 th class="sortable" data-sort="event-type" { "Event Type" }
                                 th class="sortable" data-sort="description" { "Description" }
                                 th class="sortable" data-sort="source" { "Source Artifact" }
+                                th class="sortable" data-sort="volume" { "Volume" }
                             }
                         }
                         tbody {
                             @for event in &timeline.events {
-                                tr {
+                                tr class=[row_class(event)]
+                                    data-deleted=[event.deleted.then_some("true")]
+                                    data-recovered=[event.recovered.then_some("true")]
+                                    data-severity=[event.alert.as_ref().map(|alert| severity_rank(alert.severity))] {
                                     td class="timestamp" { (format_timestamp(event.timestamp)) }
-                                    td class="event-type" { (event.event_type.to_string()) }
-                                    td class="description" { (event.description) }
+                                    td class="event-type" {
+                                        (event.event_type.to_string())
+                                        @if let Some(timestamp_source) = event.timestamp_source {
+                                            " " span class="timestamp-source" { (timestamp_source.to_string()) }
+                                        }
+                                    }
+                                    td class="description" {
+                                        @if event.deleted {
+                                            span class="deleted-badge" { "Deleted" } " "
+                                        }
+                                        @if event.recovered {
+                                            span class="recovered-badge" { "Recovered" } " "
+                                        }
+                                        (event.description)
+                                        @if let Some(alert) = &event.alert {
+                                            div class="alert" {
+                                                span class={ "severity severity-" (alert.severity.to_string().to_lowercase()) } {
+                                                    (alert.severity.to_string())
+                                                }
+                                                " " (alert.explanation)
+                                            }
+                                        }
+                                        @for detail in &event.details {
+                                            details class="event-details" {
+                                                summary {
+                                                    (detail.label)
+                                                    @if detail.values.len() > 1 {
+                                                        " (" (detail.values.len()) ")"
+                                                    }
+                                                }
+                                                ul {
+                                                    @for value in &detail.values {
+                                                        li { (value) }
+                                                    }
+                                                }
+                                            }
+                                        }
+                                        @if !event.fields.is_empty() {
+                                            details class="event-details" {
+                                                summary { "Fields (" (event.fields.len()) ")" }
+                                                ul {
+                                                    @for (name, value) in &event.fields {
+                                                        li { span class="field-name" { (name) ":" } " " (value) }
+                                                    }
+                                                }
+                                            }
+                                        }
+                                    }
                                     td class="source" { (event.source_artifact) }
+                                    td class="volume" { (event.volume.as_deref().unwrap_or("")) }
                                 }
                             }
                         }
@@ -64,6 +147,62 @@ fn create_timeline_html(timeline: &Timeline) -> Markup {
     }
 }
 
+fn create_acquisition_html(acquisition: &AcquisitionInfo) -> Markup {
+    let sector_count = acquisition.sector_count.to_string();
+    let bytes_per_sector = acquisition.bytes_per_sector.to_string();
+    let fields = [
+        ("Case Number", acquisition.case_number.as_deref()),
+        ("Evidence Number", acquisition.evidence_number.as_deref()),
+        ("Description", acquisition.description.as_deref()),
+        ("Examiner", acquisition.examiner.as_deref()),
+        ("Notes", acquisition.notes.as_deref()),
+        ("Acquisition Date", acquisition.acquisition_date.as_deref()),
+        ("System Date", acquisition.system_date.as_deref()),
+        ("Acquisition Software", acquisition.acquisition_software.as_deref()),
+        ("Acquisition OS", acquisition.acquisition_os.as_deref()),
+        ("Sectors", Some(sector_count.as_str())),
+        ("Bytes per Sector", Some(bytes_per_sector.as_str())),
+        ("MD5", acquisition.md5.as_deref()),
+        ("SHA1", acquisition.sha1.as_deref()),
+    ];
+
+    html! {
+        div class="acquisition" {
+            h2 { "Acquisition Details" }
+            table class="acquisition-table" {
+                @for (label, value) in fields {
+                    @if let Some(value) = value {
+                        tr {
+                            th { (label) }
+                            td { (value) }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn row_class(event: &TimelineEvent) -> Option<String> {
+    let classes: Vec<&str> = [
+        (event.alert.is_some(), "flagged"),
+        (event.deleted, "deleted"),
+        (event.recovered, "recovered"),
+    ].into_iter().filter_map(|(set, class)| set.then_some(class)).collect();
+    
+    (!classes.is_empty()).then(|| classes.join(" "))
+}
+
+/// Numeric severity used by the report's filter: an event is shown when its
+/// rank is at least the selected threshold.
+fn severity_rank(severity: Severity) -> u8 {
+    match severity {
+        Severity::Low => 1,
+        Severity::Medium => 2,
+        Severity::High => 3,
+    }
+}
+
 fn format_timestamp(timestamp: chrono::DateTime<chrono::Utc>) -> String {
     timestamp.format("%Y-%m-%dT%H:%M:%SZ").to_string()
 }
@@ -98,6 +237,27 @@ fn get_css_styles() -> &'static str {
             margin-bottom: 30px;
         }
         
+        .acquisition h2 {
+            color: #2c3e50;
+            font-size: 18px;
+        }
+        
+        .acquisition-table {
+            border-collapse: collapse;
+            font-size: 13px;
+        }
+        
+        .acquisition-table th {
+            text-align: left;
+            color: #7f8c8d;
+            padding: 4px 16px 4px 0;
+        }
+        
+        .acquisition-table td {
+            font-family: 'Courier New', monospace;
+            padding: 4px 0;
+        }
+        
         .timeline-table {
             width: 100%;
             border-collapse: collapse;
@@ -152,17 +312,108 @@ fn get_css_styles() -> &'static str {
             color: #e74c3c;
         }
         
+        .timestamp-source {
+            font-weight: normal;
+            font-size: 11px;
+            color: #7f8c8d;
+        }
+        
         .description {
             max-width: 400px;
             word-wrap: break-word;
         }
         
-        .source {
+        .source, .volume {
+            font-family: 'Courier New', monospace;
+            font-size: 12px;
+            color: #7f8c8d;
+        }
+        
+        .filters {
+            font-size: 14px;
+        }
+        
+        .flagged-count {
+            margin-left: 8px;
+        }
+        
+        .timeline-table tr.flagged {
+            background-color: #fdf2f2;
+        }
+        
+        .timeline-table tr.deleted td {
+            color: #95a5a6;
+        }
+        
+        .deleted-badge {
+            display: inline-block;
+            padding: 1px 6px;
+            border-radius: 3px;
+            background-color: #7f8c8d;
+            color: white;
+            font-size: 11px;
+        }
+        
+        .timeline-table tr.recovered {
+            background-color: #f4f8fb;
+        }
+        
+        .recovered-badge {
+            display: inline-block;
+            padding: 1px 6px;
+            border-radius: 3px;
+            background-color: #2980b9;
+            color: white;
+            font-size: 11px;
+        }
+        
+        #search-filter {
+            margin-left: 8px;
+            padding: 4px 8px;
+            width: 280px;
+        }
+        
+        .event-details {
+            margin-top: 4px;
+            font-size: 12px;
+        }
+        
+        .event-details summary {
+            cursor: pointer;
+            color: #7f8c8d;
+        }
+        
+        .event-details ul {
+            margin: 4px 0;
+            padding-left: 20px;
             font-family: 'Courier New', monospace;
+            font-size: 11px;
+            white-space: pre-wrap;
+        }
+        
+        .field-name {
+            color: #34495e;
+            font-weight: bold;
+        }
+        
+        .alert {
+            margin-top: 4px;
             font-size: 12px;
             color: #7f8c8d;
         }
         
+        .severity {
+            display: inline-block;
+            padding: 1px 6px;
+            border-radius: 3px;
+            color: white;
+            font-weight: bold;
+        }
+        
+        .severity-high { background-color: #c0392b; }
+        .severity-medium { background-color: #e67e22; }
+        .severity-low { background-color: #95a5a6; }
+        
         .file-creation { color: #27ae60; }
         .file-modification { color: #f39c12; }
         .file-access { color: #3498db; }
@@ -225,11 +476,42 @@ fn get_javascript() -> &'static str {
                     'timestamp': 0,
                     'event-type': 1,
                     'description': 2,
-                    'source': 3
+                    'source': 3,
+                    'volume': 4
                 };
                 
                 return row.cells[cellIndex[column]].textContent.trim();
             }
+            
+            const thresholds = { 'all': 0, 'low': 1, 'medium': 2, 'high': 3 };
+            const severityFilter = document.getElementById('severity-filter');
+            const deletedFilter = document.getElementById('deleted-filter');
+            const recoveredFilter = document.getElementById('recovered-filter');
+            const searchFilter = document.getElementById('search-filter');
+            
+            function applyFilters() {
+                const threshold = thresholds[severityFilter.value];
+                const query = searchFilter.value.trim().toLowerCase();
+                table.querySelectorAll('tbody tr').forEach(row => {
+                    const rank = parseInt(row.dataset.severity || '0', 10);
+                    const matches = !query || row.textContent.toLowerCase().includes(query);
+                    const visible = rank >= threshold && (!deletedFilter.checked || row.dataset.deleted) &&
+                        (!recoveredFilter.checked || row.dataset.recovered) && matches;
+                    row.style.display = visible ? '' : 'none';
+                    
+                    // Expand the detail lists that contain the search term
+                    if (query) {
+                        row.querySelectorAll('details').forEach(details => {
+                            details.open = details.textContent.toLowerCase().includes(query);
+                        });
+                    }
+                });
+            }
+            
+            severityFilter.addEventListener('change', applyFilters);
+            deletedFilter.addEventListener('change', applyFilters);
+            recoveredFilter.addEventListener('change', applyFilters);
+            searchFilter.addEventListener('input', applyFilters);
         });
     "#
 } 
\ No newline at end of file
@@ -0,0 +1,275 @@
+use anyhow::{Context, Result};
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::ewf::AcquisitionInfo;
+
+pub const DEFAULT_SECTOR_SIZE: u32 = 512;
+
+/// Random-access view of a disk image. Every container format (raw, EWF,
+/// and later virtual disk formats) exposes its logical media through this
+/// trait, and every artifact parser reads through it.
+pub trait ImageSource: Send + Sync {
+    /// Fills `buf` with the bytes at `offset`. Reading past the end of the
+    /// media is an error.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<()>;
+
+    /// Logical media size in bytes.
+    fn size(&self) -> u64;
+
+    fn sector_size(&self) -> u32 {
+        DEFAULT_SECTOR_SIZE
+    }
+
+    /// Acquisition details stored in the container, if the format has any.
+    fn metadata(&self) -> Option<&AcquisitionInfo> {
+        None
+    }
+
+    fn read_bytes(&self, offset: u64, length: usize) -> Result<Vec<u8>> {
+        let mut buffer = vec![0u8; length];
+        self.read_at(offset, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+fn check_bounds(offset: u64, length: usize, size: u64) -> Result<()> {
+    match offset.checked_add(length as u64) {
+        Some(end) if end <= size => Ok(()),
+        _ => anyhow::bail!("Attempted to read beyond disk image bounds"),
+    }
+}
+
+/// Raw image segments (.dd, .001, .002, ...) memory mapped back to back.
+pub struct MmapSource {
+    segments: Vec<Mmap>,
+    starts: Vec<u64>,
+    size: u64,
+}
+
+impl MmapSource {
+    pub fn open(paths: &[PathBuf]) -> Result<Self> {
+        let mut segments = Vec::with_capacity(paths.len());
+        let mut starts = Vec::with_capacity(paths.len());
+        let mut size = 0;
+
+        for path in paths {
+            let file = File::open(path)
+                .with_context(|| format!("Failed to open disk image file {}", path.display()))?;
+
+            let data = unsafe { Mmap::map(&file) }
+                .with_context(|| format!("Failed to memory map disk image {}", path.display()))?;
+
+            starts.push(size);
+            size += data.len() as u64;
+            segments.push(data);
+        }
+
+        Ok(MmapSource { segments, starts, size })
+    }
+}
+
+impl ImageSource for MmapSource {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        check_bounds(offset, buf.len(), self.size)?;
+
+        // Reads spanning a segment boundary are stitched from each segment
+        let mut written = 0;
+        while written < buf.len() {
+            let position = offset + written as u64;
+            let index = segment_for(&self.starts, position);
+            let segment = &self.segments[index];
+            let start = (position - self.starts[index]) as usize;
+            let wanted = (buf.len() - written).min(segment.len() - start);
+            buf[written..written + wanted].copy_from_slice(&segment[start..start + wanted]);
+            written += wanted;
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// Raw image segments read through buffered file I/O, for inputs that
+/// cannot be memory mapped such as block devices.
+pub struct FileSource {
+    segments: Vec<Mutex<BufReader<File>>>,
+    starts: Vec<u64>,
+    lengths: Vec<u64>,
+    size: u64,
+}
+
+impl FileSource {
+    pub fn open(paths: &[PathBuf]) -> Result<Self> {
+        let mut segments = Vec::with_capacity(paths.len());
+        let mut starts = Vec::with_capacity(paths.len());
+        let mut lengths = Vec::with_capacity(paths.len());
+        let mut size = 0;
+
+        for path in paths {
+            let mut file = File::open(path)
+                .with_context(|| format!("Failed to open disk image file {}", path.display()))?;
+
+            // Block devices report a zero metadata length, seeking works
+            let length = file.seek(SeekFrom::End(0))
+                .with_context(|| format!("Failed to determine size of {}", path.display()))?;
+
+            starts.push(size);
+            lengths.push(length);
+            size += length;
+            segments.push(Mutex::new(BufReader::new(file)));
+        }
+
+        Ok(FileSource { segments, starts, lengths, size })
+    }
+}
+
+impl ImageSource for FileSource {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        check_bounds(offset, buf.len(), self.size)?;
+
+        let mut written = 0;
+        while written < buf.len() {
+            let position = offset + written as u64;
+            let index = segment_for(&self.starts, position);
+            let start = position - self.starts[index];
+            let wanted = (buf.len() - written).min((self.lengths[index] - start) as usize);
+
+            // Relative seeks keep the read buffer for sequential access
+            let mut reader = self.segments[index].lock().unwrap();
+            let current = reader.stream_position()?;
+            reader.seek_relative(start as i64 - current as i64)?;
+            reader.read_exact(&mut buf[written..written + wanted])
+                .context("Failed to read from disk image")?;
+            written += wanted;
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// A window onto part of another source, such as a single partition.
+pub struct OffsetSource {
+    inner: Arc<dyn ImageSource>,
+    offset: u64,
+    size: u64,
+}
+
+impl OffsetSource {
+    pub fn new(inner: Arc<dyn ImageSource>, offset: u64, size: u64) -> Self {
+        // Clamp partitions that claim to extend past the end of the image
+        let size = size.min(inner.size().saturating_sub(offset));
+        OffsetSource { inner, offset, size }
+    }
+}
+
+impl ImageSource for OffsetSource {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        check_bounds(offset, buf.len(), self.size)?;
+        self.inner.read_at(self.offset + offset, buf)
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn sector_size(&self) -> u32 {
+        self.inner.sector_size()
+    }
+
+    fn metadata(&self) -> Option<&AcquisitionInfo> {
+        self.inner.metadata()
+    }
+}
+
+/// Image held entirely in memory, for synthetic fixtures in tests.
+#[cfg(test)]
+pub struct MemorySource {
+    data: Vec<u8>,
+}
+
+#[cfg(test)]
+impl MemorySource {
+    pub fn new(data: Vec<u8>) -> Self {
+        MemorySource { data }
+    }
+}
+
+#[cfg(test)]
+impl ImageSource for MemorySource {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        check_bounds(offset, buf.len(), self.data.len() as u64)?;
+        let start = offset as usize;
+        buf.copy_from_slice(&self.data[start..start + buf.len()]);
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.data.len() as u64
+    }
+}
+
+/// Index of the segment containing `offset`. Empty segments never match, so
+/// a read never starts at the end of one.
+fn segment_for(starts: &[u64], offset: u64) -> usize {
+    starts
+        .windows(2)
+        .position(|pair| pair[0] <= offset && offset < pair[1])
+        .unwrap_or(starts.len() - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered(length: usize) -> Vec<u8> {
+        (0..length).map(|i| i as u8).collect()
+    }
+
+    #[test]
+    fn memory_source_reads_within_bounds() {
+        let source = MemorySource::new(numbered(1024));
+        assert_eq!(source.size(), 1024);
+        assert_eq!(source.read_bytes(1020, 4).unwrap(), vec![252, 253, 254, 255]);
+        assert!(source.read_bytes(1021, 4).is_err());
+        assert!(source.read_bytes(u64::MAX, 1).is_err());
+    }
+
+    #[test]
+    fn offset_source_is_a_window_onto_its_inner_source() {
+        let inner: Arc<dyn ImageSource> = Arc::new(MemorySource::new(numbered(4096)));
+        let partition = OffsetSource::new(inner, 512, 1024);
+        assert_eq!(partition.size(), 1024);
+        assert_eq!(partition.read_bytes(0, 2).unwrap(), vec![0, 1]);
+        assert_eq!(partition.read_bytes(1022, 2).unwrap(), vec![254, 255]);
+        assert!(partition.read_bytes(1023, 2).is_err());
+    }
+
+    #[test]
+    fn offset_source_is_clamped_to_the_image() {
+        let inner: Arc<dyn ImageSource> = Arc::new(MemorySource::new(numbered(4096)));
+        let partition = OffsetSource::new(Arc::clone(&inner), 3072, 1 << 20);
+        assert_eq!(partition.size(), 1024);
+        assert_eq!(OffsetSource::new(inner, 8192, 512).size(), 0);
+    }
+
+    #[test]
+    fn segment_for_finds_the_segment_holding_an_offset() {
+        let starts = [0, 100, 100, 250];
+        assert_eq!(segment_for(&starts, 0), 0);
+        assert_eq!(segment_for(&starts, 99), 0);
+        // The empty segment starting at 100 is skipped
+        assert_eq!(segment_for(&starts, 100), 2);
+        assert_eq!(segment_for(&starts, 249), 2);
+        assert_eq!(segment_for(&starts, 250), 3);
+        assert_eq!(segment_for(&starts, 10_000), 3);
+    }
+}
@@ -1,26 +1,91 @@
 use clap::Parser;
 use anyhow::{Context, Result};
-use tracing::{info, Level};
-use tracing_subscriber;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn, Level};
 
+mod bam;
 mod disk_image;
+mod ewf;
+mod image_source;
 mod mft_parser;
+mod ntfs_volume;
 mod event_log_parser;
+mod evtx_carver;
+mod partition;
+mod path_resolver;
 mod prefetch_parser;
+mod registry_parser;
+mod shell_items;
+mod shellbags;
 mod timeline;
+mod user_activity;
+mod util;
+mod vfs;
+mod xpress;
 mod html_generator;
 
 use disk_image::DiskImage;
+use event_log_parser::RecordKey;
+use image_source::ImageSource;
+use partition::FileSystem;
 use timeline::Timeline;
+use vfs::Vfs;
 
 #[derive(Parser, Debug)]
 #[command(name = "chronos")]
 #[command(about = "Forensic timeline generator for Windows 11 disk images")]
 #[command(version)]
 struct Args {
-    /// Path to the forensic disk image file (.E01 or .dd)
+    /// Path to the forensic disk image file (.E01, .dd, .raw, .img or the
+    /// first segment of a split image such as .001); sibling segments
+    /// (.E02/.002 ...) are picked up automatically
     #[arg(required = true)]
     image_path: String,
+
+    /// Copy the content of deleted files that has not been overwritten into
+    /// this folder (one subfolder per volume)
+    #[arg(long, value_name = "DIR")]
+    extract_deleted: Option<PathBuf>,
+
+    /// Add every event log record to the timeline with all of its fields,
+    /// including event IDs no mapper recognises
+    #[arg(long)]
+    evtx_passthrough: bool,
+
+    /// Scan the whole image, including unallocated space and slack, for
+    /// event log chunks and records that are no longer in a live log
+    #[arg(long)]
+    carve_evtx: bool,
+}
+
+fn process_volume(volume: &dyn ImageSource, timeline: &mut Timeline, extract_dir: Option<&Path>,
+                  evtx_passthrough: bool, live_records: &mut HashSet<RecordKey>) -> Result<()> {
+    // Every parser finds its inputs through the volume's filesystem
+    let vfs = Vfs::open(volume)
+        .context("Failed to open NTFS filesystem")?;
+    
+    // Parse MFT
+    info!("Parsing Master File Table (MFT)...");
+    mft_parser::parse_mft(vfs.volume(), timeline, extract_dir)
+        .context("Failed to parse MFT")?;
+    
+    // Parse Windows Event Logs
+    info!("Parsing Windows Event Logs...");
+    event_log_parser::parse_event_logs(&vfs, timeline, evtx_passthrough, live_records)
+        .context("Failed to parse event logs")?;
+    
+    // Parse Prefetch files
+    info!("Parsing Prefetch files...");
+    prefetch_parser::parse_prefetch_files(&vfs, timeline)
+        .context("Failed to parse prefetch files")?;
+    
+    // Parse registry hives
+    info!("Parsing registry hives...");
+    registry_parser::parse_registry_hives(&vfs, timeline)
+        .context("Failed to parse registry hives")?;
+    
+    Ok(())
 }
 
 #[tokio::main]
@@ -35,7 +100,7 @@ async fn main() -> Result<()> {
     let args = Args::parse();
     
     // Validate input file
-    let image_path = std::path::Path::new(&args.image_path);
+    let image_path = Path::new(&args.image_path);
     if !image_path.exists() {
         anyhow::bail!("Image file not found: {}", args.image_path);
     }
@@ -44,8 +109,8 @@ async fn main() -> Result<()> {
         .and_then(|ext| ext.to_str())
         .unwrap_or("");
     
-    if !matches!(extension.to_lowercase().as_str(), "e01" | "dd") {
-        anyhow::bail!("Unsupported image format. Supported formats: .E01, .dd");
+    if !matches!(extension.to_lowercase().as_str(), "e01" | "dd" | "raw" | "img" | "001") {
+        anyhow::bail!("Unsupported image format. Supported formats: .E01, .dd, .raw, .img, .001");
     }
     
     info!("Processing disk image: {}", args.image_path);
@@ -54,30 +119,64 @@ async fn main() -> Result<()> {
     let disk_image = DiskImage::new(&args.image_path)
         .context("Failed to load disk image")?;
     
+    info!("Loaded {} image {} ({} segment(s), {} bytes, {}-byte sectors)",
+          if disk_image.is_e01_format() { "EnCase" } else { "raw" },
+          disk_image.path, disk_image.segment_count, disk_image.size(),
+          disk_image.sector_size());
+    
+    if let Some(acquisition) = disk_image.metadata() {
+        info!("EnCase image - case: {}, examiner: {}, MD5: {}",
+              acquisition.case_number.as_deref().unwrap_or("-"),
+              acquisition.examiner.as_deref().unwrap_or("-"),
+              acquisition.md5.as_deref().unwrap_or("-"));
+    }
+    
     // Create timeline
     let mut timeline = Timeline::new();
     
-    // Parse MFT
-    info!("Parsing Master File Table (MFT)...");
-    mft_parser::parse_mft(&disk_image, &mut timeline)
-        .context("Failed to parse MFT")?;
+    // Locate the volumes and run the artifact parsers on each NTFS volume
+    let volumes = partition::discover_volumes(&disk_image)
+        .context("Failed to read partition table")?;
     
-    // Parse Windows Event Logs
-    info!("Parsing Windows Event Logs...");
-    event_log_parser::parse_event_logs(&disk_image, &mut timeline)
-        .context("Failed to parse event logs")?;
+    let mut live_records = HashSet::new();
+    for volume in &volumes {
+        if volume.filesystem != FileSystem::Ntfs {
+            info!("Skipping {} ({}): not an NTFS volume", volume.label(), volume.role);
+            continue;
+        }
+        
+        timeline.set_volume(Some(volume.label()));
+        let source = disk_image.volume_source(volume);
+        let extract_dir = args.extract_deleted.as_ref()
+            .map(|dir| dir.join(format!("volume{}", volume.index)));
+        if let Err(e) = process_volume(&source, &mut timeline, extract_dir.as_deref(),
+                                    args.evtx_passthrough, &mut live_records) {
+            warn!("Failed to process {}: {:#}", volume.label(), e);
+        }
+    }
+    timeline.set_volume(None);
     
-    // Parse Prefetch files
-    info!("Parsing Prefetch files...");
-    prefetch_parser::parse_prefetch_files(&disk_image, &mut timeline)
-        .context("Failed to parse prefetch files")?;
+    // Carving reads every sector, so it only runs when asked for
+    if args.carve_evtx {
+        info!("Carving event log records...");
+        if let Err(e) = evtx_carver::carve_event_records(&disk_image, &live_records,
+                                                         args.evtx_passthrough, &mut timeline) {
+            warn!("Failed to carve event log records: {:#}", e);
+        }
+    }
     
     // Sort timeline chronologically
     timeline.sort();
     
+    if timeline.is_empty() {
+        warn!("No events were recovered from the disk image");
+    } else {
+        info!("Timeline contains {} events", timeline.len());
+    }
+    
     // Generate HTML output
     info!("Generating timeline.html...");
-    html_generator::generate_html(&timeline)
+    html_generator::generate_html(&timeline, disk_image.metadata())
         .context("Failed to generate HTML output")?;
     
     info!("Timeline generation completed successfully!");
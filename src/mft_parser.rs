@@ -1,133 +1,337 @@
-use anyhow::Result;
-use byteorder::{LittleEndian, ReadBytesExt};
-use chrono::{DateTime, Utc, TimeZone};
-use std::io::{Cursor, Read};
-use tracing::info;
+use anyhow::{Context, Result};
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::Path;
+use tracing::{info, warn};
 
-use crate::disk_image::DiskImage;
-use crate::timeline::{Timeline, EventType};
+use crate::path_resolver::{PathResolver, ROOT_RECORD};
+use crate::ntfs_volume::{apply_fixups, decode_data_runs, DataRun, FixupStatus, NtfsVolume};
+use crate::timeline::{Alert, Severity, Timeline, EventType, TimestampSource};
+use crate::util::windows_time_to_utc;
 
 const MFT_SIGNATURE: &[u8; 4] = b"FILE";
-const MFT_ENTRY_SIZE: usize = 1024;
+const MFT_BAD_SIGNATURE: &[u8; 4] = b"BAAD";
+const ATTRIBUTE_END: u32 = 0xFFFF_FFFF;
+const ATTRIBUTE_STANDARD_INFORMATION: u32 = 0x10;
+const ATTRIBUTE_FILE_NAME: u32 = 0x30;
+const ATTRIBUTE_DATA: u32 = 0x80;
+const MFT_RECORD_IN_USE: u16 = 0x0001;
+const MFT_RECORD_IS_DIRECTORY: u16 = 0x0002;
+/// MFT record of $Bitmap, the cluster allocation bitmap.
+const BITMAP_RECORD: u64 = 6;
+const ATTRIBUTE_FLAG_COMPRESSED: u16 = 0x0001;
+const ATTRIBUTE_FLAG_ENCRYPTED: u16 = 0x4000;
+/// $STANDARD_INFORMATION file attribute flags worth showing.
+const FILE_ATTRIBUTE_NAMES: &[(u32, &str)] = &[
+    (0x0001, "Read-only"),
+    (0x0002, "Hidden"),
+    (0x0004, "System"),
+    (0x0020, "Archive"),
+    (0x0100, "Temporary"),
+    (0x0200, "Sparse"),
+    (0x0400, "Reparse point"),
+    (0x0800, "Compressed"),
+    (0x1000, "Offline"),
+    (0x2000, "Not content indexed"),
+    (0x4000, "Encrypted"),
+];
+const TORN_RECORD_NOTE: &str =
+    "The update sequence check failed, so parts of this record may come from an older write";
 
 #[derive(Debug)]
-struct MftEntry {
-    signature: [u8; 4],
-    sequence_number: u16,
-    link_count: u16,
-    attribute_offset: u16,
-    flags: u16,
-    entry_size: u32,
-    entry_allocated: u32,
-    file_reference: u64,
-    base_file_record: u64,
-    next_attribute_id: u16,
-    attributes: Vec<MftAttribute>,
+pub(crate) struct MftEntry {
+    pub sequence_number: u16,
+    pub flags: u16,
+    pub base_file_record: u64,
+    /// The update sequence check failed; contents may mix old and new data.
+    pub torn: bool,
+    pub attributes: Vec<MftAttribute>,
 }
 
 #[derive(Debug)]
-struct MftAttribute {
-    attribute_type: u32,
-    attribute_length: u32,
-    non_resident: bool,
-    name_length: u8,
-    name_offset: u16,
-    flags: u16,
-    attribute_id: u16,
-    content: Vec<u8>,
+pub(crate) struct MftAttribute {
+    pub attribute_type: u32,
+    pub attribute_length: u32,
+    pub non_resident: bool,
+    pub name_length: u8,
+    pub flags: u16,
+    pub attribute_id: u16,
+    /// Attribute name, e.g. the stream name of an alternate data stream.
+    pub name: String,
+    /// Value of a resident attribute; empty for non-resident attributes.
+    pub content: Vec<u8>,
+    /// Cluster runs of a non-resident attribute.
+    pub data_runs: Vec<DataRun>,
+    /// First and last VCN covered by `data_runs`. A non-resident attribute
+    /// split across extension records has one extent per record.
+    pub starting_vcn: u64,
+    pub last_vcn: u64,
+    /// Log2 of the compression unit in clusters; 0 when not compressed.
+    pub compression_unit: u16,
+    /// Logical size of a non-resident attribute's value.
+    pub data_size: u64,
+    /// Bytes of a non-resident value actually written; the rest reads as zeros.
+    pub initialized_size: u64,
+}
+
+impl MftAttribute {
+    pub fn is_compressed(&self) -> bool {
+        self.flags & ATTRIBUTE_FLAG_COMPRESSED != 0
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.flags & ATTRIBUTE_FLAG_ENCRYPTED != 0
+    }
 }
 
 #[derive(Debug)]
-struct FileNameAttribute {
-    parent_directory: u64,
+struct StandardInformation {
     creation_time: u64,
-    last_access_time: u64,
     last_write_time: u64,
     mft_change_time: u64,
-    file_size: u64,
-    allocated_size: u64,
-    file_flags: u32,
-    filename_length: u8,
-    filename: String,
+    last_access_time: u64,
+    file_attributes: u32,
+    // Only present in the NTFS 3.0+ (72 byte) form of the attribute
+    owner_id: Option<u32>,
+    security_id: Option<u32>,
+    usn: Option<u64>,
+}
+
+#[derive(Debug)]
+pub(crate) struct FileNameAttribute {
+    pub parent_directory: u64,
+    pub creation_time: u64,
+    pub last_write_time: u64,
+    pub mft_change_time: u64,
+    pub last_access_time: u64,
+    pub file_flags: u32,
+    pub namespace: FileNameNamespace,
+    pub filename: String,
 }
 
-pub fn parse_mft(disk_image: &DiskImage, timeline: &mut Timeline) -> Result<()> {
+/// The naming convention a $FILE_NAME attribute was created under. A long
+/// name that is not 8.3 compatible gets a separate DOS name alongside its
+/// Win32 name; names valid in both are stored once as `Win32AndDos`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FileNameNamespace {
+    Posix,
+    Win32,
+    Dos,
+    Win32AndDos,
+}
+
+impl FileNameNamespace {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => FileNameNamespace::Win32,
+            2 => FileNameNamespace::Dos,
+            3 => FileNameNamespace::Win32AndDos,
+            _ => FileNameNamespace::Posix,
+        }
+    }
+}
+
+/// One name of a file: its preferred (Win32 or POSIX) $FILE_NAME and the DOS
+/// 8.3 alias stored in the same directory, if any. A file with hard links has
+/// one of these per directory entry.
+struct FileLink {
+    file_name: FileNameAttribute,
+    short_name: Option<String>,
+}
+
+/// The parts of an MFT record the timeline is built from.
+struct FileRecord {
+    record_number: u64,
+    sequence_number: u16,
+    /// Cleared when the file or directory has been deleted.
+    in_use: bool,
+    is_directory: bool,
+    links: Vec<FileLink>,
+    standard_info: Option<StandardInformation>,
+    torn: bool,
+}
+
+impl FileRecord {
+    /// The name used for directory paths and timestamp analysis.
+    fn primary_name(&self) -> &FileNameAttribute {
+        &self.links[0].file_name
+    }
+}
+
+/// Parses the $MFT of an NTFS volume into file events. When `extract_dir` is
+/// set, the content of deleted files that has not been overwritten is copied
+/// into it.
+pub fn parse_mft(volume: &NtfsVolume, timeline: &mut Timeline, extract_dir: Option<&Path>) -> Result<()> {
     info!("Starting MFT parsing...");
     
-    // For MVP, we'll implement a simplified MFT parser
-    // In a production version, this would need to handle NTFS structures more comprehensively
+    // Walk the $MFT record by record
+    let record_count = volume.record_count();
+    let mut records = Vec::new();
+    let mut unreadable = 0;
+    let mut torn_records = 0;
+    let mut unparseable = 0;
+    let mut extracted = 0;
     
-    // Look for MFT entries in the disk image
-    let mut offset = 0;
-    let mut events_found = 0;
+    let cluster_bitmap = match extract_dir {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+            match read_cluster_bitmap(volume) {
+                Ok(bitmap) => Some(bitmap),
+                Err(e) => {
+                    warn!("Failed to read $Bitmap, only extracting deleted content stored in MFT records: {:#}", e);
+                    None
+                }
+            }
+        }
+        None => None,
+    };
     
-    while offset + MFT_ENTRY_SIZE <= disk_image.size() {
-        if let Ok(entry) = parse_mft_entry(disk_image, offset) {
-            if let Some(file_info) = extract_file_info(&entry) {
-                add_file_events_to_timeline(timeline, &file_info);
-                events_found += 1;
+    for index in 0..record_count {
+        let record = match volume.read_mft_record(index) {
+            Ok(record) => record,
+            Err(_) => {
+                unreadable += 1;
+                continue;
+            }
+        };
+        
+        let mut entry = match parse_mft_entry(&record) {
+            Ok(entry) => entry,
+            Err(_) => {
+                // Unused records are zeroed; only count those that claim to be records
+                if record.starts_with(MFT_SIGNATURE) || record.starts_with(MFT_BAD_SIGNATURE) {
+                    unparseable += 1;
+                }
+                continue;
             }
+        };
+        
+        // Extension records are merged into their base record
+        if entry.base_file_record != 0 {
+            continue;
         }
+        volume.resolve_attribute_list(&mut entry, index);
+        if entry.torn {
+            torn_records += 1;
+        }
+        let links = select_file_links(extract_file_names(&entry));
+        let in_use = entry.flags & MFT_RECORD_IN_USE != 0;
         
-        offset += MFT_ENTRY_SIZE;
+        if let (Some(dir), false, Some(link)) = (extract_dir, in_use, links.first()) {
+            match extract_deleted_content(volume, &entry, index, &link.file_name.filename,
+                                          cluster_bitmap.as_deref(), dir) {
+                Ok(true) => extracted += 1,
+                Ok(false) => {}
+                Err(e) => warn!("Failed to extract deleted MFT record {}: {:#}", index, e),
+            }
+        }
         
-        // Limit processing for MVP to avoid excessive processing time
-        if events_found > 1000 {
-            info!("MFT parsing limited to 1000 entries for MVP");
-            break;
+        if !links.is_empty() {
+            records.push(FileRecord {
+                record_number: index,
+                sequence_number: entry.sequence_number,
+                in_use,
+                is_directory: entry.flags & MFT_RECORD_IS_DIRECTORY != 0,
+                standard_info: extract_standard_information(&entry),
+                links,
+                torn: entry.torn,
+            });
+        }
+    }
+    
+    let alerts = detect_timestomping(&records);
+    if !alerts.is_empty() {
+        warn!("{} MFT records show signs of timestamp manipulation", alerts.len());
+    }
+    
+    // Directory names are known only once the whole $MFT has been read
+    let mut resolver = PathResolver::new();
+    for record in records.iter().filter(|record| record.is_directory) {
+        let file_name = record.primary_name();
+        resolver.add_entry(record.record_number, record.sequence_number, record.in_use,
+                           file_name.parent_directory, &file_name.filename);
+    }
+    
+    for record in &records {
+        // Hard links are reported separately, each under its own path
+        for link in &record.links {
+            let path = if record.record_number == ROOT_RECORD {
+                "\\".to_string()
+            } else {
+                resolver.resolve(link.file_name.parent_directory, &link.file_name.filename)
+            };
+            add_file_events_to_timeline(timeline, record, link, &path, alerts.get(&record.record_number));
         }
     }
     
-    info!("MFT parsing completed. Found {} file events", events_found);
+    let deleted = records.iter().filter(|record| !record.in_use).count();
+    let directories = records.iter().filter(|record| record.is_directory).count();
+    info!("{} allocated and {} deleted records ({} directories)",
+          records.len() - deleted, deleted, directories);
+    if let Some(dir) = extract_dir {
+        info!("Extracted content of {} deleted files to {}", extracted, dir.display());
+    }
+    
+    if torn_records > 0 || unparseable > 0 {
+        warn!("{} MFT records failed the update sequence check and are flagged as torn, {} could not be parsed",
+              torn_records, unparseable);
+    }
+    
+    if unreadable > 0 {
+        warn!("{} of {} MFT records could not be read", unreadable, record_count);
+    }
+    
+    info!("MFT parsing completed. Scanned {} records, found {} file events", record_count, records.len());
     Ok(())
 }
 
-fn parse_mft_entry(disk_image: &DiskImage, offset: usize) -> Result<MftEntry> {
-    let data = disk_image.get_slice(offset, MFT_ENTRY_SIZE)?;
-    let mut cursor = Cursor::new(data);
-    
-    let mut signature = [0u8; 4];
-    cursor.read_exact(&mut signature)?;
-    
-    if signature != *MFT_SIGNATURE {
+pub(crate) fn parse_mft_entry(raw: &[u8]) -> Result<MftEntry> {
+    if raw.len() < 48 || (raw[..4] != *MFT_SIGNATURE && raw[..4] != *MFT_BAD_SIGNATURE) {
         anyhow::bail!("Invalid MFT entry signature");
     }
     
+    // Restore the sector tails hidden behind the update sequence array. A
+    // record whose array is itself damaged is parsed as-is and flagged torn
+    let mut data = raw.to_vec();
+    let torn = apply_fixups(&mut data).map_or(true, |status| status == FixupStatus::Torn);
+    let data = &data[..];
+    let mut cursor = Cursor::new(data);
+    
+    cursor.set_position(16);
     let sequence_number = cursor.read_u16::<LittleEndian>()?;
-    let link_count = cursor.read_u16::<LittleEndian>()?;
+    cursor.set_position(20);
     let attribute_offset = cursor.read_u16::<LittleEndian>()?;
     let flags = cursor.read_u16::<LittleEndian>()?;
     let entry_size = cursor.read_u32::<LittleEndian>()?;
-    let entry_allocated = cursor.read_u32::<LittleEndian>()?;
-    let file_reference = cursor.read_u64::<LittleEndian>()?;
+    cursor.set_position(32);
     let base_file_record = cursor.read_u64::<LittleEndian>()?;
-    let next_attribute_id = cursor.read_u16::<LittleEndian>()?;
     
-    // Parse attributes (simplified for MVP)
+    // Walk the attribute list up to the end marker
     let mut attributes = Vec::new();
     let mut attr_offset = attribute_offset as usize;
+    let attributes_end = (entry_size as usize).min(data.len());
     
-    while attr_offset < MFT_ENTRY_SIZE - 4 {
-        if let Ok(attr) = parse_attribute(&data[attr_offset..]) {
-            let attr_length = attr.attribute_length as usize;
-            attributes.push(attr);
-            attr_offset += attr_length;
-        } else {
+    while attr_offset + 4 <= attributes_end {
+        if LittleEndian::read_u32(&data[attr_offset..attr_offset + 4]) == ATTRIBUTE_END {
             break;
         }
+        match parse_attribute(&data[attr_offset..attributes_end]) {
+            Ok(attr) if attr.attribute_length > 0 => {
+                let attr_length = attr.attribute_length as usize;
+                attributes.push(attr);
+                attr_offset += attr_length;
+            }
+            _ => break,
+        }
     }
     
     Ok(MftEntry {
-        signature,
         sequence_number,
-        link_count,
-        attribute_offset,
         flags,
-        entry_size,
-        entry_allocated,
-        file_reference,
         base_file_record,
-        next_attribute_id,
+        torn,
         attributes,
     })
 }
@@ -146,45 +350,178 @@ fn parse_attribute(data: &[u8]) -> Result<MftAttribute> {
     let flags = cursor.read_u16::<LittleEndian>()?;
     let attribute_id = cursor.read_u16::<LittleEndian>()?;
     
-    let content = if non_resident {
-        // For MVP, skip non-resident attributes
-        Vec::new()
+    if attribute_length as usize > data.len() {
+        anyhow::bail!("Attribute extends beyond end of record");
+    }
+    let data = &data[..attribute_length as usize];
+    
+    let name_end = name_offset as usize + name_length as usize * 2;
+    let name = if name_length > 0 && name_end <= data.len() {
+        let units: Vec<u16> = data[name_offset as usize..name_end]
+            .chunks_exact(2)
+            .map(LittleEndian::read_u16)
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        String::new()
+    };
+    
+    let mut content = Vec::new();
+    let mut data_runs = Vec::new();
+    let mut starting_vcn = 0;
+    let mut last_vcn = 0;
+    let mut compression_unit = 0;
+    let mut data_size = 0;
+    let mut initialized_size = 0;
+    
+    if non_resident {
+        if data.len() < 64 {
+            anyhow::bail!("Non-resident attribute header too short");
+        }
+        starting_vcn = LittleEndian::read_u64(&data[16..24]);
+        last_vcn = LittleEndian::read_u64(&data[24..32]);
+        let runs_offset = LittleEndian::read_u16(&data[32..34]) as usize;
+        compression_unit = LittleEndian::read_u16(&data[34..36]);
+        data_size = LittleEndian::read_u64(&data[48..56]);
+        initialized_size = LittleEndian::read_u64(&data[56..64]);
+        if runs_offset < data.len() {
+            data_runs = decode_data_runs(&data[runs_offset..])?;
+        }
     } else {
+        let content_size = cursor.read_u32::<LittleEndian>()? as usize;
         let content_offset = cursor.read_u16::<LittleEndian>()? as usize;
-        let content_size = cursor.read_u16::<LittleEndian>()? as usize;
         
         if content_offset + content_size <= data.len() {
-            data[content_offset..content_offset + content_size].to_vec()
-        } else {
-            Vec::new()
+            content = data[content_offset..content_offset + content_size].to_vec();
         }
-    };
+    }
     
     Ok(MftAttribute {
         attribute_type,
         attribute_length,
         non_resident,
         name_length,
-        name_offset,
         flags,
         attribute_id,
+        name,
         content,
+        data_runs,
+        starting_vcn,
+        last_vcn,
+        compression_unit,
+        data_size,
+        initialized_size,
     })
 }
 
-fn extract_file_info(entry: &MftEntry) -> Option<FileNameAttribute> {
-    // Look for $FILE_NAME attribute (0x30)
-    for attr in &entry.attributes {
-        if attr.attribute_type == 0x30 && !attr.content.is_empty() {
-            if let Ok(file_info) = parse_filename_attribute(&attr.content) {
-                return Some(file_info);
-            }
+fn extract_standard_information(entry: &MftEntry) -> Option<StandardInformation> {
+    entry.attributes.iter()
+        .find(|attr| attr.attribute_type == ATTRIBUTE_STANDARD_INFORMATION && !attr.content.is_empty())
+        .and_then(|attr| parse_standard_information(&attr.content).ok())
+}
+
+impl StandardInformation {
+    /// The non-timestamp fields shown with the $SI events: the DOS file
+    /// attributes and, from NTFS 3.0, the owner and security IDs and the USN.
+    fn details(&self) -> Vec<(&'static str, String)> {
+        let mut details = Vec::new();
+        let attributes: Vec<&str> = FILE_ATTRIBUTE_NAMES.iter()
+            .filter(|(flag, _)| self.file_attributes & flag != 0)
+            .map(|(_, name)| *name)
+            .collect();
+        if !attributes.is_empty() {
+            details.push(("Attributes", attributes.join(", ")));
+        }
+        if let Some(owner_id) = self.owner_id {
+            details.push(("Owner ID", owner_id.to_string()));
+        }
+        if let Some(security_id) = self.security_id {
+            details.push(("Security ID", security_id.to_string()));
+        }
+        if let Some(usn) = self.usn {
+            details.push(("USN", usn.to_string()));
         }
+        details
     }
-    None
 }
 
-fn parse_filename_attribute(data: &[u8]) -> Result<FileNameAttribute> {
+fn parse_standard_information(data: &[u8]) -> Result<StandardInformation> {
+    if data.len() < 48 {
+        anyhow::bail!("Standard information attribute data too short");
+    }
+    
+    let mut cursor = Cursor::new(data);
+    let creation_time = cursor.read_u64::<LittleEndian>()?;
+    let last_write_time = cursor.read_u64::<LittleEndian>()?;
+    let mft_change_time = cursor.read_u64::<LittleEndian>()?;
+    let last_access_time = cursor.read_u64::<LittleEndian>()?;
+    let file_attributes = cursor.read_u32::<LittleEndian>()?;
+    
+    let (owner_id, security_id, usn) = if data.len() >= 72 {
+        cursor.set_position(48);
+        let owner_id = cursor.read_u32::<LittleEndian>()?;
+        let security_id = cursor.read_u32::<LittleEndian>()?;
+        cursor.set_position(64);
+        let usn = cursor.read_u64::<LittleEndian>()?;
+        (Some(owner_id), Some(security_id), Some(usn))
+    } else {
+        (None, None, None)
+    };
+    
+    Ok(StandardInformation {
+        creation_time,
+        last_write_time,
+        mft_change_time,
+        last_access_time,
+        file_attributes,
+        owner_id,
+        security_id,
+        usn,
+    })
+}
+
+fn extract_file_names(entry: &MftEntry) -> Vec<FileNameAttribute> {
+    entry.attributes.iter()
+        .filter(|attr| attr.attribute_type == ATTRIBUTE_FILE_NAME && !attr.content.is_empty())
+        .filter_map(|attr| parse_filename_attribute(&attr.content).ok())
+        .collect()
+}
+
+/// Groups a record's $FILE_NAME attributes into links, preferring Win32 and
+/// POSIX names for display and keeping DOS 8.3 names as aliases of the long
+/// name in the same directory. A DOS name without a long counterpart is kept
+/// as a link of its own.
+fn select_file_links(file_names: Vec<FileNameAttribute>) -> Vec<FileLink> {
+    let (dos_names, long_names): (Vec<_>, Vec<_>) = file_names.into_iter()
+        .partition(|file_name| file_name.namespace == FileNameNamespace::Dos);
+    
+    let mut links: Vec<FileLink> = Vec::new();
+    for file_name in long_names {
+        let duplicate = links.iter().any(|link| {
+            link.file_name.parent_directory == file_name.parent_directory
+                && link.file_name.filename == file_name.filename
+        });
+        if !duplicate {
+            links.push(FileLink { file_name, short_name: None });
+        }
+    }
+    
+    for file_name in dos_names {
+        let owner = links.iter_mut().find(|link| {
+            link.file_name.namespace == FileNameNamespace::Win32
+                && link.short_name.is_none()
+                && link.file_name.parent_directory == file_name.parent_directory
+        });
+        match owner {
+            Some(link) => link.short_name = Some(file_name.filename),
+            None => links.push(FileLink { file_name, short_name: None }),
+        }
+    }
+    
+    links
+}
+
+pub(crate) fn parse_filename_attribute(data: &[u8]) -> Result<FileNameAttribute> {
     if data.len() < 66 {
         anyhow::bail!("Filename attribute data too short");
     }
@@ -192,15 +529,20 @@ fn parse_filename_attribute(data: &[u8]) -> Result<FileNameAttribute> {
     let mut cursor = Cursor::new(data);
     let parent_directory = cursor.read_u64::<LittleEndian>()?;
     let creation_time = cursor.read_u64::<LittleEndian>()?;
-    let last_access_time = cursor.read_u64::<LittleEndian>()?;
     let last_write_time = cursor.read_u64::<LittleEndian>()?;
     let mft_change_time = cursor.read_u64::<LittleEndian>()?;
-    let file_size = cursor.read_u64::<LittleEndian>()?;
-    let allocated_size = cursor.read_u64::<LittleEndian>()?;
+    let last_access_time = cursor.read_u64::<LittleEndian>()?;
+    cursor.set_position(56);
     let file_flags = cursor.read_u32::<LittleEndian>()?;
+    cursor.set_position(64);
     let filename_length = cursor.read_u8()?;
+    let namespace = FileNameNamespace::from_u8(cursor.read_u8()?);
     
-    let filename_bytes = &data[66..66 + filename_length as usize * 2];
+    let filename_end = 66 + filename_length as usize * 2;
+    if filename_end > data.len() {
+        anyhow::bail!("Filename extends beyond attribute");
+    }
+    let filename_bytes = &data[66..filename_end];
     let filename = String::from_utf16_lossy(
         &filename_bytes.chunks(2)
             .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
@@ -210,52 +552,613 @@ fn parse_filename_attribute(data: &[u8]) -> Result<FileNameAttribute> {
     Ok(FileNameAttribute {
         parent_directory,
         creation_time,
-        last_access_time,
         last_write_time,
         mft_change_time,
-        file_size,
-        allocated_size,
+        last_access_time,
         file_flags,
-        filename_length,
+        namespace,
         filename,
     })
 }
 
-fn add_file_events_to_timeline(timeline: &mut Timeline, file_info: &FileNameAttribute) {
-    // Convert Windows FILETIME to UTC DateTime
-    let creation_time = windows_time_to_utc(file_info.creation_time);
-    let access_time = windows_time_to_utc(file_info.last_access_time);
-    let write_time = windows_time_to_utc(file_info.last_write_time);
-    let mft_change_time = windows_time_to_utc(file_info.mft_change_time);
+/// Tolerance when comparing a record's creation time with its MFT neighbours;
+/// records are allocated in order but clocks and reuse add some noise.
+const SEQUENCE_TOLERANCE: u64 = 24 * 3600 * 10_000_000;
+/// Number of preceding allocated records a creation time is compared with.
+const SEQUENCE_WINDOW: usize = 8;
+const FILETIME_TICKS_PER_SECOND: u64 = 10_000_000;
+
+/// Compares $STANDARD_INFORMATION with $FILE_NAME timestamps and with the
+/// surrounding records to flag classic timestomping indicators. $FN times
+/// can only be set by the kernel, so they serve as the reference.
+fn detect_timestomping(records: &[FileRecord]) -> HashMap<u64, Alert> {
+    let mut alerts = HashMap::new();
     
-    // Add events to timeline
-    if creation_time > Utc::now() - chrono::Duration::days(365) {
-        timeline.add_file_event(creation_time, EventType::FileCreation, 
-                              &file_info.filename, "MFT");
+    // The $MFT is created when the volume is formatted, which for a system
+    // volume is the Windows installation
+    let install_time = records.iter()
+        .find(|record| record.record_number == 0)
+        .map(|record| record.primary_name().creation_time)
+        .filter(|&time| time != 0);
+    
+    for (position, record) in records.iter().enumerate() {
+        let si = match &record.standard_info {
+            Some(si) if si.creation_time != 0 => si,
+            _ => continue,
+        };
+        let fn_attr = record.primary_name();
+        let mut indicators = Vec::new();
+        
+        if fn_attr.creation_time != 0 && si.creation_time < fn_attr.creation_time {
+            indicators.push((Severity::High,
+                             "$SI creation time is earlier than $FN creation time".to_string()));
+        }
+        
+        // Tools that set times through the Win32 API commonly drop the
+        // sub-second part, which NTFS otherwise always records
+        let zero_fraction = |time: u64| time != 0 && time.is_multiple_of(FILETIME_TICKS_PER_SECOND);
+        if zero_fraction(si.creation_time) && zero_fraction(si.last_write_time)
+            && !zero_fraction(fn_attr.creation_time)
+        {
+            indicators.push((Severity::Medium,
+                             "$SI creation and modification times have zeroed sub-second fractions".to_string()));
+        }
+        
+        if let Some(install_time) = install_time {
+            if record.record_number >= 16 && si.creation_time < install_time {
+                indicators.push((Severity::Medium,
+                                 "$SI creation time predates the volume installation".to_string()));
+            }
+        }
+        
+        // Records are allocated in order, so a creation time older than every
+        // recently allocated record before it is out of sequence
+        let neighbours: Vec<u64> = records[position.saturating_sub(SEQUENCE_WINDOW)..position].iter()
+            .filter(|neighbour| neighbour.record_number >= 16)
+            .map(|neighbour| neighbour.primary_name().creation_time)
+            .filter(|&time| time != 0)
+            .collect();
+        if record.record_number >= 16 && neighbours.len() == SEQUENCE_WINDOW {
+            let earliest = neighbours.iter().copied().min().unwrap_or(0);
+            if si.creation_time.saturating_add(SEQUENCE_TOLERANCE) < earliest {
+                indicators.push((Severity::Low,
+                                 "$SI creation time is out of sequence with neighbouring MFT records".to_string()));
+            }
+        }
+        
+        if let Some(severity) = indicators.iter().map(|(severity, _)| *severity).max() {
+            let explanation = indicators.into_iter()
+                .map(|(_, explanation)| explanation)
+                .collect::<Vec<_>>()
+                .join("; ");
+            alerts.insert(record.record_number, Alert { severity, explanation });
+        }
     }
     
-    if access_time > Utc::now() - chrono::Duration::days(365) {
-        timeline.add_file_event(access_time, EventType::FileAccess, 
-                              &file_info.filename, "MFT");
+    alerts
+}
+
+fn add_file_events_to_timeline(timeline: &mut Timeline, record: &FileRecord, link: &FileLink,
+                               path: &str, alert: Option<&Alert>) {
+    let file_info = &link.file_name;
+    
+    // Torn records are kept but labelled, since part of them may be stale
+    let source = if record.torn { "MFT (torn record)" } else { "MFT" };
+    
+    // $STANDARD_INFORMATION holds the MACB times Explorer shows and most
+    // tools report; $FILE_NAME holds the times set when the name was created
+    let mut time_sets = Vec::new();
+    let si_details = record.standard_info.as_ref().map(StandardInformation::details).unwrap_or_default();
+    if let Some(si) = &record.standard_info {
+        time_sets.push((TimestampSource::StandardInformation,
+                        [si.creation_time, si.last_write_time, si.last_access_time, si.mft_change_time]));
     }
+    time_sets.push((TimestampSource::FileName,
+                    [file_info.creation_time, file_info.last_write_time,
+                     file_info.last_access_time, file_info.mft_change_time]));
+    
+    let event_types = [
+        EventType::FileCreation,
+        EventType::FileModification,
+        EventType::FileAccess,
+        EventType::FileMftChange,
+    ];
     
-    if write_time > Utc::now() - chrono::Duration::days(365) {
-        timeline.add_file_event(write_time, EventType::FileModification, 
-                              &file_info.filename, "MFT");
+    for (timestamp_source, times) in time_sets {
+        for (event_type, &time) in event_types.iter().zip(times.iter()) {
+            if let Some(timestamp) = windows_time_to_utc(time) {
+                let event = timeline.add_file_event(timestamp, event_type.clone(), path,
+                                                    source, Some(timestamp_source));
+                event.alert = alert.cloned();
+                event.deleted = !record.in_use;
+                if record.torn {
+                    event.add_detail("Torn record", vec![TORN_RECORD_NOTE.to_string()]);
+                }
+                if let Some(short_name) = &link.short_name {
+                    event.add_detail("Short name", vec![short_name.clone()]);
+                }
+                if timestamp_source == TimestampSource::StandardInformation {
+                    for (label, value) in &si_details {
+                        event.add_detail(label, vec![value.clone()]);
+                    }
+                }
+            }
+        }
     }
+}
+
+/// Whether the `length` clusters starting at `lcn` are all free in the
+/// volume's cluster bitmap. Clusters past the end of the bitmap count as
+/// allocated. The bitmap is checked a byte at a time.
+fn clusters_free(bitmap: &[u8], lcn: u64, length: u64) -> bool {
+    let end = match lcn.checked_add(length) {
+        Some(end) if end <= bitmap.len() as u64 * 8 => end,
+        _ => return false,
+    };
     
-    if mft_change_time > Utc::now() - chrono::Duration::days(365) {
-        timeline.add_file_event(mft_change_time, EventType::FileMftChange, 
-                              &file_info.filename, "MFT");
+    let mut cluster = lcn;
+    while cluster < end {
+        let byte_start = cluster / 8 * 8;
+        let first_bit = cluster - byte_start;
+        let last_bit = (end - byte_start).min(8);
+        let mask = ((1u16 << last_bit) - (1u16 << first_bit)) as u8;
+        if bitmap[(cluster / 8) as usize] & mask != 0 {
+            return false;
+        }
+        cluster = byte_start + 8;
     }
+    true
 }
 
-fn windows_time_to_utc(windows_time: u64) -> DateTime<Utc> {
-    // Windows FILETIME is 100-nanosecond intervals since 1601-01-01
-    // Convert to Unix timestamp (seconds since 1970-01-01)
-    let unix_seconds = (windows_time as i64 - 116444736000000000) / 10000000;
-    match Utc.timestamp_opt(unix_seconds, 0) {
-        chrono::LocalResult::Single(dt) => dt,
-        _ => Utc::now(),
+fn read_cluster_bitmap(volume: &NtfsVolume) -> Result<Vec<u8>> {
+    let entry = parse_mft_entry(&volume.read_mft_record(BITMAP_RECORD)?)?;
+    let data = entry.attributes.iter()
+        .find(|attr| attr.attribute_type == ATTRIBUTE_DATA && attr.name_length == 0)
+        .context("$Bitmap has no $DATA attribute")?;
+    volume.read_attribute(data)
+}
+
+/// Writes the unnamed $DATA stream of a deleted record to `dir`, named after
+/// its record number and file name. Non-resident content is only extracted
+/// while none of its clusters have been allocated to another file, so it is
+/// skipped when the cluster bitmap is unavailable. Returns whether anything
+/// was written.
+fn extract_deleted_content(volume: &NtfsVolume, entry: &MftEntry, record_number: u64, name: &str,
+                           cluster_bitmap: Option<&[u8]>, dir: &Path) -> Result<bool> {
+    let data = match entry.attributes.iter()
+        .find(|attr| attr.attribute_type == ATTRIBUTE_DATA && attr.name_length == 0)
+    {
+        Some(data) => data,
+        None => return Ok(false),
+    };
+    
+    if data.non_resident {
+        let bitmap = match cluster_bitmap {
+            Some(bitmap) => bitmap,
+            None => return Ok(false),
+        };
+        let reallocated = data.data_runs.iter()
+            .any(|run| run.lcn.is_some_and(|lcn| !clusters_free(bitmap, lcn, run.length)));
+        if reallocated {
+            info!("Content of deleted '{}' (MFT record {}) has been overwritten", name, record_number);
+            return Ok(false);
+        }
+    }
+    
+    let content = volume.read_attribute(data)?;
+    let safe_name: String = name.chars()
+        .map(|c| if c.is_control() || "\\/:*?\"<>|".contains(c) { '_' } else { c })
+        .collect();
+    let output = dir.join(format!("{}_{}", record_number, safe_name));
+    std::fs::write(&output, content)
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_source::MemorySource;
+
+    /// 2024-01-01 10:00:00.1234567 UTC as a FILETIME.
+    const BASE_TIME: u64 = 133_485_768_001_234_567;
+    const DAY: u64 = 24 * 3600 * FILETIME_TICKS_PER_SECOND;
+
+    fn file_name(parent_directory: u64, filename: &str, namespace: FileNameNamespace,
+                 creation_time: u64) -> FileNameAttribute {
+        FileNameAttribute {
+            parent_directory,
+            creation_time,
+            last_write_time: creation_time,
+            mft_change_time: creation_time,
+            last_access_time: creation_time,
+            file_flags: 0,
+            namespace,
+            filename: filename.to_string(),
+        }
+    }
+
+    /// A file record whose $SI creation and modification times are
+    /// `si_times` and whose $FN times are all `fn_creation`.
+    fn record(record_number: u64, si_times: (u64, u64), fn_creation: u64) -> FileRecord {
+        let (creation_time, last_write_time) = si_times;
+        FileRecord {
+            record_number,
+            sequence_number: 1,
+            in_use: true,
+            is_directory: false,
+            links: vec![FileLink {
+                file_name: file_name(ROOT_RECORD, &format!("file{}.txt", record_number),
+                                     FileNameNamespace::Win32AndDos, fn_creation),
+                short_name: None,
+            }],
+            standard_info: Some(StandardInformation {
+                creation_time,
+                last_write_time,
+                mft_change_time: last_write_time,
+                last_access_time: last_write_time,
+                file_attributes: 0,
+                owner_id: None,
+                security_id: None,
+                usn: None,
+            }),
+            torn: false,
+        }
+    }
+
+    fn alert_for(records: &[FileRecord], record_number: u64) -> Option<(Severity, String)> {
+        detect_timestomping(records).remove(&record_number)
+            .map(|alert| (alert.severity, alert.explanation))
+    }
+
+    #[test]
+    fn untouched_records_raise_no_alert() {
+        let records = [record(0, (BASE_TIME, BASE_TIME), BASE_TIME),
+                       record(40, (BASE_TIME + DAY, BASE_TIME + 2 * DAY), BASE_TIME + DAY)];
+        assert!(detect_timestomping(&records).is_empty());
+    }
+
+    #[test]
+    fn flags_si_creation_before_fn_creation() {
+        let records = [record(40, (BASE_TIME - 30 * DAY, BASE_TIME), BASE_TIME)];
+        assert_eq!(alert_for(&records, 40),
+                   Some((Severity::High, "$SI creation time is earlier than $FN creation time".to_string())));
+    }
+
+    #[test]
+    fn flags_zeroed_sub_second_fractions() {
+        let whole_second = BASE_TIME - BASE_TIME % FILETIME_TICKS_PER_SECOND;
+        let records = [record(40, (whole_second + DAY, whole_second + DAY), BASE_TIME)];
+        assert_eq!(alert_for(&records, 40),
+                   Some((Severity::Medium,
+                         "$SI creation and modification times have zeroed sub-second fractions".to_string())));
+
+        // Only one of the two times being whole seconds happens naturally
+        let records = [record(40, (whole_second + DAY, BASE_TIME + DAY), BASE_TIME)];
+        assert_eq!(alert_for(&records, 40), None);
+    }
+
+    #[test]
+    fn flags_creation_before_the_volume_installation() {
+        let records = [record(0, (BASE_TIME, BASE_TIME), BASE_TIME),
+                       record(5, (BASE_TIME - DAY, BASE_TIME - DAY), BASE_TIME - DAY),
+                       record(40, (BASE_TIME - DAY, BASE_TIME - DAY), BASE_TIME - DAY)];
+        assert_eq!(alert_for(&records, 40),
+                   Some((Severity::Medium, "$SI creation time predates the volume installation".to_string())));
+        // System files below record 16 are created while formatting
+        assert_eq!(alert_for(&records, 5), None);
+    }
+
+    #[test]
+    fn flags_creation_out_of_sequence_with_neighbours() {
+        let mut records: Vec<FileRecord> = (16..24)
+            .map(|number| record(number, (BASE_TIME + number * DAY, BASE_TIME + number * DAY),
+                                 BASE_TIME + number * DAY))
+            .collect();
+        records.push(record(24, (BASE_TIME, BASE_TIME), BASE_TIME));
+        records.push(record(25, (BASE_TIME + 15 * DAY, BASE_TIME + 15 * DAY), BASE_TIME + 15 * DAY));
+
+        assert_eq!(alert_for(&records, 24),
+                   Some((Severity::Low,
+                         "$SI creation time is out of sequence with neighbouring MFT records".to_string())));
+        // Within a day of the earliest neighbour
+        assert_eq!(alert_for(&records, 25), None);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn combines_indicators_under_the_highest_severity() {
+        let whole_second = BASE_TIME - BASE_TIME % FILETIME_TICKS_PER_SECOND;
+        let records = [record(0, (BASE_TIME, BASE_TIME), BASE_TIME),
+                       record(40, (whole_second - DAY, whole_second - DAY), BASE_TIME + DAY)];
+        let (severity, explanation) = alert_for(&records, 40).unwrap();
+        assert_eq!(severity, Severity::High);
+        assert_eq!(explanation.split("; ").count(), 3);
+    }
+
+    #[test]
+    fn tolerates_times_at_the_end_of_the_filetime_range() {
+        let mut records: Vec<FileRecord> = (16..24)
+            .map(|number| record(number, (BASE_TIME, BASE_TIME), BASE_TIME))
+            .collect();
+        records.push(record(24, (u64::MAX - 1, u64::MAX - 1), u64::MAX - 1));
+        assert_eq!(alert_for(&records, 24), None);
+    }
+
+    fn link_names(links: &[FileLink]) -> Vec<(u64, &str, Option<&str>)> {
+        links.iter()
+            .map(|link| (link.file_name.parent_directory, link.file_name.filename.as_str(),
+                         link.short_name.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn prefers_win32_names_and_keeps_dos_aliases() {
+        // The DOS name is usually stored first
+        let links = select_file_links(vec![
+            file_name(30, "PROGRA~1", FileNameNamespace::Dos, BASE_TIME),
+            file_name(30, "Program Files", FileNameNamespace::Win32, BASE_TIME),
+        ]);
+        assert_eq!(link_names(&links), vec![(30, "Program Files", Some("PROGRA~1"))]);
+
+        let links = select_file_links(vec![file_name(30, "README.TXT", FileNameNamespace::Win32AndDos, BASE_TIME)]);
+        assert_eq!(link_names(&links), vec![(30, "README.TXT", None)]);
+
+        // A DOS name whose long name is missing is still reported
+        let links = select_file_links(vec![file_name(30, "ORPHAN~1.TXT", FileNameNamespace::Dos, BASE_TIME)]);
+        assert_eq!(link_names(&links), vec![(30, "ORPHAN~1.TXT", None)]);
+    }
+
+    #[test]
+    fn reports_every_hard_link() {
+        let links = select_file_links(vec![
+            file_name(30, "Long Document.docx", FileNameNamespace::Win32, BASE_TIME),
+            file_name(31, "Long Document.docx", FileNameNamespace::Win32, BASE_TIME),
+            file_name(31, "LONGDO~1.DOC", FileNameNamespace::Dos, BASE_TIME),
+            file_name(32, "link.docx", FileNameNamespace::Posix, BASE_TIME),
+            // The same name twice in one directory is one link
+            file_name(32, "link.docx", FileNameNamespace::Posix, BASE_TIME),
+        ]);
+        assert_eq!(link_names(&links), vec![
+            (30, "Long Document.docx", None),
+            (31, "Long Document.docx", Some("LONGDO~1.DOC")),
+            (32, "link.docx", None),
+        ]);
+    }
+
+    #[test]
+    fn short_names_are_a_detail_of_the_long_name_events() {
+        let mut record = record(40, (BASE_TIME, BASE_TIME), BASE_TIME);
+        record.links = select_file_links(vec![
+            file_name(ROOT_RECORD, "PROGRA~1", FileNameNamespace::Dos, BASE_TIME),
+            file_name(ROOT_RECORD, "Program Files", FileNameNamespace::Win32, BASE_TIME),
+        ]);
+        let mut timeline = Timeline::new();
+        add_file_events_to_timeline(&mut timeline, &record, &record.links[0], "\\Program Files", None);
+
+        // Four $SI and four $FN times
+        assert_eq!(timeline.len(), 8);
+        for event in &timeline.events {
+            assert!(event.description.contains("'\\Program Files'"));
+            assert!(!event.description.contains("PROGRA~1"));
+            let short_names: Vec<_> = event.details.iter()
+                .filter(|detail| detail.label == "Short name")
+                .collect();
+            assert_eq!(short_names.len(), 1);
+            assert_eq!(short_names[0].values, vec!["PROGRA~1".to_string()]);
+        }
+    }
+
+    #[test]
+    fn checks_cluster_runs_against_the_bitmap() {
+        // Clusters 3 and 12 allocated
+        let bitmap = [0b0000_1000, 0b0001_0000];
+        assert!(clusters_free(&bitmap, 4, 8));
+        assert!(clusters_free(&bitmap, 0, 3));
+        assert!(!clusters_free(&bitmap, 0, 4));
+        assert!(!clusters_free(&bitmap, 10, 3));
+        assert!(clusters_free(&bitmap, 13, 3));
+        // Past the end of the bitmap, or overflowing
+        assert!(!clusters_free(&bitmap, 13, 4));
+        assert!(!clusters_free(&bitmap, 4, u64::MAX));
+        assert!(clusters_free(&bitmap, 16, 0));
+    }
+
+    const CLUSTER_SIZE: usize = 512;
+    const RECORD_SIZE: usize = 1024;
+    const MFT_LCN: usize = 16;
+    const MFT_RECORDS: usize = 24;
+    const VOLUME_CLUSTERS: usize = 128;
+
+    fn resident_attribute(attribute_type: u32, content: &[u8]) -> Vec<u8> {
+        let mut attr = vec![0u8; (24 + content.len()).div_ceil(8) * 8];
+        let length = attr.len() as u32;
+        attr[0..4].copy_from_slice(&attribute_type.to_le_bytes());
+        attr[4..8].copy_from_slice(&length.to_le_bytes());
+        attr[16..20].copy_from_slice(&(content.len() as u32).to_le_bytes());
+        attr[20..22].copy_from_slice(&24u16.to_le_bytes());
+        attr[24..24 + content.len()].copy_from_slice(content);
+        attr
+    }
+
+    fn non_resident_attribute(attribute_type: u32, mapping_pairs: &[u8], data_size: u64) -> Vec<u8> {
+        let mut attr = vec![0u8; (64 + mapping_pairs.len() + 1).div_ceil(8) * 8];
+        let length = attr.len() as u32;
+        attr[0..4].copy_from_slice(&attribute_type.to_le_bytes());
+        attr[4..8].copy_from_slice(&length.to_le_bytes());
+        attr[8] = 1;
+        attr[32..34].copy_from_slice(&64u16.to_le_bytes());
+        attr[48..56].copy_from_slice(&data_size.to_le_bytes());
+        attr[56..64].copy_from_slice(&data_size.to_le_bytes());
+        attr[64..64 + mapping_pairs.len()].copy_from_slice(mapping_pairs);
+        attr
+    }
+
+    fn standard_information() -> Vec<u8> {
+        let mut content = vec![0u8; 72];
+        for time in content[..32].chunks_exact_mut(8) {
+            time.copy_from_slice(&BASE_TIME.to_le_bytes());
+        }
+        resident_attribute(ATTRIBUTE_STANDARD_INFORMATION, &content)
+    }
+
+    fn file_name_attribute(parent_record: u64, name: &str) -> Vec<u8> {
+        let units: Vec<u16> = name.encode_utf16().collect();
+        let mut content = vec![0u8; 66];
+        content[0..8].copy_from_slice(&(parent_record | 1 << 48).to_le_bytes());
+        for time in content[8..40].chunks_exact_mut(8) {
+            time.copy_from_slice(&BASE_TIME.to_le_bytes());
+        }
+        content[64] = units.len() as u8;
+        content[65] = 3;
+        content.extend(units.iter().flat_map(|unit| unit.to_le_bytes()));
+        resident_attribute(ATTRIBUTE_FILE_NAME, &content)
+    }
+
+    /// A FILE record with a valid update sequence array.
+    fn mft_record(flags: u16, attributes: &[Vec<u8>]) -> Vec<u8> {
+        let mut record = vec![0u8; RECORD_SIZE];
+        record[..4].copy_from_slice(MFT_SIGNATURE);
+        record[4..8].copy_from_slice(&[48, 0, 3, 0]);
+        record[16..18].copy_from_slice(&1u16.to_le_bytes());
+        record[20..22].copy_from_slice(&56u16.to_le_bytes());
+        record[22..24].copy_from_slice(&flags.to_le_bytes());
+
+        let mut offset = 56;
+        for attr in attributes {
+            record[offset..offset + attr.len()].copy_from_slice(attr);
+            offset += attr.len();
+        }
+        record[offset..offset + 4].copy_from_slice(&ATTRIBUTE_END.to_le_bytes());
+        record[24..28].copy_from_slice(&(offset as u32 + 8).to_le_bytes());
+        record[28..32].copy_from_slice(&(RECORD_SIZE as u32).to_le_bytes());
+
+        // Move the sector tails into the update sequence array
+        record[48..50].copy_from_slice(&[0x01, 0x00]);
+        for stride in 1..3 {
+            let end = stride * 512;
+            record.copy_within(end - 2..end, 48 + stride * 2);
+            record[end - 2..end].copy_from_slice(&[0x01, 0x00]);
+        }
+        record
+    }
+
+    /// A 128-cluster volume whose $MFT holds a live file, deleted files with
+    /// content in free clusters, in a reallocated cluster and in the record
+    /// itself, and, when `with_bitmap` is set, the $Bitmap those are checked
+    /// against.
+    fn ntfs_image(with_bitmap: bool) -> MemorySource {
+        let mut image = vec![0u8; VOLUME_CLUSTERS * CLUSTER_SIZE];
+        image[3..11].copy_from_slice(b"NTFS    ");
+        image[0x0B..0x0D].copy_from_slice(&(CLUSTER_SIZE as u16).to_le_bytes());
+        image[0x0D] = 1;
+        image[0x28..0x30].copy_from_slice(&(VOLUME_CLUSTERS as u64).to_le_bytes());
+        image[0x30..0x38].copy_from_slice(&(MFT_LCN as u64).to_le_bytes());
+        image[0x38..0x40].copy_from_slice(&(MFT_LCN as u64).to_le_bytes());
+        // 2^10 byte records
+        image[0x40] = 0xF6;
+        image[0x44] = 0xF6;
+
+        let mft_clusters = (MFT_RECORDS * RECORD_SIZE / CLUSTER_SIZE) as u8;
+        // Boot sector, $MFT and cluster 80 are allocated
+        let mut bitmap = vec![0u8; VOLUME_CLUSTERS / 8];
+        bitmap[..(MFT_LCN + mft_clusters as usize) / 8].fill(0xFF);
+        bitmap[10] = 0x01;
+
+        let mut records = vec![
+            (0, mft_record(MFT_RECORD_IN_USE, &[
+                standard_information(),
+                file_name_attribute(ROOT_RECORD, "$MFT"),
+                non_resident_attribute(ATTRIBUTE_DATA, &[0x11, mft_clusters, MFT_LCN as u8],
+                                       (MFT_RECORDS * RECORD_SIZE) as u64),
+            ])),
+            (ROOT_RECORD, mft_record(MFT_RECORD_IN_USE | MFT_RECORD_IS_DIRECTORY, &[
+                standard_information(),
+                file_name_attribute(ROOT_RECORD, "."),
+            ])),
+            (16, mft_record(MFT_RECORD_IN_USE, &[
+                standard_information(),
+                file_name_attribute(ROOT_RECORD, "live.txt"),
+                resident_attribute(ATTRIBUTE_DATA, b"live"),
+            ])),
+            (17, mft_record(0, &[
+                standard_information(),
+                file_name_attribute(ROOT_RECORD, "deleted.txt"),
+                non_resident_attribute(ATTRIBUTE_DATA, &[0x11, 0x01, 70], 9),
+            ])),
+            (18, mft_record(0, &[
+                standard_information(),
+                file_name_attribute(ROOT_RECORD, "overwritten.txt"),
+                non_resident_attribute(ATTRIBUTE_DATA, &[0x11, 0x01, 80], 9),
+            ])),
+            (19, mft_record(0, &[
+                standard_information(),
+                file_name_attribute(ROOT_RECORD, "resident.txt"),
+                resident_attribute(ATTRIBUTE_DATA, b"small"),
+            ])),
+        ];
+        if with_bitmap {
+            records.push((BITMAP_RECORD, mft_record(MFT_RECORD_IN_USE, &[
+                standard_information(),
+                file_name_attribute(ROOT_RECORD, "$Bitmap"),
+                resident_attribute(ATTRIBUTE_DATA, &bitmap),
+            ])));
+        }
+        for (number, record) in records {
+            let offset = MFT_LCN * CLUSTER_SIZE + number as usize * RECORD_SIZE;
+            image[offset..offset + RECORD_SIZE].copy_from_slice(&record);
+        }
+        image[70 * CLUSTER_SIZE..70 * CLUSTER_SIZE + 9].copy_from_slice(b"recovered");
+        image[80 * CLUSTER_SIZE..80 * CLUSTER_SIZE + 9].copy_from_slice(b"reused!!!");
+        MemorySource::new(image)
+    }
+
+    /// Parses the $MFT of `image`, extracting deleted content into a fresh
+    /// directory, and returns the timeline and the extracted files.
+    fn parse_image(image: &MemorySource, name: &str) -> (Timeline, Vec<(String, Vec<u8>)>) {
+        let volume = NtfsVolume::open(image).unwrap();
+        let mut timeline = Timeline::new();
+        let directory = std::env::temp_dir().join(format!("chronos-{}-{}", name, std::process::id()));
+        parse_mft(&volume, &mut timeline, Some(&directory)).unwrap();
+
+        let mut extracted: Vec<(String, Vec<u8>)> = std::fs::read_dir(&directory).unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                (path.file_name().unwrap().to_string_lossy().into_owned(), std::fs::read(&path).unwrap())
+            })
+            .collect();
+        extracted.sort();
+        std::fs::remove_dir_all(&directory).unwrap();
+        (timeline, extracted)
+    }
+
+    #[test]
+    fn reports_deleted_records() {
+        let (timeline, _) = parse_image(&ntfs_image(true), "mft-deleted");
+        let deleted_flags = |path: &str| -> Vec<bool> {
+            timeline.events.iter()
+                .filter(|event| event.description.contains(&format!("'{}'", path)))
+                .map(|event| event.deleted)
+                .collect()
+        };
+
+        assert_eq!(deleted_flags("\\live.txt"), vec![false; 8]);
+        assert_eq!(deleted_flags("\\deleted.txt"), vec![true; 8]);
+        assert_eq!(deleted_flags("\\resident.txt"), vec![true; 8]);
+        assert_eq!(deleted_flags("\\$Bitmap"), vec![false; 8]);
+    }
+
+    #[test]
+    fn extracts_deleted_content_that_was_not_overwritten() {
+        let (_, extracted) = parse_image(&ntfs_image(true), "mft-extract");
+        assert_eq!(extracted, vec![
+            ("17_deleted.txt".to_string(), b"recovered".to_vec()),
+            ("19_resident.txt".to_string(), b"small".to_vec()),
+        ]);
+    }
+
+    #[test]
+    fn extracts_only_resident_content_without_the_bitmap() {
+        let (_, extracted) = parse_image(&ntfs_image(false), "mft-no-bitmap");
+        assert_eq!(extracted, vec![("19_resident.txt".to_string(), b"small".to_vec())]);
+    }
+}
@@ -0,0 +1,823 @@
+use anyhow::{Context, Result};
+use byteorder::{ByteOrder, LittleEndian};
+use tracing::{info, warn};
+
+use crate::image_source::ImageSource;
+use crate::mft_parser::{self, MftAttribute, MftEntry};
+
+const NTFS_OEM_ID: &[u8; 8] = b"NTFS    ";
+const ATTRIBUTE_ATTRIBUTE_LIST: u32 = 0x20;
+const ATTRIBUTE_DATA: u32 = 0x80;
+/// Compressed attributes are stored in chunks of 4 KiB uncompressed data.
+const LZNT1_CHUNK_SIZE: usize = 4096;
+/// NTFS compresses attributes in units of 2^4 = 16 clusters.
+const COMPRESSION_UNIT: u16 = 4;
+/// Upper bound on attribute values read into memory, to survive corrupt sizes.
+const MAX_ATTRIBUTE_SIZE: u64 = 1 << 30;
+/// Buffers start at most this large and grow as data is actually read, so a
+/// corrupt size does not reserve memory up front.
+const MAX_INITIAL_CAPACITY: usize = 1 << 20;
+/// NTFS supports clusters of up to 2 MiB.
+const MAX_CLUSTER_SIZE: u32 = 2 * 1024 * 1024;
+/// Bounds on MFT and index record sizes; Windows uses 1 KiB or 4 KiB records.
+const MIN_RECORD_SIZE: u32 = 256;
+const MAX_RECORD_SIZE: u32 = 64 * 1024;
+
+/// The fields of the NTFS volume boot record needed to locate the $MFT.
+#[derive(Debug, Clone)]
+pub struct BootSector {
+    pub bytes_per_sector: u32,
+    pub sectors_per_cluster: u32,
+    pub total_sectors: u64,
+    pub mft_lcn: u64,
+    pub mft_mirror_lcn: u64,
+    pub mft_record_size: u32,
+    pub index_record_size: u32,
+    pub serial_number: u64,
+}
+
+impl BootSector {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < 512 || &data[3..11] != NTFS_OEM_ID {
+            anyhow::bail!("Not an NTFS boot sector");
+        }
+
+        let bytes_per_sector = LittleEndian::read_u16(&data[0x0B..0x0D]) as u32;
+        // Values above 0x80 encode 2^(256 - n) sectors for clusters > 64 KiB
+        let sectors_per_cluster = match data[0x0D] {
+            n if n > 0x80 => match 1u32.checked_shl(256 - n as u32) {
+                Some(sectors) => sectors,
+                None => anyhow::bail!("Invalid NTFS cluster size exponent {}", 256 - n as u32),
+            },
+            n => n as u32,
+        };
+
+        if !bytes_per_sector.is_power_of_two() || bytes_per_sector < 256 || sectors_per_cluster == 0 {
+            anyhow::bail!("Invalid NTFS geometry ({} bytes/sector, {} sectors/cluster)",
+                          bytes_per_sector, sectors_per_cluster);
+        }
+
+        let cluster_size = match bytes_per_sector.checked_mul(sectors_per_cluster) {
+            Some(size) if size <= MAX_CLUSTER_SIZE => size,
+            _ => anyhow::bail!("Invalid NTFS cluster size ({} bytes/sector, {} sectors/cluster)",
+                               bytes_per_sector, sectors_per_cluster),
+        };
+
+        Ok(BootSector {
+            bytes_per_sector,
+            sectors_per_cluster,
+            total_sectors: LittleEndian::read_u64(&data[0x28..0x30]),
+            mft_lcn: LittleEndian::read_u64(&data[0x30..0x38]),
+            mft_mirror_lcn: LittleEndian::read_u64(&data[0x38..0x40]),
+            mft_record_size: record_size(data[0x40] as i8, cluster_size)
+                .context("Invalid MFT record size")?,
+            index_record_size: record_size(data[0x44] as i8, cluster_size)
+                .context("Invalid index record size")?,
+            serial_number: LittleEndian::read_u64(&data[0x48..0x50]),
+        })
+    }
+
+    pub fn cluster_size(&self) -> u64 {
+        self.bytes_per_sector as u64 * self.sectors_per_cluster as u64
+    }
+
+    pub fn cluster_count(&self) -> u64 {
+        self.total_sectors / self.sectors_per_cluster as u64
+    }
+}
+
+/// Record sizes are stored as a cluster count, or as 2^-n bytes when negative.
+fn record_size(value: i8, cluster_size: u32) -> Result<u32> {
+    let size = if value < 0 {
+        1u32.checked_shl(-(value as i32) as u32)
+    } else {
+        (value as u32).checked_mul(cluster_size)
+    };
+    match size {
+        Some(size) if (MIN_RECORD_SIZE..=MAX_RECORD_SIZE).contains(&size) => Ok(size),
+        _ => anyhow::bail!("record size value {} with {} byte clusters is out of range", value, cluster_size),
+    }
+}
+
+/// NTFS protects the last two bytes of every 512-byte stride of a
+/// multi-sector structure with an update sequence number, regardless of the
+/// device sector size.
+const FIXUP_STRIDE: usize = 512;
+
+/// Signatures of the multi-sector structures that carry an update sequence
+/// array: MFT records, index buffers and $LogFile restart/record pages.
+/// `BAAD` is written by chkdsk over records it found damaged.
+const MULTI_SECTOR_SIGNATURES: [&[u8; 4]; 5] = [b"FILE", b"INDX", b"RCRD", b"RSTR", b"BAAD"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixupStatus {
+    Valid,
+    /// At least one stride did not end in the update sequence number, so the
+    /// structure was only partially written (or was marked BAAD by chkdsk).
+    Torn,
+}
+
+/// Validates and reverses the update sequence array of a FILE, INDX, RCRD or
+/// RSTR structure in place. Strides whose check value does not match are left
+/// untouched and the structure is reported as torn.
+pub fn apply_fixups(data: &mut [u8]) -> Result<FixupStatus> {
+    if data.len() < 8 || !MULTI_SECTOR_SIGNATURES.iter().any(|signature| data[..4] == signature[..]) {
+        anyhow::bail!("Not a multi-sector structure");
+    }
+
+    let usa_offset = LittleEndian::read_u16(&data[4..6]) as usize;
+    let usa_count = LittleEndian::read_u16(&data[6..8]) as usize;
+    if usa_count < 2 || usa_offset + usa_count * 2 > data.len() || (usa_count - 1) * FIXUP_STRIDE > data.len() {
+        anyhow::bail!("Invalid update sequence array ({} entries at offset {})", usa_count, usa_offset);
+    }
+
+    let mut status = if &data[..4] == b"BAAD" { FixupStatus::Torn } else { FixupStatus::Valid };
+    let check_value = [data[usa_offset], data[usa_offset + 1]];
+
+    for stride in 1..usa_count {
+        let end = stride * FIXUP_STRIDE;
+        let entry = usa_offset + stride * 2;
+        if data[end - 2..end] != check_value {
+            status = FixupStatus::Torn;
+            continue;
+        }
+        data[end - 2] = data[entry];
+        data[end - 1] = data[entry + 1];
+    }
+
+    Ok(status)
+}
+
+/// A single run of a non-resident attribute: `length` clusters starting at
+/// `lcn`, or a sparse run (no clusters allocated) when `lcn` is `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataRun {
+    pub lcn: Option<u64>,
+    pub length: u64,
+}
+
+/// Decodes an NTFS mapping pairs array into absolute cluster runs.
+pub fn decode_data_runs(data: &[u8]) -> Result<Vec<DataRun>> {
+    let mut runs = Vec::new();
+    let mut position = 0;
+    let mut lcn: i64 = 0;
+
+    while position < data.len() && data[position] != 0 {
+        let header = data[position];
+        let length_size = (header & 0x0F) as usize;
+        let offset_size = (header >> 4) as usize;
+        position += 1;
+
+        if length_size == 0 || length_size > 8 || offset_size > 8
+            || position + length_size + offset_size > data.len()
+        {
+            anyhow::bail!("Malformed data run at offset {}", position - 1);
+        }
+
+        let length = read_unsigned(&data[position..position + length_size]);
+        position += length_size;
+
+        if offset_size == 0 {
+            runs.push(DataRun { lcn: None, length });
+        } else {
+            lcn = match lcn.checked_add(read_signed(&data[position..position + offset_size])) {
+                Some(lcn) if lcn >= 0 => lcn,
+                _ => anyhow::bail!("Data run at offset {} points outside the volume", position - 1 - length_size),
+            };
+            runs.push(DataRun { lcn: Some(lcn as u64), length });
+        }
+        position += offset_size;
+    }
+
+    Ok(runs)
+}
+
+fn read_unsigned(bytes: &[u8]) -> u64 {
+    bytes.iter().rev().fold(0u64, |value, &b| (value << 8) | b as u64)
+}
+
+fn read_signed(bytes: &[u8]) -> i64 {
+    let value = read_unsigned(bytes);
+    let shift = 64 - bytes.len() * 8;
+    ((value << shift) as i64) >> shift
+}
+
+fn mft_data_runs(image: &dyn ImageSource, boot: &BootSector, lcn: u64) -> Result<(Vec<DataRun>, u64)> {
+    let offset = lcn.checked_mul(boot.cluster_size())
+        .with_context(|| format!("$MFT cluster {} is out of range", lcn))?;
+    let record = image.read_bytes(offset, boot.mft_record_size as usize)
+        .context("Failed to read $MFT record 0")?;
+    let entry = mft_parser::parse_mft_entry(&record)
+        .context("Failed to parse $MFT record 0")?;
+
+    let data = entry.attributes.iter()
+        .find(|attr| attr.attribute_type == ATTRIBUTE_DATA && attr.name_length == 0 && attr.non_resident)
+        .context("$MFT record 0 has no non-resident $DATA attribute")?;
+
+    Ok((data.data_runs.clone(), data.data_size))
+}
+
+/// An NTFS volume with its $MFT located through the boot sector and the
+/// data runs of MFT record 0.
+pub struct NtfsVolume<'a> {
+    image: &'a dyn ImageSource,
+    pub boot: BootSector,
+    mft_runs: Vec<DataRun>,
+    mft_size: u64,
+}
+
+impl<'a> NtfsVolume<'a> {
+    pub fn open(image: &'a dyn ImageSource) -> Result<Self> {
+        let boot = BootSector::parse(&image.read_bytes(0, 512)?)
+            .context("Failed to parse NTFS boot sector")?;
+
+        info!(
+            "NTFS volume serial {:016X}: {} bytes, {} bytes/cluster, {} byte MFT records, {} byte index records",
+            boot.serial_number,
+            boot.total_sectors.saturating_mul(boot.bytes_per_sector as u64),
+            boot.cluster_size(),
+            boot.mft_record_size,
+            boot.index_record_size
+        );
+
+        // Record 0 describes the $MFT itself; its $DATA runs locate the rest.
+        // $MFTMirr holds a copy of it in case the primary is damaged.
+        let (mft_runs, mft_size) = match mft_data_runs(image, &boot, boot.mft_lcn) {
+            Ok(runs) => runs,
+            Err(e) => {
+                warn!("$MFT record 0 unusable ({:#}), trying $MFTMirr", e);
+                mft_data_runs(image, &boot, boot.mft_mirror_lcn)
+                    .context("Failed to locate $MFT from $MFTMirr")?
+            }
+        };
+
+        let volume = NtfsVolume {
+            image,
+            mft_runs,
+            mft_size,
+            boot,
+        };
+
+        info!("$MFT is {} bytes in {} run(s)", volume.mft_size, volume.mft_runs.len());
+        Ok(volume)
+    }
+
+    pub fn record_size(&self) -> usize {
+        self.boot.mft_record_size as usize
+    }
+
+    pub fn record_count(&self) -> u64 {
+        self.mft_size / self.boot.mft_record_size as u64
+    }
+
+    /// Reads MFT record `index` as stored on disk.
+    pub fn read_mft_record(&self, index: u64) -> Result<Vec<u8>> {
+        let offset = index * self.boot.mft_record_size as u64;
+        self.read_runs(&self.mft_runs, offset, self.record_size())
+    }
+
+    /// Reads and parses MFT record `index` with its extension records merged.
+    pub fn read_file_record(&self, index: u64) -> Result<MftEntry> {
+        let mut entry = mft_parser::parse_mft_entry(&self.read_mft_record(index)?)
+            .with_context(|| format!("Failed to parse MFT record {}", index))?;
+        self.resolve_attribute_list(&mut entry, index);
+        Ok(entry)
+    }
+
+    /// Merges in the attributes that the $ATTRIBUTE_LIST of MFT record
+    /// `index` places in extension records, so the entry carries the file's
+    /// complete attribute set.
+    pub fn resolve_attribute_list(&self, entry: &mut MftEntry, index: u64) {
+        let attribute_list = entry.attributes.iter()
+            .find(|attr| attr.attribute_type == ATTRIBUTE_ATTRIBUTE_LIST)
+            .map(|attr| self.read_attribute(attr));
+
+        match attribute_list {
+            Some(Ok(list)) => self.merge_extension_records(entry, index, &list),
+            Some(Err(e)) => warn!("Failed to read $ATTRIBUTE_LIST of MFT record {}: {:#}", index, e),
+            None => {}
+        }
+    }
+
+    fn merge_extension_records(&self, entry: &mut MftEntry, index: u64, list: &[u8]) {
+        let mut extensions: Vec<(u64, u32, u16)> = Vec::new();
+        let mut position = 0;
+
+        // Each entry names an attribute by type and ID and the record holding it
+        while position + 26 <= list.len() {
+            let attribute_type = LittleEndian::read_u32(&list[position..position + 4]);
+            let length = LittleEndian::read_u16(&list[position + 4..position + 6]) as usize;
+            let reference = LittleEndian::read_u64(&list[position + 16..position + 24]);
+            let attribute_id = LittleEndian::read_u16(&list[position + 24..position + 26]);
+            if length < 26 {
+                break;
+            }
+
+            let record_number = reference & 0x0000_FFFF_FFFF_FFFF;
+            if record_number != index {
+                extensions.push((record_number, attribute_type, attribute_id));
+            }
+            position += length;
+        }
+
+        let mut records: Vec<u64> = extensions.iter().map(|&(record, _, _)| record).collect();
+        records.sort_unstable();
+        records.dedup();
+
+        for record_number in records {
+            let extension = match self.read_mft_record(record_number)
+                .and_then(|raw| mft_parser::parse_mft_entry(&raw))
+            {
+                Ok(extension) => extension,
+                Err(e) => {
+                    warn!("Failed to read extension record {} of MFT record {}: {:#}", record_number, index, e);
+                    continue;
+                }
+            };
+            if extension.base_file_record & 0x0000_FFFF_FFFF_FFFF != index {
+                warn!("MFT record {} is no longer an extension of record {}", record_number, index);
+                continue;
+            }
+
+            entry.torn |= extension.torn;
+            entry.attributes.extend(extension.attributes.into_iter().filter(|attr| {
+                extensions.contains(&(record_number, attr.attribute_type, attr.attribute_id))
+            }));
+        }
+
+        merge_attribute_extents(&mut entry.attributes);
+    }
+
+    /// Reads the value of an attribute, following the runs of non-resident
+    /// attributes. Sparse runs read as zeros and compressed attributes are
+    /// decompressed; encrypted attributes are returned as stored.
+    pub fn read_attribute(&self, attr: &MftAttribute) -> Result<Vec<u8>> {
+        if !attr.non_resident {
+            return Ok(attr.content.clone());
+        }
+        if attr.data_size > MAX_ATTRIBUTE_SIZE {
+            anyhow::bail!("Attribute size {} is implausible", attr.data_size);
+        }
+
+        let size = attr.data_size as usize;
+        let mut content = if attr.is_compressed() && !attr.is_encrypted() {
+            if attr.compression_unit != COMPRESSION_UNIT {
+                anyhow::bail!("Unsupported compression unit of 2^{} clusters", attr.compression_unit);
+            }
+            self.read_compressed_runs(&attr.data_runs, 1 << COMPRESSION_UNIT, size)?
+        } else {
+            self.read_runs(&attr.data_runs, 0, size)?
+        };
+
+        // Data past the initialized size is undefined on disk
+        let initialized = (attr.initialized_size as usize).min(size);
+        content[initialized..].fill(0);
+        Ok(content)
+    }
+
+    /// Reads a compressed stream one compression unit at a time: units with
+    /// every cluster allocated are stored raw, units ending in a sparse run
+    /// hold LZNT1 data in their allocated clusters, and fully sparse units
+    /// are zeros.
+    fn read_compressed_runs(&self, runs: &[DataRun], unit_clusters: u64, size: usize) -> Result<Vec<u8>> {
+        let cluster_size = self.boot.cluster_size();
+        let unit_size = (unit_clusters * cluster_size) as usize;
+        let mut content = Vec::with_capacity(size.min(MAX_INITIAL_CAPACITY));
+        let mut unit_start = 0u64;
+
+        while content.len() < size {
+            let clusters = clusters_in_range(runs, unit_start, unit_clusters);
+            let allocated: Vec<u64> = clusters.iter().flatten().copied().collect();
+            let wanted = unit_size.min(size - content.len());
+
+            if allocated.is_empty() {
+                content.resize(content.len() + wanted, 0);
+            } else {
+                let mut stored = Vec::with_capacity(allocated.len() * cluster_size as usize);
+                for lcn in &allocated {
+                    stored.extend_from_slice(&self.image.read_bytes(self.run_offset(*lcn, 1)?, cluster_size as usize)?);
+                }
+                if (allocated.len() as u64) < unit_clusters {
+                    let mut unit = decompress_lznt1(&stored, unit_size)?;
+                    unit.resize(wanted, 0);
+                    content.extend_from_slice(&unit);
+                } else {
+                    stored.resize(wanted, 0);
+                    content.extend_from_slice(&stored);
+                }
+            }
+
+            unit_start += unit_clusters;
+        }
+
+        Ok(content)
+    }
+
+    /// Returns the byte offset of a run of `length` clusters at `lcn`, which
+    /// must lie within the volume.
+    fn run_offset(&self, lcn: u64, length: u64) -> Result<u64> {
+        match lcn.checked_add(length) {
+            Some(end) if end <= self.boot.cluster_count() => Ok(lcn * self.boot.cluster_size()),
+            _ => anyhow::bail!("Data run of {} clusters at LCN {} lies beyond the end of the volume", length, lcn),
+        }
+    }
+
+    /// Reads `length` bytes at `offset` of the stream described by `runs`.
+    /// Sparse runs read as zeros.
+    pub fn read_runs(&self, runs: &[DataRun], offset: u64, length: usize) -> Result<Vec<u8>> {
+        if length == 0 {
+            return Ok(Vec::new());
+        }
+
+        let cluster_size = self.boot.cluster_size();
+        let mut buffer = Vec::with_capacity(length.min(MAX_INITIAL_CAPACITY));
+        let mut run_start = 0u64;
+        let end = offset.checked_add(length as u64)
+            .with_context(|| format!("Read at offset {} is out of range", offset))?;
+
+        for run in runs {
+            let run_end = run.length.checked_mul(cluster_size)
+                .and_then(|run_size| run_start.checked_add(run_size))
+                .with_context(|| format!("Data run of {} clusters is out of range", run.length))?;
+            let position = offset + buffer.len() as u64;
+            if position < run_end && position >= run_start {
+                let wanted = (end.min(run_end) - position) as usize;
+                match run.lcn {
+                    Some(lcn) => {
+                        let disk_offset = self.run_offset(lcn, run.length)? + (position - run_start);
+                        buffer.extend_from_slice(&self.image.read_bytes(disk_offset, wanted)?);
+                    }
+                    None => buffer.resize(buffer.len() + wanted, 0),
+                }
+                if buffer.len() == length {
+                    return Ok(buffer);
+                }
+            }
+            run_start = run_end;
+        }
+
+        anyhow::bail!("Read at offset {} extends beyond the mapped runs", offset)
+    }
+}
+
+/// Returns the LCN of each of the `count` clusters starting at `vcn`, or
+/// `None` for sparse or unmapped clusters.
+fn clusters_in_range(runs: &[DataRun], vcn: u64, count: u64) -> Vec<Option<u64>> {
+    let mut clusters = vec![None; count as usize];
+    let mut run_start = 0u64;
+
+    for run in runs {
+        let run_end = run_start.saturating_add(run.length);
+        if run_end > vcn && run_start < vcn + count {
+            if let Some(lcn) = run.lcn {
+                for cluster in vcn.max(run_start)..(vcn + count).min(run_end) {
+                    clusters[(cluster - vcn) as usize] = Some(lcn.saturating_add(cluster - run_start));
+                }
+            }
+        }
+        run_start = run_end;
+    }
+
+    clusters
+}
+
+/// Joins the extents of non-resident attributes that were split across
+/// extension records into a single attribute with one runlist, in VCN order.
+fn merge_attribute_extents(attributes: &mut Vec<MftAttribute>) {
+    let mut merged: Vec<MftAttribute> = Vec::with_capacity(attributes.len());
+    let mut extents: Vec<MftAttribute> = Vec::new();
+
+    for attr in attributes.drain(..) {
+        if attr.non_resident && attr.starting_vcn > 0 {
+            extents.push(attr);
+        } else {
+            merged.push(attr);
+        }
+    }
+
+    extents.sort_by_key(|extent| extent.starting_vcn);
+    for extent in extents {
+        let first = merged.iter_mut().find(|attr| {
+            attr.non_resident && attr.attribute_type == extent.attribute_type && attr.name == extent.name
+        });
+        match first {
+            Some(first) if first.last_vcn + 1 == extent.starting_vcn => {
+                first.data_runs.extend(extent.data_runs);
+                first.last_vcn = extent.last_vcn;
+            }
+            _ => warn!("Dropping non-contiguous extent at VCN {} of attribute 0x{:X}",
+                       extent.starting_vcn, extent.attribute_type),
+        }
+    }
+
+    *attributes = merged;
+}
+
+/// Decompresses LZNT1 data, as used for NTFS compressed attributes, into at
+/// most `max_size` bytes.
+pub fn decompress_lznt1(data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(max_size);
+    let mut position = 0;
+
+    while position + 2 <= data.len() && output.len() < max_size {
+        let header = LittleEndian::read_u16(&data[position..position + 2]);
+        if header == 0 {
+            break;
+        }
+        let chunk_size = (header & 0x0FFF) as usize + 1;
+        let chunk_end = position + 2 + chunk_size;
+        if chunk_end > data.len() {
+            anyhow::bail!("LZNT1 chunk extends beyond the compressed data");
+        }
+
+        // Every chunk but the last stands for a full 4 KiB of output
+        let chunk_start = output.len().div_ceil(LZNT1_CHUNK_SIZE) * LZNT1_CHUNK_SIZE;
+        output.resize(chunk_start, 0);
+        let chunk = &data[position + 2..chunk_end];
+
+        if header & 0x8000 == 0 {
+            output.extend_from_slice(chunk);
+        } else {
+            decompress_lznt1_chunk(chunk, &mut output, chunk_start)?;
+        }
+        position = chunk_end;
+    }
+
+    output.truncate(max_size);
+    Ok(output)
+}
+
+fn decompress_lznt1_chunk(chunk: &[u8], output: &mut Vec<u8>, chunk_start: usize) -> Result<()> {
+    let mut position = 0;
+
+    while position < chunk.len() {
+        let flags = chunk[position];
+        position += 1;
+
+        for bit in 0..8 {
+            if position >= chunk.len() {
+                return Ok(());
+            }
+            if flags & (1 << bit) == 0 {
+                output.push(chunk[position]);
+                position += 1;
+                continue;
+            }
+            if position + 2 > chunk.len() {
+                anyhow::bail!("Truncated LZNT1 back-reference");
+            }
+
+            let token = LittleEndian::read_u16(&chunk[position..position + 2]) as usize;
+            position += 2;
+
+            // The split between offset and length bits moves as the chunk
+            // fills, since offsets can only reach back to its start
+            let decompressed = output.len() - chunk_start;
+            let mut length_bits = 12;
+            let mut threshold = 0x10;
+            while decompressed > threshold {
+                length_bits -= 1;
+                threshold <<= 1;
+            }
+
+            let length = (token & ((1 << length_bits) - 1)) + 3;
+            let offset = (token >> length_bits) + 1;
+            if offset > decompressed {
+                anyhow::bail!("LZNT1 back-reference before the start of the chunk");
+            }
+            for _ in 0..length {
+                output.push(output[output.len() - offset]);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_source::MemorySource;
+
+    /// A two-sector FILE record whose sectors end in the update sequence
+    /// number 0x0001; the original bytes are AA BB and CC DD.
+    fn file_record() -> Vec<u8> {
+        let mut record = vec![0u8; 1024];
+        record[..4].copy_from_slice(b"FILE");
+        record[4..8].copy_from_slice(&[48, 0, 3, 0]);
+        record[48..54].copy_from_slice(&[0x01, 0x00, 0xAA, 0xBB, 0xCC, 0xDD]);
+        record[510..512].copy_from_slice(&[0x01, 0x00]);
+        record[1022..1024].copy_from_slice(&[0x01, 0x00]);
+        record
+    }
+
+    #[test]
+    fn applies_fixups() {
+        let mut record = file_record();
+        assert_eq!(apply_fixups(&mut record).unwrap(), FixupStatus::Valid);
+        assert_eq!(record[510..512], [0xAA, 0xBB]);
+        assert_eq!(record[1022..1024], [0xCC, 0xDD]);
+    }
+
+    #[test]
+    fn torn_records_keep_their_intact_sectors() {
+        let mut record = file_record();
+        record[1022] = 0x02;
+        assert_eq!(apply_fixups(&mut record).unwrap(), FixupStatus::Torn);
+        assert_eq!(record[510..512], [0xAA, 0xBB]);
+        assert_eq!(record[1022..1024], [0x02, 0x00]);
+
+        let mut record = file_record();
+        record[..4].copy_from_slice(b"BAAD");
+        assert_eq!(apply_fixups(&mut record).unwrap(), FixupStatus::Torn);
+    }
+
+    #[test]
+    fn rejects_invalid_update_sequence_arrays() {
+        let mut record = file_record();
+        record[..4].copy_from_slice(b"XXXX");
+        assert!(apply_fixups(&mut record).is_err());
+
+        // More strides than the record holds
+        let mut record = file_record();
+        record[6] = 4;
+        assert!(apply_fixups(&mut record).is_err());
+
+        // Array past the end of the record
+        let mut record = file_record();
+        record[4..6].copy_from_slice(&1022u16.to_le_bytes());
+        assert!(apply_fixups(&mut record).is_err());
+    }
+
+    fn run(lcn: Option<u64>, length: u64) -> DataRun {
+        DataRun { lcn, length }
+    }
+
+    #[test]
+    fn decodes_data_runs() {
+        // 16 clusters at 256, 8 sparse, then 4 clusters 16 before the last run
+        let mapping_pairs = [0x21, 0x10, 0x00, 0x01, 0x01, 0x08, 0x11, 0x04, 0xF0, 0x00, 0xFF];
+        assert_eq!(decode_data_runs(&mapping_pairs).unwrap(),
+                   vec![run(Some(256), 16), run(None, 8), run(Some(240), 4)]);
+        // A runlist may end without a terminator at the end of the attribute
+        assert_eq!(decode_data_runs(&[0x11, 0x02, 0x7F]).unwrap(), vec![run(Some(127), 2)]);
+        assert_eq!(decode_data_runs(&[0x00]).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn rejects_malformed_data_runs() {
+        // Before the start of the volume
+        assert!(decode_data_runs(&[0x11, 0x01, 0x10, 0x11, 0x01, 0xE0]).is_err());
+        // Truncated offset
+        assert!(decode_data_runs(&[0x21, 0x10, 0x00]).is_err());
+        // No length
+        assert!(decode_data_runs(&[0x10, 0x05]).is_err());
+        // Oversized length field
+        assert!(decode_data_runs(&[0x19, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01]).is_err());
+        // LCN overflowing past i64::MAX
+        let overflow = [0x81, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F,
+                        0x11, 0x01, 0x01];
+        assert!(decode_data_runs(&overflow).is_err());
+    }
+
+    /// A volume of 64 clusters of 512 bytes over a numbered image.
+    fn volume(image: &MemorySource) -> NtfsVolume<'_> {
+        let boot = BootSector {
+            bytes_per_sector: 512,
+            sectors_per_cluster: 1,
+            total_sectors: 64,
+            mft_lcn: 0,
+            mft_mirror_lcn: 0,
+            mft_record_size: 1024,
+            index_record_size: 4096,
+            serial_number: 0,
+        };
+        NtfsVolume { image, boot, mft_runs: Vec::new(), mft_size: 0 }
+    }
+
+    #[test]
+    fn reads_across_runs() {
+        let image = MemorySource::new((0..64 * 512).map(|i| (i / 512) as u8).collect());
+        let volume = volume(&image);
+        let runs = [run(Some(10), 1), run(None, 1), run(Some(3), 2)];
+
+        let data = volume.read_runs(&runs, 256, 1024).unwrap();
+        assert!(data[..256].iter().all(|&byte| byte == 10));
+        assert!(data[256..768].iter().all(|&byte| byte == 0));
+        assert!(data[768..].iter().all(|&byte| byte == 3));
+        assert!(volume.read_runs(&runs, 1024, 1025).is_err());
+    }
+
+    #[test]
+    fn rejects_runs_outside_the_volume() {
+        let image = MemorySource::new(vec![0; 64 * 512]);
+        let volume = volume(&image);
+
+        assert!(volume.read_runs(&[run(Some(63), 2)], 0, 512).is_err());
+        assert!(volume.read_runs(&[run(Some(u64::MAX), 1)], 0, 512).is_err());
+        assert!(volume.read_runs(&[run(Some(0), u64::MAX)], 0, 512).is_err());
+        assert!(volume.read_runs(&[run(Some(0), 1)], u64::MAX, 512).is_err());
+    }
+
+    fn compressed_attribute(compression_unit: u16, data_size: u64) -> MftAttribute {
+        MftAttribute {
+            attribute_type: ATTRIBUTE_DATA,
+            attribute_length: 0,
+            non_resident: true,
+            name_length: 0,
+            // ATTRIBUTE_FLAG_COMPRESSED
+            flags: 0x0001,
+            attribute_id: 0,
+            name: String::new(),
+            content: Vec::new(),
+            // One compression unit: a cluster of LZNT1 data, then sparse
+            data_runs: vec![run(Some(5), 1), run(None, 15)],
+            starting_vcn: 0,
+            last_vcn: 15,
+            compression_unit,
+            data_size,
+            initialized_size: data_size,
+        }
+    }
+
+    #[test]
+    fn reads_compressed_attributes() {
+        let mut data = vec![0u8; 64 * 512];
+        let chunk = lznt1_chunk(true, &[0x08, b'a', b'b', b'c', 0x06, 0x20]);
+        data[5 * 512..5 * 512 + chunk.len()].copy_from_slice(&chunk);
+        let image = MemorySource::new(data);
+        let volume = volume(&image);
+
+        assert_eq!(volume.read_attribute(&compressed_attribute(4, 12)).unwrap(), b"abcabcabcabc");
+        let content = volume.read_attribute(&compressed_attribute(4, 8192)).unwrap();
+        assert_eq!(content.len(), 8192);
+        assert!(content[12..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn rejects_implausible_compressed_attributes() {
+        let image = MemorySource::new(vec![0; 64 * 512]);
+        let volume = volume(&image);
+
+        assert!(volume.read_attribute(&compressed_attribute(0, 12)).is_err());
+        assert!(volume.read_attribute(&compressed_attribute(5, 12)).is_err());
+        assert!(volume.read_attribute(&compressed_attribute(64, 12)).is_err());
+        assert!(volume.read_attribute(&compressed_attribute(4, u64::MAX)).is_err());
+    }
+
+    fn lznt1_chunk(compressed: bool, data: &[u8]) -> Vec<u8> {
+        let header = 0x3000 | if compressed { 0x8000 } else { 0 } | (data.len() as u16 - 1);
+        let mut chunk = header.to_le_bytes().to_vec();
+        chunk.extend(data);
+        chunk
+    }
+
+    #[test]
+    fn decompresses_lznt1() {
+        // Three literals, then 9 bytes from 3 back
+        let data = lznt1_chunk(true, &[0x08, b'a', b'b', b'c', 0x06, 0x20]);
+        assert_eq!(decompress_lznt1(&data, 4096).unwrap(), b"abcabcabcabc");
+        assert_eq!(decompress_lznt1(&data, 5).unwrap(), b"abcab");
+
+        let data = lznt1_chunk(false, b"stored");
+        assert_eq!(decompress_lznt1(&data, 4096).unwrap(), b"stored");
+    }
+
+    #[test]
+    fn lznt1_offsets_widen_as_the_chunk_fills() {
+        // After 17 bytes a back-reference has 5 offset bits and 11 length bits
+        let mut compressed = vec![0x00];
+        compressed.extend(b"abcdefgh");
+        compressed.push(0x00);
+        compressed.extend(b"ijklmnop");
+        compressed.extend([0x02, b'q', 0x00, 0x80]);
+        let output = decompress_lznt1(&lznt1_chunk(true, &compressed), 4096).unwrap();
+        assert_eq!(output, b"abcdefghijklmnopqabc");
+    }
+
+    #[test]
+    fn lznt1_chunks_before_the_last_fill_4k() {
+        let mut data = lznt1_chunk(false, b"ab");
+        // One literal repeated into a full chunk
+        data.extend(lznt1_chunk(true, &[0x02, b'c', 0xFC, 0x0F]));
+        data.extend(lznt1_chunk(false, b"d"));
+        data.extend([0, 0]);
+
+        let output = decompress_lznt1(&data, 3 * LZNT1_CHUNK_SIZE).unwrap();
+        assert_eq!(output.len(), 2 * LZNT1_CHUNK_SIZE + 1);
+        assert_eq!(&output[..2], b"ab");
+        assert!(output[2..LZNT1_CHUNK_SIZE].iter().all(|&byte| byte == 0));
+        assert!(output[LZNT1_CHUNK_SIZE..2 * LZNT1_CHUNK_SIZE].iter().all(|&byte| byte == b'c'));
+        assert_eq!(output[2 * LZNT1_CHUNK_SIZE], b'd');
+    }
+
+    #[test]
+    fn rejects_corrupt_lznt1() {
+        // Back-reference with nothing decompressed yet
+        assert!(decompress_lznt1(&lznt1_chunk(true, &[0x01, 0x00, 0x10]), 4096).is_err());
+        // Chunk longer than the data
+        let mut data = lznt1_chunk(false, b"abcdef");
+        data.truncate(5);
+        assert!(decompress_lznt1(&data, 4096).is_err());
+    }
+}
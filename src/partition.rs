@@ -0,0 +1,529 @@
+use anyhow::Result;
+use byteorder::{ByteOrder, LittleEndian};
+use tracing::{info, warn};
+
+use crate::image_source::ImageSource;
+
+const MBR_SIGNATURE: u16 = 0xAA55;
+const MBR_TABLE_OFFSET: usize = 446;
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const GPT_MIN_HEADER_SIZE: usize = 92;
+const GPT_MIN_ENTRY_SIZE: usize = 128;
+const GPT_MAX_ENTRY_SIZE: usize = 4096;
+const GPT_MAX_ENTRIES: usize = 1024;
+const MAX_LOGICAL_PARTITIONS: usize = 128;
+
+const GUID_EFI_SYSTEM: &str = "C12A7328-F81F-11D2-BA4B-00A0C93EC93B";
+const GUID_MICROSOFT_RESERVED: &str = "E3C9E316-0B5C-4DB8-817D-F92DF00215AE";
+const GUID_BASIC_DATA: &str = "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7";
+const GUID_WINDOWS_RECOVERY: &str = "DE94BBA4-06D1-4D40-A16A-BFD50179D6AC";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionScheme {
+    Mbr,
+    Gpt,
+    /// The image holds a bare filesystem without a partition table.
+    None,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartitionRole {
+    EfiSystem,
+    MicrosoftReserved,
+    BasicData,
+    Recovery,
+    Other(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileSystem {
+    Ntfs,
+    Fat,
+    ExFat,
+    Unknown,
+}
+
+#[derive(Debug, Clone)]
+pub struct Volume {
+    pub index: usize,
+    pub offset: u64,
+    pub size: u64,
+    pub scheme: PartitionScheme,
+    pub role: PartitionRole,
+    pub filesystem: FileSystem,
+    pub name: String,
+}
+
+impl Volume {
+    /// Short label used to tag timeline events, e.g. `Partition 3 (NTFS)`.
+    pub fn label(&self) -> String {
+        match self.scheme {
+            PartitionScheme::None => format!("Volume ({})", self.filesystem),
+            _ => format!("Partition {} ({})", self.index, self.filesystem),
+        }
+    }
+}
+
+impl std::fmt::Display for PartitionRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PartitionRole::EfiSystem => write!(f, "EFI System"),
+            PartitionRole::MicrosoftReserved => write!(f, "Microsoft Reserved"),
+            PartitionRole::BasicData => write!(f, "Basic Data"),
+            PartitionRole::Recovery => write!(f, "Recovery"),
+            PartitionRole::Other(kind) => write!(f, "{}", kind),
+        }
+    }
+}
+
+impl std::fmt::Display for FileSystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileSystem::Ntfs => write!(f, "NTFS"),
+            FileSystem::Fat => write!(f, "FAT"),
+            FileSystem::ExFat => write!(f, "exFAT"),
+            FileSystem::Unknown => write!(f, "unknown filesystem"),
+        }
+    }
+}
+
+/// Reads the partition table (GPT, else MBR with extended partitions) and
+/// returns every volume with its filesystem identified from its boot sector.
+/// An image without a partition table is returned as a single volume.
+pub fn discover_volumes(image: &dyn ImageSource) -> Result<Vec<Volume>> {
+    let sector_size = image.sector_size() as u64;
+    let mbr = image.read_bytes(0, 512)?;
+
+    let mut volumes = if LittleEndian::read_u16(&mbr[510..512]) != MBR_SIGNATURE
+        || identify_filesystem(&mbr) != FileSystem::Unknown
+    {
+        // A boot sector at offset 0 means a bare volume image, whose VBR
+        // also ends in 0x55AA
+        Vec::new()
+    } else if mbr_entries(&mbr).iter().any(|entry| entry.partition_type == 0xEE) {
+        match parse_gpt(image, sector_size) {
+            Ok(volumes) => volumes,
+            Err(e) => {
+                warn!("Failed to parse GPT, falling back to MBR: {}", e);
+                parse_mbr(image, &mbr, sector_size)?
+            }
+        }
+    } else {
+        parse_mbr(image, &mbr, sector_size)?
+    };
+
+    if volumes.is_empty() {
+        volumes.push(Volume {
+            index: 1,
+            offset: 0,
+            size: image.size(),
+            scheme: PartitionScheme::None,
+            role: PartitionRole::BasicData,
+            filesystem: identify_filesystem(&mbr),
+            name: String::new(),
+        });
+    }
+
+    for volume in &mut volumes {
+        if volume.filesystem == FileSystem::Unknown && volume.size >= 512 {
+            if let Ok(boot_sector) = image.read_bytes(volume.offset, 512) {
+                volume.filesystem = identify_filesystem(&boot_sector);
+            }
+        }
+        info!(
+            "Found {} at offset {} ({} bytes, {}{})",
+            volume.label(),
+            volume.offset,
+            volume.size,
+            volume.role,
+            if volume.name.is_empty() { String::new() } else { format!(", \"{}\"", volume.name) }
+        );
+    }
+
+    Ok(volumes)
+}
+
+struct MbrEntry {
+    partition_type: u8,
+    start_lba: u64,
+    sector_count: u64,
+}
+
+fn mbr_entries(sector: &[u8]) -> Vec<MbrEntry> {
+    (0..4)
+        .map(|i| {
+            let entry = &sector[MBR_TABLE_OFFSET + i * 16..MBR_TABLE_OFFSET + (i + 1) * 16];
+            MbrEntry {
+                partition_type: entry[4],
+                start_lba: LittleEndian::read_u32(&entry[8..12]) as u64,
+                sector_count: LittleEndian::read_u32(&entry[12..16]) as u64,
+            }
+        })
+        .filter(|entry| entry.partition_type != 0 && entry.sector_count != 0)
+        .collect()
+}
+
+fn is_extended(partition_type: u8) -> bool {
+    matches!(partition_type, 0x05 | 0x0F | 0x85)
+}
+
+fn parse_mbr(image: &dyn ImageSource, mbr: &[u8], sector_size: u64) -> Result<Vec<Volume>> {
+    let mut volumes = Vec::new();
+
+    for entry in mbr_entries(mbr) {
+        if is_extended(entry.partition_type) {
+            parse_extended(image, entry.start_lba, sector_size, &mut volumes);
+        } else {
+            push_mbr_volume(&mut volumes, &entry, entry.start_lba, sector_size);
+        }
+    }
+
+    Ok(volumes)
+}
+
+/// Walks the chain of extended boot records. Logical partitions are relative
+/// to their EBR, links to the next EBR relative to the extended partition.
+fn parse_extended(image: &dyn ImageSource, extended_lba: u64, sector_size: u64, volumes: &mut Vec<Volume>) {
+    let mut ebr_lba = extended_lba;
+
+    for _ in 0..MAX_LOGICAL_PARTITIONS {
+        let ebr = match image.read_bytes(ebr_lba * sector_size, 512) {
+            Ok(ebr) if LittleEndian::read_u16(&ebr[510..512]) == MBR_SIGNATURE => ebr,
+            _ => {
+                warn!("Invalid extended boot record at LBA {}", ebr_lba);
+                return;
+            }
+        };
+
+        let entries = mbr_entries(&ebr);
+        let mut next = None;
+        for entry in entries {
+            if is_extended(entry.partition_type) {
+                next = Some(extended_lba + entry.start_lba);
+            } else {
+                push_mbr_volume(volumes, &entry, ebr_lba + entry.start_lba, sector_size);
+            }
+        }
+
+        match next {
+            Some(lba) if lba != ebr_lba => ebr_lba = lba,
+            _ => return,
+        }
+    }
+}
+
+fn push_mbr_volume(volumes: &mut Vec<Volume>, entry: &MbrEntry, start_lba: u64, sector_size: u64) {
+    let role = match entry.partition_type {
+        0xEF => PartitionRole::EfiSystem,
+        0x27 => PartitionRole::Recovery,
+        0x07 | 0x0B | 0x0C | 0x0E | 0x01 | 0x04 | 0x06 => PartitionRole::BasicData,
+        other => PartitionRole::Other(format!("MBR type 0x{:02X}", other)),
+    };
+
+    volumes.push(Volume {
+        index: volumes.len() + 1,
+        offset: start_lba * sector_size,
+        size: entry.sector_count * sector_size,
+        scheme: PartitionScheme::Mbr,
+        role,
+        filesystem: FileSystem::Unknown,
+        name: String::new(),
+    });
+}
+
+/// Reads the primary GPT, or the backup in the last sector of the disk when
+/// the primary header or entry array is damaged.
+fn parse_gpt(image: &dyn ImageSource, sector_size: u64) -> Result<Vec<Volume>> {
+    let (table, entry_size) = match read_gpt_table(image, 1, sector_size) {
+        Ok(table) => table,
+        Err(e) => {
+            let backup_lba = (image.size() / sector_size).saturating_sub(1);
+            warn!("Invalid primary GPT ({}), trying the backup at LBA {}", e, backup_lba);
+            read_gpt_table(image, backup_lba, sector_size)
+                .map_err(|backup| anyhow::anyhow!("Invalid primary GPT ({}) and backup GPT ({})", e, backup))?
+        }
+    };
+
+    let mut volumes = Vec::new();
+
+    for entry in table.chunks_exact(entry_size) {
+        let type_guid = format_guid(&entry[..16]);
+        if entry[..16].iter().all(|&b| b == 0) {
+            continue;
+        }
+
+        let first_lba = LittleEndian::read_u64(&entry[32..40]);
+        let last_lba = LittleEndian::read_u64(&entry[40..48]);
+        let name_units: Vec<u16> = entry[56..128]
+            .chunks_exact(2)
+            .map(LittleEndian::read_u16)
+            .take_while(|&unit| unit != 0)
+            .collect();
+
+        let role = match type_guid.as_str() {
+            GUID_EFI_SYSTEM => PartitionRole::EfiSystem,
+            GUID_MICROSOFT_RESERVED => PartitionRole::MicrosoftReserved,
+            GUID_BASIC_DATA => PartitionRole::BasicData,
+            GUID_WINDOWS_RECOVERY => PartitionRole::Recovery,
+            _ => PartitionRole::Other(type_guid),
+        };
+
+        volumes.push(Volume {
+            index: volumes.len() + 1,
+            offset: first_lba * sector_size,
+            size: (last_lba.saturating_sub(first_lba) + 1) * sector_size,
+            scheme: PartitionScheme::Gpt,
+            role,
+            filesystem: FileSystem::Unknown,
+            name: String::from_utf16_lossy(&name_units),
+        });
+    }
+
+    Ok(volumes)
+}
+
+/// Reads the GPT header at `lba` and its partition entry array, checking
+/// the CRC32 of both. Returns the entry array and the size of one entry.
+fn read_gpt_table(image: &dyn ImageSource, lba: u64, sector_size: u64) -> Result<(Vec<u8>, usize)> {
+    let mut header = image.read_bytes(lba * sector_size, sector_size as usize)?;
+    if header[..8] != *GPT_SIGNATURE {
+        anyhow::bail!("Missing GPT header signature");
+    }
+
+    let header_size = LittleEndian::read_u32(&header[12..16]) as usize;
+    if !(GPT_MIN_HEADER_SIZE..=header.len()).contains(&header_size) {
+        anyhow::bail!("Invalid GPT header size {}", header_size);
+    }
+    // The header checksum is computed with its own field zeroed
+    let header_crc = LittleEndian::read_u32(&header[16..20]);
+    header[16..20].fill(0);
+    if crc32(&header[..header_size]) != header_crc {
+        anyhow::bail!("GPT header checksum mismatch");
+    }
+
+    let entries_lba = LittleEndian::read_u64(&header[72..80]);
+    let entry_count = LittleEndian::read_u32(&header[80..84]) as usize;
+    let entry_size = LittleEndian::read_u32(&header[84..88]) as usize;
+    let entries_crc = LittleEndian::read_u32(&header[88..92]);
+    if !(GPT_MIN_ENTRY_SIZE..=GPT_MAX_ENTRY_SIZE).contains(&entry_size) || entry_count > GPT_MAX_ENTRIES {
+        anyhow::bail!("Implausible GPT entry layout ({} entries of {} bytes)", entry_count, entry_size);
+    }
+
+    let entries_offset = entries_lba.checked_mul(sector_size)
+        .ok_or_else(|| anyhow::anyhow!("GPT entry array LBA {} is out of range", entries_lba))?;
+    let table = image.read_bytes(entries_offset, entry_count * entry_size)?;
+    if crc32(&table) != entries_crc {
+        anyhow::bail!("GPT partition entry array checksum mismatch");
+    }
+
+    Ok((table, entry_size))
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = flate2::Crc::new();
+    crc.update(data);
+    crc.sum()
+}
+
+/// Identifies the filesystem from the OEM ID and FAT type strings of a
+/// volume boot record.
+fn identify_filesystem(boot_sector: &[u8]) -> FileSystem {
+    if boot_sector.len() < 90 {
+        return FileSystem::Unknown;
+    }
+    if &boot_sector[3..11] == b"NTFS    " {
+        FileSystem::Ntfs
+    } else if &boot_sector[3..11] == b"EXFAT   " {
+        FileSystem::ExFat
+    } else if boot_sector[54..59] == *b"FAT12" || boot_sector[54..59] == *b"FAT16" || boot_sector[82..87] == *b"FAT32" {
+        FileSystem::Fat
+    } else {
+        FileSystem::Unknown
+    }
+}
+
+/// Formats a mixed-endian on-disk GUID in its canonical text form.
+fn format_guid(bytes: &[u8]) -> String {
+    format!(
+        "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{}",
+        LittleEndian::read_u32(&bytes[0..4]),
+        LittleEndian::read_u16(&bytes[4..6]),
+        LittleEndian::read_u16(&bytes[6..8]),
+        bytes[8],
+        bytes[9],
+        bytes[10..16].iter().map(|b| format!("{:02X}", b)).collect::<String>()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_source::MemorySource;
+
+    const SECTOR: usize = 512;
+    const DISK_SECTORS: usize = 4096;
+
+    fn set_entry(sector: &mut [u8], slot: usize, partition_type: u8, start_lba: u32, sector_count: u32) {
+        let entry = &mut sector[MBR_TABLE_OFFSET + slot * 16..MBR_TABLE_OFFSET + (slot + 1) * 16];
+        entry[4] = partition_type;
+        LittleEndian::write_u32(&mut entry[8..12], start_lba);
+        LittleEndian::write_u32(&mut entry[12..16], sector_count);
+    }
+
+    fn sector_mut(disk: &mut [u8], lba: usize) -> &mut [u8] {
+        let sector = &mut disk[lba * SECTOR..(lba + 1) * SECTOR];
+        LittleEndian::write_u16(&mut sector[510..512], MBR_SIGNATURE);
+        sector
+    }
+
+    fn ntfs_boot_sector(disk: &mut [u8], lba: usize) {
+        sector_mut(disk, lba)[3..11].copy_from_slice(b"NTFS    ");
+    }
+
+    #[test]
+    fn walks_the_extended_boot_record_chain() {
+        let mut disk = vec![0u8; 2048 * SECTOR];
+        let mbr = sector_mut(&mut disk, 0);
+        set_entry(mbr, 0, 0x07, 64, 100);
+        set_entry(mbr, 1, 0x0F, 200, 1000);
+        // Logical partitions are relative to their EBR, links to the
+        // extended partition
+        set_entry(sector_mut(&mut disk, 200), 0, 0x07, 10, 90);
+        set_entry(sector_mut(&mut disk, 200), 1, 0x05, 300, 200);
+        set_entry(sector_mut(&mut disk, 500), 0, 0x0C, 5, 100);
+        ntfs_boot_sector(&mut disk, 64);
+        ntfs_boot_sector(&mut disk, 210);
+
+        let volumes = discover_volumes(&MemorySource::new(disk)).unwrap();
+        let layout: Vec<_> = volumes.iter()
+            .map(|volume| (volume.index, volume.offset, volume.size, volume.filesystem))
+            .collect();
+        assert_eq!(layout, vec![
+            (1, 64 * 512, 100 * 512, FileSystem::Ntfs),
+            (2, 210 * 512, 90 * 512, FileSystem::Ntfs),
+            (3, 505 * 512, 100 * 512, FileSystem::Unknown),
+        ]);
+        assert!(volumes.iter().all(|volume| volume.scheme == PartitionScheme::Mbr));
+        assert_eq!(volumes[2].role, PartitionRole::BasicData);
+    }
+
+    #[test]
+    fn stops_at_an_extended_boot_record_linking_to_itself() {
+        let mut disk = vec![0u8; 1024 * SECTOR];
+        set_entry(sector_mut(&mut disk, 0), 0, 0x05, 100, 500);
+        set_entry(sector_mut(&mut disk, 100), 0, 0x07, 1, 50);
+        set_entry(sector_mut(&mut disk, 100), 1, 0x05, 0, 500);
+
+        let volumes = discover_volumes(&MemorySource::new(disk)).unwrap();
+        assert_eq!(volumes.len(), 1);
+        assert_eq!(volumes[0].offset, 101 * 512);
+    }
+
+    #[test]
+    fn bare_volumes_have_no_partition_table() {
+        let mut disk = vec![0u8; 64 * SECTOR];
+        ntfs_boot_sector(&mut disk, 0);
+
+        let volumes = discover_volumes(&MemorySource::new(disk)).unwrap();
+        assert_eq!(volumes.len(), 1);
+        assert_eq!(volumes[0].scheme, PartitionScheme::None);
+        assert_eq!(volumes[0].filesystem, FileSystem::Ntfs);
+    }
+
+    /// The mixed-endian on-disk form of a GUID, the inverse of `format_guid`.
+    fn guid_bytes(guid: &str) -> Vec<u8> {
+        let mut bytes: Vec<u8> = guid.split('-').collect::<String>().as_bytes()
+            .chunks(2)
+            .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+            .collect();
+        bytes[0..4].reverse();
+        bytes[4..6].reverse();
+        bytes[6..8].reverse();
+        bytes
+    }
+
+    fn gpt_entries() -> Vec<u8> {
+        let mut entries = vec![0u8; 128 * 128];
+        for (index, (type_guid, first, last, name)) in [
+            (GUID_EFI_SYSTEM, 34u64, 233u64, "EFI system partition"),
+            (GUID_BASIC_DATA, 266, 3499, "Basic data partition"),
+        ].into_iter().enumerate() {
+            let entry = &mut entries[index * 128..(index + 1) * 128];
+            entry[..16].copy_from_slice(&guid_bytes(type_guid));
+            LittleEndian::write_u64(&mut entry[32..40], first);
+            LittleEndian::write_u64(&mut entry[40..48], last);
+            for (unit, bytes) in name.encode_utf16().zip(entry[56..].chunks_exact_mut(2)) {
+                LittleEndian::write_u16(bytes, unit);
+            }
+        }
+        entries
+    }
+
+    fn write_gpt(disk: &mut [u8], header_lba: usize, entries_lba: usize, entry_size: u32, entries: &[u8]) {
+        disk[entries_lba * SECTOR..entries_lba * SECTOR + entries.len()].copy_from_slice(entries);
+        let header = &mut disk[header_lba * SECTOR..header_lba * SECTOR + 92];
+        header[..8].copy_from_slice(GPT_SIGNATURE);
+        LittleEndian::write_u32(&mut header[12..16], 92);
+        LittleEndian::write_u64(&mut header[72..80], entries_lba as u64);
+        LittleEndian::write_u32(&mut header[80..84], entries.len() as u32 / entry_size);
+        LittleEndian::write_u32(&mut header[84..88], entry_size);
+        LittleEndian::write_u32(&mut header[88..92], crc32(entries));
+        let header_crc = crc32(header);
+        LittleEndian::write_u32(&mut header[16..20], header_crc);
+    }
+
+    /// A protective MBR with primary and backup GPTs.
+    fn gpt_disk() -> Vec<u8> {
+        let mut disk = vec![0u8; DISK_SECTORS * SECTOR];
+        set_entry(sector_mut(&mut disk, 0), 0, 0xEE, 1, DISK_SECTORS as u32 - 1);
+        write_gpt(&mut disk, 1, 2, 128, &gpt_entries());
+        write_gpt(&mut disk, DISK_SECTORS - 1, DISK_SECTORS - 33, 128, &gpt_entries());
+        ntfs_boot_sector(&mut disk, 266);
+        disk
+    }
+
+    fn assert_gpt_volumes(volumes: &[Volume]) {
+        let layout: Vec<_> = volumes.iter()
+            .map(|volume| (volume.offset, volume.size, volume.role.clone(), volume.name.as_str(), volume.filesystem))
+            .collect();
+        assert_eq!(layout, vec![
+            (34 * 512, 200 * 512, PartitionRole::EfiSystem, "EFI system partition", FileSystem::Unknown),
+            (266 * 512, 3234 * 512, PartitionRole::BasicData, "Basic data partition", FileSystem::Ntfs),
+        ]);
+        assert!(volumes.iter().all(|volume| volume.scheme == PartitionScheme::Gpt));
+    }
+
+    #[test]
+    fn reads_the_gpt() {
+        assert_gpt_volumes(&discover_volumes(&MemorySource::new(gpt_disk())).unwrap());
+    }
+
+    #[test]
+    fn falls_back_to_the_backup_gpt() {
+        // Damaged entry array
+        let mut disk = gpt_disk();
+        disk[2 * SECTOR + 40] ^= 1;
+        assert_gpt_volumes(&discover_volumes(&MemorySource::new(disk)).unwrap());
+
+        // Damaged header
+        let mut disk = gpt_disk();
+        disk[SECTOR + 80] ^= 1;
+        assert_gpt_volumes(&discover_volumes(&MemorySource::new(disk.clone())).unwrap());
+
+        // Both damaged: the protective MBR entry is all that is left
+        disk[(DISK_SECTORS - 1) * SECTOR + 80] ^= 1;
+        let volumes = discover_volumes(&MemorySource::new(disk)).unwrap();
+        assert_eq!(volumes.len(), 1);
+        assert_eq!(volumes[0].scheme, PartitionScheme::Mbr);
+        assert_eq!(volumes[0].role, PartitionRole::Other("MBR type 0xEE".to_string()));
+    }
+
+    #[test]
+    fn rejects_implausible_gpt_entry_layouts() {
+        for (entry_size, entry_count) in [(64, 128), (8192, 4), (128, 2048)] {
+            let mut disk = vec![0u8; DISK_SECTORS * SECTOR];
+            write_gpt(&mut disk, 1, 2, entry_size, &vec![0u8; (entry_size * entry_count) as usize]);
+            assert!(read_gpt_table(&MemorySource::new(disk), 1, SECTOR as u64).is_err());
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+/// MFT record number of the root directory.
+pub const ROOT_RECORD: u64 = 5;
+/// Virtual directory that files with a broken parent chain are placed under.
+pub const ORPHAN_DIRECTORY: &str = "$OrphanFiles";
+
+/// Parent chains longer than this are treated as corrupt (or cyclic).
+const MAX_DEPTH: usize = 256;
+
+/// Splits a 64-bit file reference into its record number (low 48 bits) and
+/// sequence number (high 16 bits).
+pub fn split_file_reference(reference: u64) -> (u64, u16) {
+    (reference & 0x0000_FFFF_FFFF_FFFF, (reference >> 48) as u16)
+}
+
+struct NameEntry {
+    sequence_number: u16,
+    in_use: bool,
+    parent_reference: u64,
+    name: String,
+}
+
+/// Builds full paths from the parent directory references of $FILE_NAME
+/// attributes. A reference only links to its parent when the parent record
+/// still carries the referenced sequence number; otherwise the directory was
+/// deleted and its record reused, and the file is reported under
+/// `\$OrphanFiles`. Freeing a record increments its sequence number, so a
+/// deleted directory that was not reused is one ahead of its references.
+#[derive(Default)]
+pub struct PathResolver {
+    entries: HashMap<u64, NameEntry>,
+    directory_cache: HashMap<u64, String>,
+}
+
+impl PathResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the name of MFT record `record_number`.
+    pub fn add_entry(&mut self, record_number: u64, sequence_number: u16, in_use: bool,
+                     parent_reference: u64, name: &str) {
+        self.entries.insert(record_number, NameEntry {
+            sequence_number,
+            in_use,
+            parent_reference,
+            name: name.to_string(),
+        });
+    }
+
+    /// Returns the full path of `name` stored in the directory referenced by
+    /// `parent_reference`, e.g. `\Windows\System32\svchost.exe`.
+    pub fn resolve(&mut self, parent_reference: u64, name: &str) -> String {
+        let directory = self.resolve_directory(parent_reference);
+        if directory == "\\" {
+            format!("\\{}", name)
+        } else {
+            format!("{}\\{}", directory, name)
+        }
+    }
+
+    /// Resolves the path of the directory a file reference points at, caching
+    /// each directory along the way.
+    fn resolve_directory(&mut self, reference: u64) -> String {
+        let mut components = Vec::new();
+        let mut visited = Vec::new();
+        let mut current = reference;
+
+        let prefix = loop {
+            let (record_number, sequence_number) = split_file_reference(current);
+            if record_number == ROOT_RECORD {
+                break String::new();
+            }
+
+            let entry = match self.entries.get(&record_number) {
+                // A zero sequence number is used by old NTFS versions that
+                // did not record one
+                Some(entry) if sequence_number == 0
+                    || entry.sequence_number == sequence_number
+                    || (!entry.in_use && entry.sequence_number == sequence_number.wrapping_add(1)) => entry,
+                _ => break format!("\\{}", ORPHAN_DIRECTORY),
+            };
+            if let Some(path) = self.directory_cache.get(&record_number) {
+                break path.clone();
+            }
+            if visited.contains(&record_number) || visited.len() >= MAX_DEPTH {
+                break format!("\\{}", ORPHAN_DIRECTORY);
+            }
+
+            visited.push(record_number);
+            components.push(entry.name.clone());
+            current = entry.parent_reference;
+        };
+
+        // Cache every directory on the chain, from the top down
+        let mut path = prefix;
+        for (record_number, component) in visited.iter().zip(components.iter()).rev() {
+            path = format!("{}\\{}", path, component);
+            self.directory_cache.insert(*record_number, path.clone());
+        }
+
+        if path.is_empty() {
+            "\\".to_string()
+        } else {
+            path
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reference(record_number: u64, sequence_number: u16) -> u64 {
+        (sequence_number as u64) << 48 | record_number
+    }
+
+    /// `\Windows\System32` as records 30 and 31.
+    fn resolver() -> PathResolver {
+        let mut resolver = PathResolver::new();
+        resolver.add_entry(30, 2, true, reference(ROOT_RECORD, 5), "Windows");
+        resolver.add_entry(31, 7, true, reference(30, 2), "System32");
+        resolver
+    }
+
+    #[test]
+    fn resolves_paths_through_parent_references() {
+        let mut resolver = resolver();
+        assert_eq!(resolver.resolve(reference(31, 7), "svchost.exe"), "\\Windows\\System32\\svchost.exe");
+        assert_eq!(resolver.resolve(reference(ROOT_RECORD, 5), "pagefile.sys"), "\\pagefile.sys");
+        // Old NTFS versions store no sequence number
+        assert_eq!(resolver.resolve(reference(30, 0), "win.ini"), "\\Windows\\win.ini");
+        assert_eq!(split_file_reference(reference(31, 7)), (31, 7));
+    }
+
+    #[test]
+    fn caches_every_directory_on_the_chain() {
+        let mut resolver = resolver();
+        resolver.resolve(reference(31, 7), "svchost.exe");
+        assert_eq!(resolver.directory_cache.get(&30).map(String::as_str), Some("\\Windows"));
+        assert_eq!(resolver.directory_cache.get(&31).map(String::as_str), Some("\\Windows\\System32"));
+        // The root is never cached; it resolves without a lookup
+        assert!(!resolver.directory_cache.contains_key(&ROOT_RECORD));
+
+        // Later lookups stop at the cached directory
+        resolver.add_entry(30, 2, true, reference(ROOT_RECORD, 5), "Renamed");
+        assert_eq!(resolver.resolve(reference(31, 7), "cmd.exe"), "\\Windows\\System32\\cmd.exe");
+    }
+
+    #[test]
+    fn files_with_a_reused_parent_are_orphans() {
+        let mut resolver = resolver();
+        assert_eq!(resolver.resolve(reference(31, 6), "old.txt"), "\\$OrphanFiles\\old.txt");
+        assert_eq!(resolver.resolve(reference(99, 1), "lost.txt"), "\\$OrphanFiles\\lost.txt");
+    }
+
+    #[test]
+    fn files_in_deleted_directories_keep_their_path() {
+        let mut resolver = resolver();
+        // Deleting a directory moves its record one sequence number ahead
+        resolver.add_entry(40, 4, false, reference(30, 2), "Temp");
+        assert_eq!(resolver.resolve(reference(40, 3), "dropper.exe"), "\\Windows\\Temp\\dropper.exe");
+        // Deleted and reused since, or deleted more than once
+        resolver.add_entry(41, 4, true, reference(30, 2), "Reused");
+        resolver.add_entry(42, 5, false, reference(30, 2), "Twice");
+        assert_eq!(resolver.resolve(reference(41, 3), "a.txt"), "\\$OrphanFiles\\a.txt");
+        assert_eq!(resolver.resolve(reference(42, 3), "b.txt"), "\\$OrphanFiles\\b.txt");
+    }
+
+    #[test]
+    fn parent_cycles_end_under_orphan_files() {
+        let mut resolver = PathResolver::new();
+        resolver.add_entry(50, 1, true, reference(51, 1), "A");
+        resolver.add_entry(51, 1, true, reference(50, 1), "B");
+        assert_eq!(resolver.resolve(reference(50, 1), "file.txt"), "\\$OrphanFiles\\B\\A\\file.txt");
+
+        // Chains deeper than MAX_DEPTH are cut off the same way, even when
+        // they would reach the root
+        let mut resolver = PathResolver::new();
+        let last = 100 + MAX_DEPTH as u64 + 10;
+        for record_number in 100..last {
+            resolver.add_entry(record_number, 1, true, reference(record_number + 1, 1), "d");
+        }
+        resolver.add_entry(last, 1, true, reference(ROOT_RECORD, 5), "top");
+        assert!(resolver.resolve(reference(100, 1), "deep.txt").starts_with("\\$OrphanFiles\\"));
+        assert_eq!(resolver.resolve(reference(last, 1), "shallow.txt"), "\\top\\shallow.txt");
+    }
+}
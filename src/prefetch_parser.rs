@@ -1,144 +1,511 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use byteorder::{ByteOrder, LittleEndian};
 use chrono::{DateTime, Utc};
-use tracing::info;
+use tracing::{info, warn};
 
-use crate::disk_image::DiskImage;
 use crate::timeline::Timeline;
+use crate::util::{read_utf16_string, windows_time_to_utc};
+use crate::vfs::Vfs;
+use crate::xpress;
 
 const PREFETCH_SIGNATURE: &[u8; 4] = b"SCCA";
 const PREFETCH_HEADER_SIZE: usize = 84;
+const PREFETCH_GLOB: &str = r"Windows\Prefetch\*.pf";
 
+/// Windows 10 and later store prefetch files in a "MAM" container; the
+/// fourth byte names the compression format (4 = Xpress Huffman) and its top
+/// bit flags a CRC32 between the size and the data.
+const MAM_SIGNATURE: &[u8; 3] = b"MAM";
+const MAM_XPRESS_HUFFMAN: u8 = 4;
+const MAM_HAS_CHECKSUM: u8 = 0x80;
+/// Prefetch files are a few hundred KB at most; larger decompressed sizes
+/// come from corrupt headers and are refused before allocating.
+const MAX_PREFETCH_SIZE: usize = 16 * 1024 * 1024;
+
+const VERSION_XP: u32 = 17;
+const VERSION_VISTA: u32 = 23;
+const VERSION_WIN8: u32 = 26;
+const VERSION_WIN10: u32 = 30;
+const VERSION_WIN11: u32 = 31;
+
+/// Offsets into the file information that follows the header.
+const FILE_METRICS_OFFSET: usize = 84;
+const FILENAME_STRINGS_OFFSET: usize = 100;
+const VOLUMES_OFFSET: usize = 108;
+
+/// A decoded SCCA prefetch file.
 #[derive(Debug)]
-struct PrefetchHeader {
-    signature: [u8; 4],
-    version: u32,
-    magic: u32,
-    unknown: u32,
-    file_size: u32,
-    filename: String,
+struct PrefetchFile {
+    executable_name: String,
+    /// Hash of the executable's path, part of the prefetch file name.
     hash: u32,
-    unknown2: [u8; 16],
-    last_run_times: [u64; 8],
+    /// Most recent first; versions before 26 only record the last run.
+    last_run_times: Vec<DateTime<Utc>>,
     run_count: u32,
-    unknown3: [u8; 16],
-    volume_info: [u32; 8],
-    volume_paths: Vec<String>,
+    volumes: Vec<PrefetchVolume>,
+    /// Files loaded by the executable in its first seconds of running.
+    files: Vec<String>,
 }
 
-pub fn parse_prefetch_files(_disk_image: &DiskImage, timeline: &mut Timeline) -> Result<()> {
-    info!("Starting Prefetch file parsing...");
-    
-    // For MVP, we'll simulate parsing prefetch files since we can't directly access
-    // the file system structure from a raw disk image without proper NTFS mounting
-    // In a production version, this would need to locate and parse the actual .pf files
-    
-    parse_sample_prefetch_files(timeline)?;
-    
-    info!("Prefetch file parsing completed");
-    Ok(())
+impl PrefetchFile {
+    /// The full path of the executable when the file list includes it,
+    /// otherwise the (possibly truncated) name from the header.
+    fn executable_path(&self) -> &str {
+        let name = self.executable_name.to_uppercase();
+        self.files.iter()
+            .find(|file| {
+                let file_name = file.rsplit('\\').next().unwrap_or(file).to_uppercase();
+                file_name == name || (name.chars().count() == 29 && file_name.starts_with(&name))
+            })
+            .map(String::as_str)
+            .unwrap_or(&self.executable_name)
+    }
 }
 
-fn parse_sample_prefetch_files(timeline: &mut Timeline) -> Result<()> {
-    // For MVP, we'll simulate some prefetch file events
-    // In production, this would parse the actual .pf files from C:\Windows\Prefetch\
-    
-    let sample_prefetch_files = vec![
-        ("SVCHOST.EXE-E39A42F1.pf", "svchost.exe", "2024-01-15T12:30:00Z"),
-        ("EXPLORER.EXE-12345678.pf", "explorer.exe", "2024-01-15T14:15:30Z"),
-        ("CMD.EXE-E29B523A.pf", "cmd.exe", "2024-01-15T16:45:20Z"),
-        ("NOTEPAD.EXE-ABCD1234.pf", "notepad.exe", "2024-01-16T09:20:15Z"),
-        ("CHROME.EXE-56789012.pf", "chrome.exe", "2024-01-16T10:30:45Z"),
-    ];
-    
-    for (prefetch_file, executable_name, timestamp_str) in sample_prefetch_files {
-        if let Ok(timestamp) = DateTime::parse_from_rfc3339(timestamp_str) {
-            timeline.add_program_execution(
-                timestamp.with_timezone(&Utc),
-                executable_name,
-                prefetch_file
-            );
+#[derive(Debug)]
+struct PrefetchVolume {
+    device_path: String,
+    serial_number: u32,
+    creation_time: Option<DateTime<Utc>>,
+    /// Directories on the volume the executable accessed.
+    directories: Vec<String>,
+}
+
+impl std::fmt::Display for PrefetchVolume {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (serial {:04X}-{:04X}", self.device_path,
+               self.serial_number >> 16, self.serial_number & 0xFFFF)?;
+        if let Some(created) = self.creation_time {
+            write!(f, ", created {}", created.format("%Y-%m-%d %H:%M:%S UTC"))?;
         }
+        write!(f, ")")
     }
-    
-    Ok(())
 }
 
-// Production-ready prefetch parser (commented out for MVP)
-/*
-fn parse_prefetch_file(disk_image: &DiskImage, file_path: &str, timeline: &mut Timeline) -> Result<()> {
-    // This would be the actual implementation for parsing .pf files
-    // from the disk image
-    
-    // First, we'd need to locate the file in the NTFS file system
-    // Then parse the .pf file structure
-    
-    let header = parse_prefetch_header(disk_image, file_path)?;
-    
-    // Extract executable name from filename
-    let executable_name = extract_executable_name(&header.filename);
-    
-    // Add run events for each valid last run time
-    for &run_time in &header.last_run_times {
-        if run_time != 0 {
-            let timestamp = windows_time_to_utc(run_time);
-            timeline.add_program_execution(timestamp, &executable_name, file_path);
+pub fn parse_prefetch_files(vfs: &Vfs, timeline: &mut Timeline) -> Result<()> {
+    info!("Starting Prefetch file parsing...");
+
+    let prefetch_files = vfs.glob(PREFETCH_GLOB)?;
+    let mut parsed = 0;
+    for path in &prefetch_files {
+        let prefetch = vfs.read_file(path)
+            .and_then(|content| parse_prefetch_file(&content));
+        match prefetch {
+            Ok(prefetch) => {
+                add_execution_events(&prefetch, path, timeline);
+                parsed += 1;
+            }
+            Err(e) => warn!("Failed to parse {}: {:#}", path, e),
         }
     }
-    
+
+    info!("Parsed {} of {} prefetch files", parsed, prefetch_files.len());
     Ok(())
 }
 
-fn parse_prefetch_header(disk_image: &DiskImage, file_path: &str) -> Result<PrefetchHeader> {
-    // This would parse the actual prefetch file header
-    // For MVP, we'll skip the actual file system traversal
-    
-    // The header structure is:
-    // - 4 bytes: Signature ("SCCA")
-    // - 4 bytes: Version
-    // - 4 bytes: Magic
-    // - 4 bytes: Unknown
-    // - 4 bytes: File size
-    // - 60 bytes: Filename (null-terminated)
-    // - 4 bytes: Hash
-    // - 16 bytes: Unknown
-    // - 64 bytes: Last run times (8 x 8 bytes)
-    // - 4 bytes: Run count
-    // - 16 bytes: Unknown
-    // - 32 bytes: Volume info (8 x 4 bytes)
-    // - Variable: Volume paths
-    
-    Ok(PrefetchHeader {
-        signature: *PREFETCH_SIGNATURE,
-        version: 0,
-        magic: 0,
-        unknown: 0,
-        file_size: 0,
-        filename: String::new(),
-        hash: 0,
-        unknown2: [0; 16],
-        last_run_times: [0; 8],
-        run_count: 0,
-        unknown3: [0; 16],
-        volume_info: [0; 8],
-        volume_paths: Vec::new(),
-    })
+fn add_execution_events(prefetch: &PrefetchFile, path: &str, timeline: &mut Timeline) {
+    let volumes: Vec<String> = prefetch.volumes.iter()
+        .map(|volume| volume.to_string())
+        .collect();
+    let directories: Vec<String> = prefetch.volumes.iter()
+        .flat_map(|volume| volume.directories.iter().cloned())
+        .collect();
+
+    for &run_time in &prefetch.last_run_times {
+        let event = timeline.add_program_execution(
+            run_time,
+            prefetch.executable_path(),
+            prefetch.run_count,
+            path,
+        );
+        event.add_detail("Loaded files", prefetch.files.clone());
+        event.add_detail("Volumes", volumes.clone());
+        event.add_detail("Directories", directories.clone());
+        event.add_detail("Prefetch hash", vec![format!("{:08X}", prefetch.hash)]);
+    }
 }
 
-fn extract_executable_name(filename: &str) -> String {
-    // Extract executable name from prefetch filename
-    // Format: EXECUTABLE.EXE-HASH.pf
-    if let Some(dash_pos) = filename.rfind('-') {
-        if let Some(dot_pos) = filename.rfind('.') {
-            return filename[..dash_pos].to_lowercase();
+/// Parses a prefetch file, decompressing it first if it is a MAM container.
+fn parse_prefetch_file(content: &[u8]) -> Result<PrefetchFile> {
+    let data = if content.starts_with(MAM_SIGNATURE) {
+        decompress_mam(content)?
+    } else {
+        content.to_vec()
+    };
+
+    if data.len() < PREFETCH_HEADER_SIZE || &data[4..8] != PREFETCH_SIGNATURE {
+        anyhow::bail!("Not a prefetch file (missing SCCA signature)");
+    }
+
+    let version = LittleEndian::read_u32(&data[0..4]);
+    let (run_times_offset, run_time_count, run_count_offset, volume_entry_size) = match version {
+        VERSION_XP => (120, 1, 144, 40),
+        VERSION_VISTA => (128, 1, 152, 104),
+        VERSION_WIN8 => (128, 8, 208, 104),
+        VERSION_WIN10 | VERSION_WIN11 => {
+            // Two layouts exist; the shorter file information moves the
+            // run count 8 bytes back and the file metrics with it
+            let metrics_offset = read_u32_at(&data, FILE_METRICS_OFFSET)?;
+            let run_count_offset = if metrics_offset == 0x128 { 200 } else { 208 };
+            (128, 8, run_count_offset, 96)
+        }
+        _ => anyhow::bail!("Unsupported prefetch version {}", version),
+    };
+
+    let executable_name = read_utf16_string(&data[16..76]);
+    let hash = LittleEndian::read_u32(&data[76..80]);
+
+    let mut last_run_times = Vec::new();
+    for index in 0..run_time_count {
+        let run_time = read_u64_at(&data, run_times_offset + index * 8)?;
+        if let Some(run_time) = windows_time_to_utc(run_time) {
+            last_run_times.push(run_time);
         }
     }
-    filename.to_string()
+    let run_count = read_u32_at(&data, run_count_offset)?;
+
+    let files = parse_filename_strings(&data)
+        .context("Failed to read the file list")?;
+    let volumes = parse_volumes(&data, volume_entry_size)
+        .context("Failed to read the volume information")?;
+
+    Ok(PrefetchFile {
+        executable_name,
+        hash,
+        last_run_times,
+        run_count,
+        volumes,
+        files,
+    })
+}
+
+/// Unpacks a Windows 10+ MAM container: the signature, the decompressed size,
+/// an optional CRC32 and the Xpress Huffman compressed prefetch file.
+fn decompress_mam(content: &[u8]) -> Result<Vec<u8>> {
+    if content.len() < 8 {
+        anyhow::bail!("Truncated MAM header");
+    }
+    let format = content[3];
+    if format & !MAM_HAS_CHECKSUM != MAM_XPRESS_HUFFMAN {
+        anyhow::bail!("Unsupported MAM compression format {}", format & !MAM_HAS_CHECKSUM);
+    }
+
+    let size = LittleEndian::read_u32(&content[4..8]) as usize;
+    if size > MAX_PREFETCH_SIZE {
+        anyhow::bail!("MAM decompressed size {} exceeds the {} byte limit", size, MAX_PREFETCH_SIZE);
+    }
+    let data_offset = if format & MAM_HAS_CHECKSUM != 0 { 12 } else { 8 };
+    let data = content.get(data_offset..)
+        .ok_or_else(|| anyhow::anyhow!("Truncated MAM header"))?;
+
+    xpress::decompress_xpress_huffman(data, size)
+        .context("Failed to decompress MAM prefetch file")
+}
+
+/// Reads the NUL-separated UTF-16 paths of the files the executable loaded.
+fn parse_filename_strings(data: &[u8]) -> Result<Vec<String>> {
+    let offset = read_u32_at(data, FILENAME_STRINGS_OFFSET)? as usize;
+    let size = read_u32_at(data, FILENAME_STRINGS_OFFSET + 4)? as usize;
+    let strings = data.get(offset..offset + size)
+        .ok_or_else(|| anyhow::anyhow!("Filename strings extend beyond the file"))?;
+
+    let units: Vec<u16> = strings.chunks_exact(2)
+        .map(LittleEndian::read_u16)
+        .collect();
+    Ok(units.split(|&unit| unit == 0)
+        .filter(|name| !name.is_empty())
+        .map(String::from_utf16_lossy)
+        .collect())
+}
+
+/// Reads the device path, serial number, creation time and accessed
+/// directories of each volume the executable loaded files from.
+fn parse_volumes(data: &[u8], entry_size: usize) -> Result<Vec<PrefetchVolume>> {
+    let offset = read_u32_at(data, VOLUMES_OFFSET)? as usize;
+    let count = read_u32_at(data, VOLUMES_OFFSET + 4)? as usize;
+
+    let mut volumes = Vec::new();
+    for index in 0..count {
+        let entry = offset + index * entry_size;
+        let path_offset = offset + read_u32_at(data, entry)? as usize;
+        let path_length = read_u32_at(data, entry + 4)? as usize;
+        let device_path = data.get(path_offset..path_offset + path_length * 2)
+            .map(read_utf16_string)
+            .ok_or_else(|| anyhow::anyhow!("Volume device path extends beyond the file"))?;
+
+        let directories_offset = offset + read_u32_at(data, entry + 28)? as usize;
+        let directory_count = read_u32_at(data, entry + 32)? as usize;
+
+        volumes.push(PrefetchVolume {
+            device_path,
+            creation_time: windows_time_to_utc(read_u64_at(data, entry + 8)?),
+            serial_number: read_u32_at(data, entry + 16)?,
+            directories: parse_directory_strings(data, directories_offset, directory_count)?,
+        });
+    }
+
+    Ok(volumes)
+}
+
+/// Reads `count` directory strings, each a character count followed by the
+/// NUL-terminated UTF-16 path.
+fn parse_directory_strings(data: &[u8], offset: usize, count: usize) -> Result<Vec<String>> {
+    let mut directories = Vec::with_capacity(count.min(1024));
+    let mut position = offset;
+    for _ in 0..count {
+        let length = data.get(position..position + 2)
+            .map(LittleEndian::read_u16)
+            .ok_or_else(|| anyhow::anyhow!("Directory strings extend beyond the file"))? as usize;
+        let directory = data.get(position + 2..position + 2 + length * 2)
+            .ok_or_else(|| anyhow::anyhow!("Directory strings extend beyond the file"))?;
+        directories.push(read_utf16_string(directory));
+        position += 2 + (length + 1) * 2;
+    }
+    Ok(directories)
+}
+
+fn read_u32_at(data: &[u8], offset: usize) -> Result<u32> {
+    data.get(offset..offset + 4)
+        .map(LittleEndian::read_u32)
+        .ok_or_else(|| anyhow::anyhow!("Truncated prefetch file"))
+}
+
+fn read_u64_at(data: &[u8], offset: usize) -> Result<u64> {
+    data.get(offset..offset + 8)
+        .map(LittleEndian::read_u64)
+        .ok_or_else(|| anyhow::anyhow!("Truncated prefetch file"))
 }
 
-fn windows_time_to_utc(windows_time: u64) -> DateTime<Utc> {
-    // Windows FILETIME is 100-nanosecond intervals since 1601-01-01
-    // Convert to Unix timestamp (seconds since 1970-01-01)
-    let unix_seconds = (windows_time as i64 - 116444736000000000) / 10000000;
-    Utc.timestamp_opt(unix_seconds, 0).unwrap_or(Utc::now())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeline::EventType;
+    use crate::xpress::tests::{encode_block, Token};
+
+    /// 2024-01-15 10:02:03.4567890 UTC as a FILETIME.
+    const LAST_RUN: u64 = 133_497_865_234_567_890;
+    const HOUR: u64 = 3600 * 10_000_000;
+    const HASH: u32 = 0xD841_4F97;
+    const FILENAME_STRINGS: usize = 0x200;
+    const VOLUMES: usize = 0x300;
+    const VOLUME_PATH: &str = "\\VOLUME{01d9a1b2c3d4e5f6-8e1c2a3b}";
+    const LOADED_FILES: [&str; 2] = [
+        "\\VOLUME{01d9a1b2c3d4e5f6-8e1c2a3b}\\WINDOWS\\SYSTEM32\\NTDLL.DLL",
+        "\\VOLUME{01d9a1b2c3d4e5f6-8e1c2a3b}\\WINDOWS\\SYSTEM32\\NOTEPAD.EXE",
+    ];
+    const DIRECTORIES: [&str; 2] = [
+        "\\VOLUME{01d9a1b2c3d4e5f6-8e1c2a3b}\\WINDOWS",
+        "\\VOLUME{01d9a1b2c3d4e5f6-8e1c2a3b}\\WINDOWS\\SYSTEM32",
+    ];
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn put_u32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// An uncompressed SCCA file of `version` for NOTEPAD.EXE, run 42 times
+    /// with the last run at `LAST_RUN` and earlier runs an hour apart, that
+    /// loaded `LOADED_FILES` from one volume. Version 30 files use the
+    /// layout whose file metrics start at `metrics_offset`.
+    fn scca(version: u32, metrics_offset: u32) -> Vec<u8> {
+        let (run_times_offset, run_time_count, run_count_offset, volume_entry_size) = match version {
+            VERSION_XP => (120, 1, 144, 40),
+            VERSION_VISTA => (128, 1, 152, 104),
+            VERSION_WIN8 => (128, 8, 208, 104),
+            _ if metrics_offset == 0x128 => (128, 8, 200, 96),
+            _ => (128, 8, 208, 96),
+        };
+
+        let mut data = vec![0u8; 0x500];
+        put_u32(&mut data, 0, version);
+        data[4..8].copy_from_slice(PREFETCH_SIGNATURE);
+        let name = utf16("NOTEPAD.EXE");
+        data[16..16 + name.len()].copy_from_slice(&name);
+        put_u32(&mut data, 76, HASH);
+        put_u32(&mut data, FILE_METRICS_OFFSET, metrics_offset);
+
+        for index in 0..run_time_count {
+            let run_time = LAST_RUN - index as u64 * HOUR;
+            let offset = run_times_offset + index * 8;
+            data[offset..offset + 8].copy_from_slice(&run_time.to_le_bytes());
+        }
+        put_u32(&mut data, run_count_offset, 42);
+
+        let strings: Vec<u8> = LOADED_FILES.iter().flat_map(|file| utf16(&format!("{}\0", file))).collect();
+        data[FILENAME_STRINGS..FILENAME_STRINGS + strings.len()].copy_from_slice(&strings);
+        put_u32(&mut data, FILENAME_STRINGS_OFFSET, FILENAME_STRINGS as u32);
+        put_u32(&mut data, FILENAME_STRINGS_OFFSET + 4, strings.len() as u32);
+
+        // One volume entry, then its device path and directory strings
+        put_u32(&mut data, VOLUMES_OFFSET, VOLUMES as u32);
+        put_u32(&mut data, VOLUMES_OFFSET + 4, 1);
+        let path = utf16(VOLUME_PATH);
+        let path_offset = volume_entry_size;
+        data[VOLUMES + path_offset..VOLUMES + path_offset + path.len()].copy_from_slice(&path);
+        put_u32(&mut data, VOLUMES, path_offset as u32);
+        put_u32(&mut data, VOLUMES + 4, VOLUME_PATH.encode_utf16().count() as u32);
+        data[VOLUMES + 8..VOLUMES + 16].copy_from_slice(&(LAST_RUN - 1000 * HOUR).to_le_bytes());
+        put_u32(&mut data, VOLUMES + 16, 0x8E1C_2A3B);
+
+        let directories_offset = path_offset + path.len() + 2;
+        let mut position = VOLUMES + directories_offset;
+        for directory in DIRECTORIES {
+            let units = directory.encode_utf16().count();
+            data[position..position + 2].copy_from_slice(&(units as u16).to_le_bytes());
+            data[position + 2..position + 2 + units * 2].copy_from_slice(&utf16(directory));
+            position += 2 + (units + 1) * 2;
+        }
+        put_u32(&mut data, VOLUMES + 28, directories_offset as u32);
+        put_u32(&mut data, VOLUMES + 32, DIRECTORIES.len() as u32);
+        data
+    }
+
+    #[test]
+    fn parses_every_prefetch_version() {
+        for (version, run_times) in [(VERSION_XP, 1), (VERSION_VISTA, 1), (VERSION_WIN8, 8),
+                                     (VERSION_WIN10, 8), (VERSION_WIN11, 8)] {
+            let prefetch = parse_prefetch_file(&scca(version, 0x130)).unwrap();
+            assert_eq!(prefetch.executable_name, "NOTEPAD.EXE", "version {}", version);
+            assert_eq!(prefetch.hash, HASH, "version {}", version);
+            assert_eq!(prefetch.run_count, 42, "version {}", version);
+            assert_eq!(prefetch.last_run_times.len(), run_times, "version {}", version);
+            assert_eq!(prefetch.last_run_times[0].to_rfc3339(), "2024-01-15T10:02:03.456789+00:00");
+            assert_eq!(prefetch.files, LOADED_FILES, "version {}", version);
+            assert_eq!(prefetch.volumes.len(), 1, "version {}", version);
+            assert_eq!(prefetch.volumes[0].device_path, VOLUME_PATH, "version {}", version);
+            assert_eq!(prefetch.volumes[0].serial_number, 0x8E1C_2A3B, "version {}", version);
+        }
+    }
+
+    #[test]
+    fn reads_the_run_count_of_both_windows_10_layouts() {
+        for metrics_offset in [0x128, 0x130] {
+            let mut data = scca(VERSION_WIN10, metrics_offset);
+            // A different value where the other layout keeps the run count
+            let other = if metrics_offset == 0x128 { 208 } else { 200 };
+            put_u32(&mut data, other, 7);
+
+            let prefetch = parse_prefetch_file(&data).unwrap();
+            assert_eq!(prefetch.run_count, 42, "metrics at {:#x}", metrics_offset);
+            assert_eq!(prefetch.last_run_times.len(), 8);
+            assert_eq!(prefetch.last_run_times[7].to_rfc3339(), "2024-01-15T03:02:03.456789+00:00");
+        }
+    }
+
+    #[test]
+    fn reports_the_full_executable_path() {
+        let prefetch = parse_prefetch_file(&scca(VERSION_WIN8, 0)).unwrap();
+        assert_eq!(prefetch.executable_path(), LOADED_FILES[1]);
+    }
+
+    #[test]
+    fn rejects_unsupported_and_truncated_files() {
+        assert!(parse_prefetch_file(&scca(24, 0)).is_err());
+        let mut data = scca(VERSION_WIN8, 0);
+        data[4..8].copy_from_slice(b"XXXX");
+        assert!(parse_prefetch_file(&data).is_err());
+        assert!(parse_prefetch_file(&scca(VERSION_WIN8, 0)[..150]).is_err());
+        // File list past the end of the file
+        let mut data = scca(VERSION_WIN8, 0);
+        put_u32(&mut data, FILENAME_STRINGS_OFFSET, 0x4F0);
+        assert!(parse_prefetch_file(&data).is_err());
+    }
+
+    #[test]
+    fn reads_volume_information_and_directories() {
+        let prefetch = parse_prefetch_file(&scca(VERSION_VISTA, 0)).unwrap();
+        let volume = &prefetch.volumes[0];
+        assert_eq!(volume.directories, DIRECTORIES);
+        assert_eq!(volume.to_string(),
+                   "\\VOLUME{01d9a1b2c3d4e5f6-8e1c2a3b} (serial 8E1C-2A3B, created 2023-12-04 18:02:03 UTC)");
+
+        let mut data = scca(VERSION_VISTA, 0);
+        data[VOLUMES + 8..VOLUMES + 16].fill(0);
+        let prefetch = parse_prefetch_file(&data).unwrap();
+        assert_eq!(prefetch.volumes[0].to_string(), "\\VOLUME{01d9a1b2c3d4e5f6-8e1c2a3b} (serial 8E1C-2A3B)");
+    }
+
+    #[test]
+    fn rejects_truncated_directory_strings() {
+        let data = scca(VERSION_WIN8, 0);
+        let offset = VOLUMES + 104 + VOLUME_PATH.len() * 2 + 2;
+        assert_eq!(parse_directory_strings(&data, offset, 2).unwrap(), DIRECTORIES);
+        // A length running past the end, and more strings than the file holds
+        let mut truncated = data.clone();
+        truncated[offset..offset + 2].copy_from_slice(&0x7FFFu16.to_le_bytes());
+        assert!(parse_directory_strings(&truncated, offset, 1).is_err());
+        assert!(parse_directory_strings(&data, offset, 1000).is_err());
+
+        let mut data = scca(VERSION_WIN8, 0);
+        put_u32(&mut data, VOLUMES + 32, 1000);
+        assert!(parse_prefetch_file(&data).is_err());
+    }
+
+    #[test]
+    fn adds_an_execution_event_per_run_time() {
+        let prefetch = parse_prefetch_file(&scca(VERSION_WIN11, 0x130)).unwrap();
+        let mut timeline = Timeline::new();
+        add_execution_events(&prefetch, r"Windows\Prefetch\NOTEPAD.EXE-D8414F97.pf", &mut timeline);
+
+        assert_eq!(timeline.events.len(), 8);
+        for event in &timeline.events {
+            assert!(matches!(event.event_type, EventType::ProgramExecution));
+            assert_eq!(event.description, format!("Executable '{}' was run (run count 42).", LOADED_FILES[1]));
+            assert_eq!(event.source_artifact, r"Windows\Prefetch\NOTEPAD.EXE-D8414F97.pf");
+            let details: Vec<(&str, &[String])> = event.details.iter()
+                .map(|detail| (detail.label.as_str(), detail.values.as_slice()))
+                .collect();
+            assert_eq!(details, [
+                ("Loaded files", &LOADED_FILES.map(String::from)[..]),
+                ("Volumes", &[prefetch.volumes[0].to_string()][..]),
+                ("Directories", &DIRECTORIES.map(String::from)[..]),
+                ("Prefetch hash", &["D8414F97".to_string()][..]),
+            ]);
+        }
+        assert_eq!(timeline.events[7].timestamp.to_rfc3339(), "2024-01-15T03:02:03.456789+00:00");
+    }
+
+    #[test]
+    fn parses_mam_compressed_prefetch_files() {
+        let data = scca(VERSION_WIN10, 0x130);
+        let literals: Vec<Token> = data.iter().map(|&byte| Token::Literal(byte)).collect();
+        let content = mam(MAM_XPRESS_HUFFMAN | MAM_HAS_CHECKSUM, data.len() as u32, &encode_block(&literals));
+
+        let prefetch = parse_prefetch_file(&content).unwrap();
+        assert_eq!(prefetch.run_count, 42);
+        assert_eq!(prefetch.files, LOADED_FILES);
+    }
+
+    fn mam(format: u8, size: u32, compressed: &[u8]) -> Vec<u8> {
+        let mut content = MAM_SIGNATURE.to_vec();
+        content.push(format);
+        content.extend(size.to_le_bytes());
+        if format & MAM_HAS_CHECKSUM != 0 {
+            content.extend([0xDE, 0xAD, 0xBE, 0xEF]);
+        }
+        content.extend(compressed);
+        content
+    }
+
+    fn compressed_scca() -> Vec<u8> {
+        encode_block(&PREFETCH_SIGNATURE.map(Token::Literal))
+    }
+
+    #[test]
+    fn unpacks_mam_containers_with_and_without_checksum() {
+        for format in [MAM_XPRESS_HUFFMAN, MAM_XPRESS_HUFFMAN | MAM_HAS_CHECKSUM] {
+            assert_eq!(decompress_mam(&mam(format, 4, &compressed_scca())).unwrap(), PREFETCH_SIGNATURE);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_mam_headers() {
+        assert!(decompress_mam(b"MAM\x04\x04\0").is_err());
+        // Plain Xpress
+        assert!(decompress_mam(&mam(3, 4, &compressed_scca())).is_err());
+        assert!(decompress_mam(&mam(MAM_XPRESS_HUFFMAN, MAX_PREFETCH_SIZE as u32 + 1, &compressed_scca())).is_err());
+    }
 }
-*/ 
\ No newline at end of file
@@ -0,0 +1,1324 @@
+use anyhow::{Context, Result};
+use byteorder::{ByteOrder, LittleEndian};
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+use tracing::{info, warn};
+
+use crate::bam;
+use crate::shellbags;
+use crate::timeline::{EventType, Timeline, TimelineEvent};
+use crate::user_activity;
+use crate::util::{is_plausible_time, read_utf16_string, windows_time_to_utc};
+use crate::vfs::Vfs;
+
+const REGF_SIGNATURE: &[u8; 4] = b"regf";
+const HBIN_SIGNATURE: &[u8; 4] = b"hbin";
+const HBIN_HEADER_SIZE: usize = 32;
+/// Cells are 8-byte aligned within their hive bin.
+const CELL_ALIGNMENT: usize = 8;
+const BASE_BLOCK_SIZE: usize = 4096;
+/// Transaction logs only keep the first sector of the base block.
+const LOG_BASE_BLOCK_SIZE: usize = 512;
+
+/// Offsets into the base block.
+const PRIMARY_SEQUENCE_OFFSET: usize = 4;
+const SECONDARY_SEQUENCE_OFFSET: usize = 8;
+const LAST_WRITTEN_OFFSET: usize = 12;
+const MINOR_VERSION_OFFSET: usize = 24;
+const FILE_TYPE_OFFSET: usize = 28;
+const ROOT_CELL_OFFSET: usize = 36;
+const HIVE_BINS_SIZE_OFFSET: usize = 40;
+const CHECKSUM_OFFSET: usize = 508;
+
+/// Base block file types of transaction logs: the dirty vector format used
+/// up to Windows 8, and the log entry format of Windows 8.1 and later.
+const FILE_TYPE_LOG_OLD: u32 = 1;
+const FILE_TYPE_LOG_NEW: u32 = 6;
+
+const DIRTY_VECTOR_SIGNATURE: &[u8; 4] = b"DIRT";
+const OLD_LOG_PAGE_SIZE: usize = 512;
+const LOG_ENTRY_SIGNATURE: &[u8; 4] = b"HvLE";
+const LOG_ENTRY_HEADER_SIZE: usize = 40;
+const MARVIN32_SEED: u64 = 0x82EF4D887A4E55C5;
+
+/// Key node flags.
+const KEY_COMP_NAME: u16 = 0x0020;
+/// Value flags.
+const VALUE_COMP_NAME: u16 = 0x0001;
+
+/// Value data of at most four bytes is stored in the data offset field,
+/// flagged by the top bit of the size.
+const DATA_IN_OFFSET: u32 = 0x8000_0000;
+/// Larger values are split into "db" segments from hive version 1.4 on.
+const BIG_DATA_SEGMENT_SIZE: usize = 16344;
+const BIG_DATA_MINOR_VERSION: u32 = 4;
+
+pub const REG_NONE: u32 = 0;
+pub const REG_SZ: u32 = 1;
+pub const REG_EXPAND_SZ: u32 = 2;
+pub const REG_BINARY: u32 = 3;
+pub const REG_DWORD: u32 = 4;
+pub const REG_DWORD_BIG_ENDIAN: u32 = 5;
+pub const REG_LINK: u32 = 6;
+pub const REG_MULTI_SZ: u32 = 7;
+pub const REG_QWORD: u32 = 11;
+
+/// Recovered key names longer than this are taken for false matches.
+const MAX_KEY_NAME_LENGTH: usize = 512;
+
+/// Values attached to a key event are cut to this many characters.
+const VALUE_TEXT_LIMIT: usize = 120;
+/// Binary data is shown as hex up to this many bytes.
+const BINARY_PREVIEW_LIMIT: usize = 32;
+
+/// The hives parsed from a volume, by path relative to the volume root.
+/// `*` is the profile folder of NTUSER.DAT and UsrClass.dat.
+const HIVE_LOCATIONS: &[(&str, HiveKind)] = &[
+    (r"Windows\System32\config\SYSTEM", HiveKind::System),
+    (r"Windows\System32\config\SOFTWARE", HiveKind::Software),
+    (r"Windows\System32\config\SAM", HiveKind::Sam),
+    (r"Windows\System32\config\SECURITY", HiveKind::Security),
+    (r"Users\*\NTUSER.DAT", HiveKind::NtUser),
+    (r"Users\*\AppData\Local\Microsoft\Windows\UsrClass.dat", HiveKind::UsrClass),
+    (r"Windows\AppCompat\Programs\Amcache.hve", HiveKind::Amcache),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HiveKind {
+    System,
+    Software,
+    Sam,
+    Security,
+    NtUser,
+    UsrClass,
+    Amcache,
+}
+
+/// A registry hive read from the volume, with its transaction logs applied.
+pub struct HiveFile {
+    /// Path of the hive file within the volume.
+    pub path: String,
+    pub kind: HiveKind,
+    /// Profile folder name for per-user hives.
+    pub user: Option<String>,
+    pub hive: Hive,
+}
+
+impl HiveFile {
+    /// Prefix of the key paths of this hive as seen on a live system, e.g.
+    /// `HKLM\SYSTEM` or `HKU\alice`.
+    pub fn root_path(&self) -> String {
+        let user = self.user.as_deref().unwrap_or("unknown");
+        match self.kind {
+            HiveKind::System => r"HKLM\SYSTEM".to_string(),
+            HiveKind::Software => r"HKLM\SOFTWARE".to_string(),
+            HiveKind::Sam => r"HKLM\SAM".to_string(),
+            HiveKind::Security => r"HKLM\SECURITY".to_string(),
+            HiveKind::NtUser => format!(r"HKU\{}", user),
+            HiveKind::UsrClass => format!(r"HKU\{}_Classes", user),
+            HiveKind::Amcache => "Amcache".to_string(),
+        }
+    }
+}
+
+/// An offline regf hive: the base block followed by the hive bins.
+pub struct Hive {
+    data: Vec<u8>,
+}
+
+impl Hive {
+    pub fn new(data: Vec<u8>) -> Result<Self> {
+        if data.len() < BASE_BLOCK_SIZE || &data[0..4] != REGF_SIGNATURE {
+            anyhow::bail!("Not a registry hive (missing regf signature)");
+        }
+        Ok(Hive { data })
+    }
+
+    /// A hive is dirty when a write to it was not completed; the missing
+    /// changes are in its transaction logs.
+    pub fn is_dirty(&self) -> bool {
+        self.base_u32(PRIMARY_SEQUENCE_OFFSET) != self.base_u32(SECONDARY_SEQUENCE_OFFSET)
+    }
+
+    fn base_u32(&self, offset: usize) -> u32 {
+        LittleEndian::read_u32(&self.data[offset..offset + 4])
+    }
+
+    /// When the hive file was last written, from its base block.
+    pub fn last_written(&self) -> Option<DateTime<Utc>> {
+        windows_time_to_utc(LittleEndian::read_u64(&self.data[LAST_WRITTEN_OFFSET..LAST_WRITTEN_OFFSET + 8]))
+    }
+
+    fn minor_version(&self) -> u32 {
+        self.base_u32(MINOR_VERSION_OFFSET)
+    }
+
+    /// Checks the XOR checksum of the first 508 bytes of the base block.
+    pub fn has_valid_checksum(&self) -> bool {
+        base_block_checksum(&self.data) == self.base_u32(CHECKSUM_OFFSET)
+    }
+
+    /// Replays the dirty pages of the hive's .LOG1/.LOG2 files onto a dirty
+    /// hive. Returns the number of pages written into the hive.
+    pub fn apply_transaction_logs(&mut self, logs: &[Vec<u8>]) -> Result<usize> {
+        let mut new_format_entries = Vec::new();
+        let mut old_format_logs = Vec::new();
+        let mut applied = 0;
+        if !self.is_dirty() {
+            return Ok(0);
+        }
+
+        // A log can only grow the hive by what it holds itself
+        let size_limit = self.data.len() + logs.iter().map(Vec::len).max().unwrap_or(0);
+
+        for log in logs {
+            if log.len() < LOG_BASE_BLOCK_SIZE || &log[0..4] != REGF_SIGNATURE {
+                continue;
+            }
+            match LittleEndian::read_u32(&log[FILE_TYPE_OFFSET..FILE_TYPE_OFFSET + 4]) {
+                FILE_TYPE_LOG_NEW if !has_valid_log_checksum(log) => {
+                    warn!("Skipping a transaction log whose base block checksum does not match");
+                }
+                FILE_TYPE_LOG_NEW => new_format_entries.extend(read_log_entries(log)),
+                FILE_TYPE_LOG_OLD => old_format_logs.push(log),
+                _ => {}
+            }
+        }
+
+        // Each old-format log holds the pages of one write; replay those not
+        // older than the last write the hive file completed, oldest first,
+        // so the pages of the newest write win whatever the file order
+        let secondary_sequence = self.base_u32(SECONDARY_SEQUENCE_OFFSET);
+        old_format_logs.sort_by_key(|log| log_sequence(log));
+        for log in old_format_logs.into_iter().filter(|log| log_sequence(log) >= secondary_sequence) {
+            match self.apply_dirty_vector(log, size_limit) {
+                Ok(pages) => applied += pages,
+                Err(e) => warn!("Skipping a transaction log: {:#}", e),
+            }
+        }
+
+        // Entries of both logs form one sequence; replay it from the last
+        // write the hive file completed, stopping at the first gap
+        new_format_entries.sort_by_key(|entry| entry.sequence);
+        new_format_entries.dedup_by_key(|entry| entry.sequence);
+        let mut expected = None;
+        for entry in new_format_entries.iter().filter(|entry| entry.sequence >= secondary_sequence) {
+            if expected.is_some_and(|expected| entry.sequence != expected) {
+                break;
+            }
+            self.resize_hive_bins(entry.hive_bins_size, size_limit)?;
+            for (offset, page) in &entry.pages {
+                self.write_page(*offset, page)?;
+                applied += 1;
+            }
+            expected = Some(entry.sequence.wrapping_add(1));
+            self.set_sequence_numbers(entry.sequence.wrapping_add(1));
+        }
+
+        Ok(applied)
+    }
+
+    /// Applies an old-format log: a bitmap of the 512-byte pages that were
+    /// dirty, followed by those pages in order.
+    fn apply_dirty_vector(&mut self, log: &[u8], size_limit: usize) -> Result<usize> {
+        if !has_valid_log_checksum(log) {
+            anyhow::bail!("Transaction log base block checksum mismatch");
+        }
+        let vector = &log[LOG_BASE_BLOCK_SIZE..];
+        if !vector.starts_with(DIRTY_VECTOR_SIGNATURE) {
+            anyhow::bail!("Transaction log has no dirty vector");
+        }
+
+        let hive_bins_size = LittleEndian::read_u32(&log[HIVE_BINS_SIZE_OFFSET..HIVE_BINS_SIZE_OFFSET + 4]);
+        let bitmap_size = hive_bins_size as usize / (OLD_LOG_PAGE_SIZE * 8);
+        let bitmap = vector.get(4..4 + bitmap_size)
+            .context("Truncated dirty vector")?;
+        let mut page_offset = (LOG_BASE_BLOCK_SIZE + 4 + bitmap_size).next_multiple_of(OLD_LOG_PAGE_SIZE);
+
+        self.resize_hive_bins(hive_bins_size, size_limit)?;
+        let mut applied = 0;
+        for page in 0..bitmap_size * 8 {
+            if bitmap[page / 8] & (1 << (page % 8)) == 0 {
+                continue;
+            }
+            let Some(data) = log.get(page_offset..page_offset + OLD_LOG_PAGE_SIZE) else {
+                break;
+            };
+            self.write_page((page * OLD_LOG_PAGE_SIZE) as u32, data)?;
+            page_offset += OLD_LOG_PAGE_SIZE;
+            applied += 1;
+        }
+
+        let sequence = self.base_u32(PRIMARY_SEQUENCE_OFFSET);
+        self.set_sequence_numbers(sequence);
+        Ok(applied)
+    }
+
+    /// Sets the size of the hive bins, growing the hive up to `size_limit`
+    /// bytes.
+    fn resize_hive_bins(&mut self, hive_bins_size: u32, size_limit: usize) -> Result<()> {
+        let size = BASE_BLOCK_SIZE + hive_bins_size as usize;
+        if size > size_limit {
+            anyhow::bail!("Transaction log grows the hive bins to {} bytes, more than the logs hold", hive_bins_size);
+        }
+        if size > self.data.len() {
+            self.data.resize(size, 0);
+        }
+        LittleEndian::write_u32(&mut self.data[HIVE_BINS_SIZE_OFFSET..HIVE_BINS_SIZE_OFFSET + 4], hive_bins_size);
+        Ok(())
+    }
+
+    /// Writes a page at `offset` from the start of the hive bins.
+    fn write_page(&mut self, offset: u32, page: &[u8]) -> Result<()> {
+        let start = BASE_BLOCK_SIZE + offset as usize;
+        self.data.get_mut(start..start + page.len())
+            .context("Transaction log page lies beyond the hive bins")?
+            .copy_from_slice(page);
+        Ok(())
+    }
+
+    fn set_sequence_numbers(&mut self, sequence: u32) {
+        LittleEndian::write_u32(&mut self.data[PRIMARY_SEQUENCE_OFFSET..PRIMARY_SEQUENCE_OFFSET + 4], sequence);
+        LittleEndian::write_u32(&mut self.data[SECONDARY_SEQUENCE_OFFSET..SECONDARY_SEQUENCE_OFFSET + 4], sequence);
+    }
+
+    /// Returns the data of the cell at `offset` (relative to the hive bins),
+    /// without its size field, whether allocated or free.
+    fn cell(&self, offset: u32) -> Option<&[u8]> {
+        let start = BASE_BLOCK_SIZE.checked_add(offset as usize)?;
+        let size = LittleEndian::read_i32(self.data.get(start..start + 4)?).unsigned_abs() as usize;
+        if size < 4 {
+            return None;
+        }
+        self.data.get(start + 4..start + size)
+    }
+
+    pub fn root_key(&self) -> Result<Key<'_>> {
+        Key::read(self, self.base_u32(ROOT_CELL_OFFSET))
+            .context("Failed to read the root key")
+    }
+
+    /// Opens a key by its path below the root, e.g. `Select` or
+    /// `ControlSet001\Services`.
+    pub fn key(&self, path: &str) -> Option<Key<'_>> {
+        let mut key = self.root_key().ok()?;
+        for name in path.split('\\').filter(|name| !name.is_empty()) {
+            key = key.subkey(name)?;
+        }
+        Some(key)
+    }
+}
+
+impl Hive {
+    /// The parts of the hive no live cell uses, as (start, end) offsets
+    /// relative to the hive bins: free cells, the rest of a hive bin whose
+    /// cells stop making sense, and any data past the last hive bin.
+    fn unallocated_ranges(&self) -> Vec<(usize, usize)> {
+        let mut ranges = Vec::new();
+        let mut bin = BASE_BLOCK_SIZE;
+
+        while let Some(header) = self.data.get(bin..bin + HBIN_HEADER_SIZE) {
+            let bin_size = LittleEndian::read_u32(&header[8..12]) as usize;
+            if &header[0..4] != HBIN_SIGNATURE || bin_size < BASE_BLOCK_SIZE || bin + bin_size > self.data.len() {
+                break;
+            }
+            let bin_end = bin + bin_size;
+
+            let mut cell = bin + HBIN_HEADER_SIZE;
+            while cell + 4 <= bin_end {
+                let size = LittleEndian::read_i32(&self.data[cell..cell + 4]);
+                let length = size.unsigned_abs() as usize;
+                if length < CELL_ALIGNMENT || !length.is_multiple_of(CELL_ALIGNMENT) || cell + length > bin_end {
+                    ranges.push((cell, bin_end));
+                    break;
+                }
+                if size > 0 {
+                    ranges.push((cell, cell + length));
+                }
+                cell += length;
+            }
+            bin = bin_end;
+        }
+
+        if bin < self.data.len() {
+            ranges.push((bin, self.data.len()));
+        }
+        ranges.into_iter()
+            .map(|(start, end)| (start - BASE_BLOCK_SIZE, end - BASE_BLOCK_SIZE))
+            .collect()
+    }
+
+    /// Scans the unallocated parts of the hive for the key and value cells
+    /// of deleted keys and values. Every 8-byte boundary is tried, since a
+    /// free cell may hold several former cells.
+    pub fn deleted_cells(&self) -> DeletedCells<'_> {
+        let mut deleted = DeletedCells {
+            keys: Vec::new(),
+            values: Vec::new(),
+        };
+
+        for (start, end) in self.unallocated_ranges() {
+            let region = &self.data[BASE_BLOCK_SIZE + start..BASE_BLOCK_SIZE + end];
+            for position in (0..region.len()).step_by(CELL_ALIGNMENT) {
+                // Skip the size field of the former cell
+                let Some(record) = region.get(position + 4..) else {
+                    break;
+                };
+                let offset = (start + position) as u32;
+                if record.starts_with(b"nk") {
+                    if let Some(key) = Key::parse(self, offset, record).ok().filter(is_plausible_key) {
+                        deleted.keys.push(key);
+                    }
+                } else if record.starts_with(b"vk") {
+                    if let Some(value) = RegistryValue::parse(self, record) {
+                        deleted.values.push((offset, value));
+                    }
+                }
+            }
+        }
+        deleted
+    }
+}
+
+/// Key and value cells found in the unallocated parts of a hive.
+pub struct DeletedCells<'h> {
+    pub keys: Vec<Key<'h>>,
+    /// Values by the offset of their former cell.
+    pub values: Vec<(u32, RegistryValue)>,
+}
+
+/// Rejects byte patterns that only look like a key node.
+fn is_plausible_key(key: &Key) -> bool {
+    key.last_written.as_ref().is_some_and(is_plausible_time)
+        && !key.name.is_empty() && key.name.len() <= MAX_KEY_NAME_LENGTH
+        && !key.name.chars().any(char::is_control)
+}
+
+/// A key node ("nk") cell.
+pub struct Key<'h> {
+    hive: &'h Hive,
+    /// Offset of the key's cell relative to the hive bins.
+    pub offset: u32,
+    pub name: String,
+    pub last_written: Option<DateTime<Utc>>,
+    /// Offset of the parent key's cell.
+    pub parent: u32,
+    subkey_count: u32,
+    subkey_list: u32,
+    value_count: u32,
+    value_list: u32,
+}
+
+impl<'h> Key<'h> {
+    fn read(hive: &'h Hive, offset: u32) -> Result<Self> {
+        let cell = hive.cell(offset)
+            .with_context(|| format!("Key cell at 0x{:X} is out of bounds", offset))?;
+        Self::parse(hive, offset, cell)
+    }
+
+    /// Decodes a key node from its cell data.
+    fn parse(hive: &'h Hive, offset: u32, cell: &[u8]) -> Result<Self> {
+        if cell.len() < 76 || &cell[0..2] != b"nk" {
+            anyhow::bail!("Cell at 0x{:X} is not a key node", offset);
+        }
+        let flags = LittleEndian::read_u16(&cell[2..4]);
+        let name_length = LittleEndian::read_u16(&cell[72..74]) as usize;
+        let name = cell.get(76..76 + name_length)
+            .with_context(|| format!("Key name at 0x{:X} is truncated", offset))?;
+
+        Ok(Key {
+            hive,
+            offset,
+            name: decode_name(name, flags & KEY_COMP_NAME != 0),
+            last_written: windows_time_to_utc(LittleEndian::read_u64(&cell[4..12])),
+            parent: LittleEndian::read_u32(&cell[16..20]),
+            subkey_count: LittleEndian::read_u32(&cell[20..24]),
+            subkey_list: LittleEndian::read_u32(&cell[28..32]),
+            value_count: LittleEndian::read_u32(&cell[36..40]),
+            value_list: LittleEndian::read_u32(&cell[40..44]),
+        })
+    }
+
+    /// The subkeys of this key, from its (possibly indirect) subkey list.
+    /// Entries that cannot be read are skipped.
+    pub fn subkeys(&self) -> Vec<Key<'h>> {
+        let mut offsets = Vec::new();
+        if self.subkey_count > 0 {
+            collect_subkey_offsets(self.hive, self.subkey_list, &mut offsets, 0);
+        }
+        offsets.into_iter()
+            .filter_map(|offset| Key::read(self.hive, offset).ok())
+            .collect()
+    }
+
+    /// Finds a subkey by name, ignoring case.
+    pub fn subkey(&self, name: &str) -> Option<Key<'h>> {
+        self.subkeys().into_iter().find(|key| key.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn values(&self) -> Vec<RegistryValue> {
+        self.values_with_offsets().into_iter().map(|(_, value)| value).collect()
+    }
+
+    /// The values of this key with the offsets of their cells.
+    fn values_with_offsets(&self) -> Vec<(u32, RegistryValue)> {
+        if self.value_count == 0 {
+            return Vec::new();
+        }
+        let Some(list) = self.hive.cell(self.value_list) else {
+            return Vec::new();
+        };
+        list.chunks_exact(4)
+            .take(self.value_count as usize)
+            .map(LittleEndian::read_u32)
+            .filter_map(|offset| Some((offset, RegistryValue::read(self.hive, offset)?)))
+            .collect()
+    }
+
+    /// Finds a value by name, ignoring case; `""` is the default value.
+    pub fn value(&self, name: &str) -> Option<RegistryValue> {
+        self.values().into_iter().find(|value| value.name.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Follows an "lf", "lh", "li" or "ri" subkey list. Index roots ("ri") point
+/// to further lists; `depth` guards against cycles in a corrupt hive.
+fn collect_subkey_offsets(hive: &Hive, list: u32, offsets: &mut Vec<u32>, depth: usize) {
+    let Some(cell) = hive.cell(list) else {
+        return;
+    };
+    if cell.len() < 4 || depth > 2 {
+        return;
+    }
+    let count = LittleEndian::read_u16(&cell[2..4]) as usize;
+    let entries = &cell[4..];
+    match &cell[0..2] {
+        b"lf" | b"lh" => offsets.extend(entries.chunks_exact(8).take(count)
+            .map(|entry| LittleEndian::read_u32(&entry[0..4]))),
+        b"li" => offsets.extend(entries.chunks_exact(4).take(count)
+            .map(LittleEndian::read_u32)),
+        b"ri" => {
+            for entry in entries.chunks_exact(4).take(count) {
+                collect_subkey_offsets(hive, LittleEndian::read_u32(entry), offsets, depth + 1);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A value ("vk") cell with its data.
+#[derive(Debug, Clone)]
+pub struct RegistryValue {
+    /// Empty for the key's default value.
+    pub name: String,
+    pub value_type: u32,
+    pub data: Vec<u8>,
+}
+
+impl RegistryValue {
+    fn read(hive: &Hive, offset: u32) -> Option<Self> {
+        Self::parse(hive, hive.cell(offset)?)
+    }
+
+    /// Decodes a value from its cell data, reading the data it points to.
+    fn parse(hive: &Hive, cell: &[u8]) -> Option<Self> {
+        if cell.len() < 20 || &cell[0..2] != b"vk" {
+            return None;
+        }
+        let name_length = LittleEndian::read_u16(&cell[2..4]) as usize;
+        let data_size = LittleEndian::read_u32(&cell[4..8]);
+        let data_offset = LittleEndian::read_u32(&cell[8..12]);
+        let value_type = LittleEndian::read_u32(&cell[12..16]);
+        let flags = LittleEndian::read_u16(&cell[16..18]);
+        let name = decode_name(cell.get(20..20 + name_length)?, flags & VALUE_COMP_NAME != 0);
+
+        let data = if data_size & DATA_IN_OFFSET != 0 {
+            let size = ((data_size & !DATA_IN_OFFSET) as usize).min(4);
+            cell[8..8 + size].to_vec()
+        } else {
+            read_value_data(hive, data_offset, data_size as usize)?
+        };
+
+        Some(RegistryValue { name, value_type, data })
+    }
+
+    /// String data of REG_SZ, REG_EXPAND_SZ and REG_LINK values.
+    pub fn as_string(&self) -> Option<String> {
+        matches!(self.value_type, REG_SZ | REG_EXPAND_SZ | REG_LINK)
+            .then(|| read_utf16_string(&self.data))
+    }
+
+    pub fn as_multi_string(&self) -> Option<Vec<String>> {
+        (self.value_type == REG_MULTI_SZ).then(|| {
+            let units: Vec<u16> = self.data.chunks_exact(2).map(LittleEndian::read_u16).collect();
+            units.split(|&unit| unit == 0)
+                .filter(|string| !string.is_empty())
+                .map(String::from_utf16_lossy)
+                .collect()
+        })
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        match self.value_type {
+            REG_DWORD if self.data.len() >= 4 => Some(LittleEndian::read_u32(&self.data)),
+            REG_DWORD_BIG_ENDIAN if self.data.len() >= 4 => Some(byteorder::BigEndian::read_u32(&self.data)),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self.value_type {
+            REG_QWORD if self.data.len() >= 8 => Some(LittleEndian::read_u64(&self.data)),
+            _ => self.as_u32().map(u64::from),
+        }
+    }
+
+    fn type_name(&self) -> String {
+        match self.value_type {
+            REG_NONE => "REG_NONE".to_string(),
+            REG_SZ => "REG_SZ".to_string(),
+            REG_EXPAND_SZ => "REG_EXPAND_SZ".to_string(),
+            REG_BINARY => "REG_BINARY".to_string(),
+            REG_DWORD => "REG_DWORD".to_string(),
+            REG_DWORD_BIG_ENDIAN => "REG_DWORD_BIG_ENDIAN".to_string(),
+            REG_LINK => "REG_LINK".to_string(),
+            REG_MULTI_SZ => "REG_MULTI_SZ".to_string(),
+            REG_QWORD => "REG_QWORD".to_string(),
+            other => format!("type 0x{:X}", other),
+        }
+    }
+
+    /// The data as text: strings as they are, numbers in hex and decimal,
+    /// anything else as a hex preview.
+    pub fn data_text(&self) -> String {
+        if let Some(string) = self.as_string() {
+            return string;
+        }
+        if let Some(strings) = self.as_multi_string() {
+            return strings.join("; ");
+        }
+        if let Some(number) = self.as_u64() {
+            return format!("0x{:X} ({})", number, number);
+        }
+        let preview: String = self.data.iter()
+            .take(BINARY_PREVIEW_LIMIT)
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        if self.data.len() > BINARY_PREVIEW_LIMIT {
+            format!("{}... ({} bytes)", preview, self.data.len())
+        } else {
+            preview
+        }
+    }
+}
+
+impl std::fmt::Display for RegistryValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = if self.name.is_empty() { "(Default)" } else { &self.name };
+        let mut data = self.data_text();
+        if data.chars().count() > VALUE_TEXT_LIMIT {
+            data = data.chars().take(VALUE_TEXT_LIMIT).collect::<String>() + "...";
+        }
+        write!(f, "{} ({}) = {}", name, self.type_name(), data)
+    }
+}
+
+/// Reads value data from its cell, joining "db" big data segments.
+fn read_value_data(hive: &Hive, offset: u32, size: usize) -> Option<Vec<u8>> {
+    let cell = hive.cell(offset)?;
+    if size > BIG_DATA_SEGMENT_SIZE && hive.minor_version() >= BIG_DATA_MINOR_VERSION && cell.starts_with(b"db") {
+        let count = LittleEndian::read_u16(cell.get(2..4)?) as usize;
+        let segments = hive.cell(LittleEndian::read_u32(cell.get(4..8)?))?;
+        let mut data = Vec::with_capacity(size);
+        for segment in segments.chunks_exact(4).take(count) {
+            let segment = hive.cell(LittleEndian::read_u32(segment))?;
+            let length = segment.len().min(BIG_DATA_SEGMENT_SIZE).min(size - data.len());
+            data.extend_from_slice(&segment[..length]);
+        }
+        return Some(data);
+    }
+    Some(cell.get(..size)?.to_vec())
+}
+
+/// One log entry of a Windows 8.1+ transaction log.
+struct LogEntry {
+    sequence: u32,
+    hive_bins_size: u32,
+    /// Dirty pages by offset from the start of the hive bins.
+    pages: Vec<(u32, Vec<u8>)>,
+}
+
+/// The sequence number of the hive write a transaction log was made for.
+fn log_sequence(log: &[u8]) -> u32 {
+    LittleEndian::read_u32(&log[PRIMARY_SEQUENCE_OFFSET..PRIMARY_SEQUENCE_OFFSET + 4])
+}
+
+fn has_valid_log_checksum(log: &[u8]) -> bool {
+    base_block_checksum(log) == LittleEndian::read_u32(&log[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4])
+}
+
+/// Reads the consecutive log entries of a new-format transaction log,
+/// stopping at the first one that fails its hashes.
+fn read_log_entries(log: &[u8]) -> Vec<LogEntry> {
+    let mut entries = Vec::new();
+    let mut offset = LOG_BASE_BLOCK_SIZE;
+
+    while let Some(header) = log.get(offset..offset + LOG_ENTRY_HEADER_SIZE) {
+        if &header[0..4] != LOG_ENTRY_SIGNATURE {
+            break;
+        }
+        let size = LittleEndian::read_u32(&header[4..8]) as usize;
+        let Some(entry) = log.get(offset..offset + size).filter(|_| size >= LOG_ENTRY_HEADER_SIZE) else {
+            break;
+        };
+        if marvin32(MARVIN32_SEED, &entry[0..32]) != LittleEndian::read_u64(&header[32..40])
+            || marvin32(MARVIN32_SEED, &entry[LOG_ENTRY_HEADER_SIZE..]) != LittleEndian::read_u64(&header[24..32]) {
+            break;
+        }
+
+        let sequence = LittleEndian::read_u32(&header[12..16]);
+        if entries.last().is_some_and(|last: &LogEntry| last.sequence.wrapping_add(1) != sequence) {
+            break;
+        }
+        let page_count = LittleEndian::read_u32(&header[20..24]) as usize;
+        let Some(pages) = read_dirty_pages(&entry[LOG_ENTRY_HEADER_SIZE..], page_count) else {
+            break;
+        };
+
+        entries.push(LogEntry {
+            sequence,
+            hive_bins_size: LittleEndian::read_u32(&header[16..20]),
+            pages,
+        });
+        offset += size;
+    }
+
+    entries
+}
+
+/// Splits a log entry body into its dirty pages: a table of (offset, size)
+/// references followed by the page data in the same order.
+fn read_dirty_pages(body: &[u8], count: usize) -> Option<Vec<(u32, Vec<u8>)>> {
+    let references = body.get(..count.checked_mul(8)?)?;
+    let mut position = references.len();
+    let mut pages = Vec::with_capacity(count);
+    for reference in references.chunks_exact(8) {
+        let offset = LittleEndian::read_u32(&reference[0..4]);
+        let size = LittleEndian::read_u32(&reference[4..8]) as usize;
+        pages.push((offset, body.get(position..position + size)?.to_vec()));
+        position += size;
+    }
+    Some(pages)
+}
+
+/// Marvin32; the log entry hashes use `MARVIN32_SEED`.
+fn marvin32(seed: u64, data: &[u8]) -> u64 {
+    fn mix(lo: &mut u32, hi: &mut u32, value: u32) {
+        *lo = lo.wrapping_add(value);
+        *hi ^= *lo;
+        *lo = lo.rotate_left(20).wrapping_add(*hi);
+        *hi = hi.rotate_left(9) ^ *lo;
+        *lo = lo.rotate_left(27).wrapping_add(*hi);
+        *hi = hi.rotate_left(19);
+    }
+
+    let mut lo = seed as u32;
+    let mut hi = (seed >> 32) as u32;
+    let mut words = data.chunks_exact(4);
+    for word in &mut words {
+        mix(&mut lo, &mut hi, LittleEndian::read_u32(word));
+    }
+    // The tail is padded with a 0x80 byte
+    let last = words.remainder().iter().rev()
+        .fold(0x80u32, |last, &byte| (last << 8) | byte as u32);
+    mix(&mut lo, &mut hi, last);
+    mix(&mut lo, &mut hi, 0);
+
+    ((hi as u64) << 32) | lo as u64
+}
+
+/// XOR of the first 127 dwords of a base block, avoiding 0 and -1.
+fn base_block_checksum(base_block: &[u8]) -> u32 {
+    let checksum = base_block[..CHECKSUM_OFFSET].chunks_exact(4)
+        .fold(0, |checksum, dword| checksum ^ LittleEndian::read_u32(dword));
+    match checksum {
+        0 => 1,
+        0xFFFF_FFFF => 0xFFFF_FFFE,
+        checksum => checksum,
+    }
+}
+
+pub fn parse_registry_hives(vfs: &Vfs, timeline: &mut Timeline) -> Result<()> {
+    info!("Starting registry hive parsing...");
+
+    let hives = load_hives(vfs);
+    let mut key_count = 0;
+    let mut deleted_count = 0;
+    let mut activity_count = 0;
+    let mut shellbag_count = 0;
+    for hive in &hives {
+        let key_paths = add_key_events(hive, timeline);
+        key_count += key_paths.len();
+        deleted_count += add_deleted_key_events(hive, &key_paths, timeline);
+
+        if hive.kind == HiveKind::NtUser {
+            activity_count += user_activity::add_user_activity_events(hive, timeline);
+        }
+        shellbag_count += shellbags::add_shellbag_events(hive, timeline);
+    }
+
+    let bam_count = bam::add_bam_events(&hives, timeline);
+
+    info!("Registry parsing completed: {} hives, {} keys, {} deleted keys recovered, {} user activity events, {} ShellBags, {} BAM/DAM entries",
+          hives.len(), key_count, deleted_count, activity_count, shellbag_count, bam_count);
+    Ok(())
+}
+
+/// Reads every hive found on the volume and brings it up to date with its
+/// transaction logs.
+fn load_hives(vfs: &Vfs) -> Vec<HiveFile> {
+    let mut hives = Vec::new();
+    for &(pattern, kind) in HIVE_LOCATIONS {
+        for path in vfs.glob(pattern).unwrap_or_default() {
+            match load_hive(vfs, &path) {
+                Ok(hive) => hives.push(HiveFile {
+                    user: profile_name(&path, kind),
+                    path,
+                    kind,
+                    hive,
+                }),
+                Err(e) => warn!("Skipping registry hive {}: {:#}", path, e),
+            }
+        }
+    }
+    hives
+}
+
+fn load_hive(vfs: &Vfs, path: &str) -> Result<Hive> {
+    let mut hive = Hive::new(vfs.read_file(path)?)?;
+    if !hive.has_valid_checksum() {
+        warn!("Base block checksum of {} does not match", path);
+    }
+
+    let logs: Vec<Vec<u8>> = ["LOG1", "LOG2"].iter()
+        .filter_map(|extension| vfs.read_file(&format!("{}.{}", path, extension)).ok())
+        .collect();
+    let was_dirty = hive.is_dirty();
+    match hive.apply_transaction_logs(&logs) {
+        Ok(0) => {}
+        Ok(pages) => info!("Applied {} dirty pages from the transaction logs of {}", pages, path),
+        Err(e) => warn!("Failed to apply the transaction logs of {}: {:#}", path, e),
+    }
+    if was_dirty && hive.is_dirty() {
+        warn!("{} is dirty and its transaction logs could not be applied; recent changes may be missing", path);
+    }
+    Ok(hive)
+}
+
+/// The profile folder a per-user hive lives in: `\Users\<name>\...`.
+fn profile_name(path: &str, kind: HiveKind) -> Option<String> {
+    if !matches!(kind, HiveKind::NtUser | HiveKind::UsrClass) {
+        return None;
+    }
+    path.split('\\').filter(|component| !component.is_empty()).nth(1).map(str::to_string)
+}
+
+/// Adds a LastWrite event for every key of the hive and returns the paths of
+/// the keys by cell offset. Keys are walked depth-first; a key reachable
+/// twice in a corrupt hive is only reported once.
+fn add_key_events(hive_file: &HiveFile, timeline: &mut Timeline) -> HashMap<u32, String> {
+    let mut key_paths = HashMap::new();
+    let root = match hive_file.hive.root_key() {
+        Ok(root) => root,
+        Err(e) => {
+            warn!("Skipping registry hive {}: {:#}", hive_file.path, e);
+            return key_paths;
+        }
+    };
+
+    let mut pending = vec![(root, hive_file.root_path())];
+    while let Some((key, path)) = pending.pop() {
+        if key_paths.contains_key(&key.offset) {
+            continue;
+        }
+        if let Some(last_written) = key.last_written {
+            let values = key.values().iter().map(|value| value.to_string()).collect();
+            timeline.add_registry_key_event(last_written, &path, &hive_file.path)
+                .add_detail("Values", values);
+        }
+        for subkey in key.subkeys() {
+            let subkey_path = format!("{}\\{}", path, subkey.name);
+            pending.push((subkey, subkey_path));
+        }
+        key_paths.insert(key.offset, path);
+    }
+    key_paths
+}
+
+/// Adds the deleted keys found in the hive's free cells and slack, marked as
+/// deleted, and returns their number. Values whose key cannot be recovered
+/// are listed on one event at the hive's last write time.
+fn add_deleted_key_events(hive_file: &HiveFile, key_paths: &HashMap<u32, String>,
+                          timeline: &mut Timeline) -> usize {
+    let deleted = hive_file.hive.deleted_cells();
+    let deleted_keys: HashMap<u32, &Key> = deleted.keys.iter()
+        .map(|key| (key.offset, key))
+        .collect();
+
+    let mut linked_values = HashSet::new();
+    for key in &deleted.keys {
+        let Some(last_written) = key.last_written else {
+            continue;
+        };
+        let path = deleted_key_path(key, hive_file, key_paths, &deleted_keys);
+        let values: Vec<(u32, RegistryValue)> = key.values_with_offsets();
+        linked_values.extend(values.iter().map(|(offset, _)| *offset));
+
+        let event = timeline.add_registry_key_event(last_written, &path, &hive_file.path);
+        event.deleted = true;
+        event.add_detail("Values", values.iter().map(|(_, value)| value.to_string()).collect());
+        event.add_detail("Recovered from", vec![format!("Unallocated cell at offset 0x{:X}", key.offset)]);
+    }
+
+    let unlinked: Vec<String> = deleted.values.iter()
+        .filter(|(offset, _)| !linked_values.contains(offset))
+        .map(|(offset, value)| format!("{} [cell 0x{:X}]", value, offset))
+        .collect();
+    if let (false, Some(last_written)) = (unlinked.is_empty(), hive_file.hive.last_written()) {
+        let mut event = TimelineEvent::new(
+            last_written,
+            EventType::RegistryKeyLastWrite,
+            format!("{} deleted values in {} could not be linked to their key; the time is the hive's last write.",
+                    unlinked.len(), hive_file.root_path()),
+            &hive_file.path,
+        );
+        event.deleted = true;
+        event.add_detail("Values", unlinked);
+        timeline.add_event(event);
+    }
+
+    deleted.keys.len()
+}
+
+/// Rebuilds the path of a deleted key through its parents, live or deleted.
+/// Keys whose parent chain is broken are placed under `(unknown parent)`.
+fn deleted_key_path(key: &Key, hive_file: &HiveFile, key_paths: &HashMap<u32, String>,
+                    deleted_keys: &HashMap<u32, &Key>) -> String {
+    let mut names = vec![key.name.clone()];
+    let mut visited = HashSet::from([key.offset]);
+    let mut parent = key.parent;
+    let prefix = loop {
+        if let Some(path) = key_paths.get(&parent) {
+            break path.clone();
+        }
+        match deleted_keys.get(&parent) {
+            Some(parent_key) if visited.insert(parent) => {
+                names.push(parent_key.name.clone());
+                parent = parent_key.parent;
+            }
+            _ => break format!("{}\\(unknown parent)", hive_file.root_path()),
+        }
+    };
+    names.push(prefix);
+    names.reverse();
+    names.join("\\")
+}
+
+/// Key and value names are Latin-1 when flagged as compressed, UTF-16
+/// otherwise.
+fn decode_name(name: &[u8], compressed: bool) -> String {
+    if compressed {
+        name.iter().map(|&byte| byte as char).collect()
+    } else {
+        let units: Vec<u16> = name.chunks_exact(2).map(LittleEndian::read_u16).collect();
+        String::from_utf16_lossy(&units)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// 2024-01-15 10:02:03 UTC.
+    const KEY_TIME: u64 = 133497865234567890;
+    const FILE_TYPE_PRIMARY: u32 = 0;
+
+    /// Builds a hive with a single hive bin, one cell at a time.
+    pub(crate) struct HiveBuilder {
+        bins: Vec<u8>,
+    }
+
+    impl HiveBuilder {
+        pub(crate) fn new() -> Self {
+            let mut bins = vec![0u8; HBIN_HEADER_SIZE];
+            bins[..4].copy_from_slice(HBIN_SIGNATURE);
+            HiveBuilder { bins }
+        }
+
+        /// Appends an allocated cell and returns its offset.
+        fn cell(&mut self, content: &[u8]) -> u32 {
+            let offset = self.bins.len() as u32;
+            let size = (4 + content.len()).next_multiple_of(CELL_ALIGNMENT);
+            self.bins.extend((-(size as i32)).to_le_bytes());
+            self.bins.extend(content);
+            self.bins.resize(offset as usize + size, 0);
+            offset
+        }
+
+        pub(crate) fn key(&mut self, name: &str, subkey_count: u32, subkey_list: u32, values: &[u32]) -> u32 {
+            let value_list = if values.is_empty() {
+                u32::MAX
+            } else {
+                self.cell(&values.iter().flat_map(|value| value.to_le_bytes()).collect::<Vec<_>>())
+            };
+            let mut cell = vec![0u8; 76];
+            cell[0..2].copy_from_slice(b"nk");
+            LittleEndian::write_u16(&mut cell[2..4], KEY_COMP_NAME);
+            LittleEndian::write_u64(&mut cell[4..12], KEY_TIME);
+            LittleEndian::write_u32(&mut cell[20..24], subkey_count);
+            LittleEndian::write_u32(&mut cell[28..32], subkey_list);
+            LittleEndian::write_u32(&mut cell[36..40], values.len() as u32);
+            LittleEndian::write_u32(&mut cell[40..44], value_list);
+            LittleEndian::write_u16(&mut cell[72..74], name.len() as u16);
+            cell.extend(name.as_bytes());
+            self.cell(&cell)
+        }
+
+        pub(crate) fn value(&mut self, name: &str, value_type: u32, data: &[u8]) -> u32 {
+            let mut cell = vec![0u8; 20];
+            cell[0..2].copy_from_slice(b"vk");
+            LittleEndian::write_u16(&mut cell[2..4], name.len() as u16);
+            if data.len() <= 4 {
+                LittleEndian::write_u32(&mut cell[4..8], data.len() as u32 | DATA_IN_OFFSET);
+                cell[8..8 + data.len()].copy_from_slice(data);
+            } else {
+                let data_offset = self.cell(data);
+                LittleEndian::write_u32(&mut cell[4..8], data.len() as u32);
+                LittleEndian::write_u32(&mut cell[8..12], data_offset);
+            }
+            LittleEndian::write_u32(&mut cell[12..16], value_type);
+            LittleEndian::write_u16(&mut cell[16..18], VALUE_COMP_NAME);
+            cell.extend(name.as_bytes());
+            self.cell(&cell)
+        }
+
+        /// A subkey list: "lf" and "lh" entries carry a hint after each
+        /// offset, "li" and "ri" entries are bare offsets.
+        pub(crate) fn list(&mut self, signature: &[u8; 2], offsets: &[u32]) -> u32 {
+            let mut cell = signature.to_vec();
+            cell.extend((offsets.len() as u16).to_le_bytes());
+            for offset in offsets {
+                cell.extend(offset.to_le_bytes());
+                if matches!(signature, b"lf" | b"lh") {
+                    cell.extend([0; 4]);
+                }
+            }
+            self.cell(&cell)
+        }
+
+        /// Fills the bin up with a free cell and prepends the base block.
+        pub(crate) fn build(mut self, root: u32) -> Vec<u8> {
+            let free = BASE_BLOCK_SIZE - self.bins.len();
+            self.bins.extend((free as i32).to_le_bytes());
+            self.bins.resize(BASE_BLOCK_SIZE, 0);
+            LittleEndian::write_u32(&mut self.bins[8..12], BASE_BLOCK_SIZE as u32);
+
+            let mut hive = base_block(FILE_TYPE_PRIMARY, 1, BASE_BLOCK_SIZE as u32);
+            LittleEndian::write_u32(&mut hive[ROOT_CELL_OFFSET..ROOT_CELL_OFFSET + 4], root);
+            set_checksum(&mut hive);
+            hive.resize(BASE_BLOCK_SIZE, 0);
+            hive.extend(self.bins);
+            hive
+        }
+    }
+
+    fn base_block(file_type: u32, sequence: u32, hive_bins_size: u32) -> Vec<u8> {
+        let mut block = vec![0u8; LOG_BASE_BLOCK_SIZE];
+        block[..4].copy_from_slice(REGF_SIGNATURE);
+        LittleEndian::write_u32(&mut block[PRIMARY_SEQUENCE_OFFSET..PRIMARY_SEQUENCE_OFFSET + 4], sequence);
+        LittleEndian::write_u32(&mut block[SECONDARY_SEQUENCE_OFFSET..SECONDARY_SEQUENCE_OFFSET + 4], sequence);
+        LittleEndian::write_u64(&mut block[LAST_WRITTEN_OFFSET..LAST_WRITTEN_OFFSET + 8], KEY_TIME);
+        LittleEndian::write_u32(&mut block[FILE_TYPE_OFFSET..FILE_TYPE_OFFSET + 4], file_type);
+        LittleEndian::write_u32(&mut block[HIVE_BINS_SIZE_OFFSET..HIVE_BINS_SIZE_OFFSET + 4], hive_bins_size);
+        set_checksum(&mut block);
+        block
+    }
+
+    fn set_checksum(block: &mut [u8]) {
+        let checksum = base_block_checksum(block);
+        LittleEndian::write_u32(&mut block[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4], checksum);
+    }
+
+    /// ROOT has A and B through an "lf" list and C through an "lh" list,
+    /// joined by an "ri" index root; A has D through an "li" list.
+    fn sample_hive() -> Hive {
+        let mut builder = HiveBuilder::new();
+        let d = builder.key("D", 0, u32::MAX, &[]);
+        let d_list = builder.list(b"li", &[d]);
+        let a = builder.key("A", 1, d_list, &[]);
+        let b = builder.key("B", 0, u32::MAX, &[]);
+        let c = builder.key("C", 0, u32::MAX, &[]);
+        let lf = builder.list(b"lf", &[a, b]);
+        let lh = builder.list(b"lh", &[c]);
+        let ri = builder.list(b"ri", &[lf, lh]);
+
+        let path: Vec<u8> = "C:\\Windows\0".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let values = [
+            builder.value("Path", REG_SZ, &path),
+            builder.value("Count", REG_DWORD, &7u32.to_le_bytes()),
+        ];
+        let root = builder.key("ROOT", 3, ri, &values);
+        Hive::new(builder.build(root)).unwrap()
+    }
+
+    #[test]
+    fn walks_keys_through_every_subkey_list_kind() {
+        let hive = sample_hive();
+        assert!(hive.has_valid_checksum());
+        assert!(!hive.is_dirty());
+
+        let root = hive.root_key().unwrap();
+        assert_eq!(root.name, "ROOT");
+        assert_eq!(root.last_written.unwrap().to_rfc3339(), "2024-01-15T10:02:03.456789+00:00");
+        let names: Vec<String> = root.subkeys().into_iter().map(|key| key.name).collect();
+        assert_eq!(names, ["A", "B", "C"]);
+        assert_eq!(hive.key(r"a\d").unwrap().name, "D");
+        assert!(hive.key(r"A\E").is_none());
+    }
+
+    #[test]
+    fn reads_values_stored_inline_and_in_cells() {
+        let hive = sample_hive();
+        let root = hive.root_key().unwrap();
+        assert_eq!(root.value("path").unwrap().as_string().unwrap(), r"C:\Windows");
+        assert_eq!(root.value("Count").unwrap().as_u32(), Some(7));
+        assert!(root.value("Missing").is_none());
+        assert!(hive.key("B").unwrap().values().is_empty());
+    }
+
+    #[test]
+    fn marvin32_matches_the_published_vectors() {
+        const SEED: u64 = 0x004F_B61A_001B_DBCC;
+        assert_eq!(marvin32(SEED, &[]), 0x30ED_35C1_00CD_3C7D);
+        assert_eq!(marvin32(SEED, &[0xAF]), 0x48E7_3FC7_7D75_DDC1);
+        assert_eq!(marvin32(SEED, &[0xE7, 0x0F]), 0xB5F6_E1FC_485D_BFF8);
+        assert_eq!(marvin32(SEED, &[0x37, 0xF4, 0x95]), 0xF0B0_7C78_9B8C_F7E8);
+        assert_eq!(marvin32(SEED, &[0x86, 0x42, 0xDC, 0x59]), 0x7008_F2E8_7E9C_F556);
+        assert_eq!(marvin32(SEED, &[0x15, 0x3F, 0xB7, 0x98, 0x26]), 0xE6C0_8C6D_A2AF_A997);
+        assert_eq!(marvin32(SEED, &[0x09, 0x32, 0xE6, 0x24, 0x6C, 0x47]), 0x6F04_BF1A_5EA2_4060);
+        assert_eq!(marvin32(SEED, &[0xAB, 0x42, 0x7E, 0xA8, 0xD1, 0x0F, 0xC7]), 0xE118_47E4_F067_8C41);
+    }
+
+    /// A new-format log with one entry writing `page` at `offset`.
+    fn new_format_log(sequence: u32, hive_bins_size: u32, offset: u32, page: &[u8]) -> Vec<u8> {
+        let mut log = base_block(FILE_TYPE_LOG_NEW, sequence, hive_bins_size);
+        let mut entry = vec![0u8; LOG_ENTRY_HEADER_SIZE];
+        entry[..4].copy_from_slice(LOG_ENTRY_SIGNATURE);
+        LittleEndian::write_u32(&mut entry[4..8], (LOG_ENTRY_HEADER_SIZE + 8 + page.len()) as u32);
+        LittleEndian::write_u32(&mut entry[12..16], sequence);
+        LittleEndian::write_u32(&mut entry[16..20], hive_bins_size);
+        LittleEndian::write_u32(&mut entry[20..24], 1);
+        entry.extend(offset.to_le_bytes());
+        entry.extend((page.len() as u32).to_le_bytes());
+        entry.extend(page);
+        let body_hash = marvin32(MARVIN32_SEED, &entry[LOG_ENTRY_HEADER_SIZE..]);
+        LittleEndian::write_u64(&mut entry[24..32], body_hash);
+        let header_hash = marvin32(MARVIN32_SEED, &entry[..32]);
+        LittleEndian::write_u64(&mut entry[32..40], header_hash);
+        log.extend(entry);
+        log
+    }
+
+    /// The sample hive with its last write (sequence 2) left incomplete.
+    fn dirty_hive() -> Hive {
+        let mut hive = sample_hive();
+        LittleEndian::write_u32(&mut hive.data[PRIMARY_SEQUENCE_OFFSET..PRIMARY_SEQUENCE_OFFSET + 4], 2);
+        assert!(hive.is_dirty());
+        hive
+    }
+
+    #[test]
+    fn replays_new_format_transaction_logs() {
+        let mut hive = dirty_hive();
+        let log = new_format_log(1, 2 * BASE_BLOCK_SIZE as u32, BASE_BLOCK_SIZE as u32, &[0x41; BASE_BLOCK_SIZE]);
+        assert_eq!(hive.apply_transaction_logs(&[log]).unwrap(), 1);
+        assert!(!hive.is_dirty());
+        assert_eq!(hive.data.len(), 3 * BASE_BLOCK_SIZE);
+        assert!(hive.data[2 * BASE_BLOCK_SIZE..].iter().all(|&byte| byte == 0x41));
+        assert_eq!(hive.root_key().unwrap().name, "ROOT");
+    }
+
+    #[test]
+    fn skips_damaged_transaction_logs() {
+        let log = new_format_log(1, BASE_BLOCK_SIZE as u32, 0, &[0x41; 512]);
+
+        // Base block checksum
+        let mut damaged = log.clone();
+        damaged[CHECKSUM_OFFSET] ^= 1;
+        let mut hive = dirty_hive();
+        assert_eq!(hive.apply_transaction_logs(&[damaged]).unwrap(), 0);
+        assert!(hive.is_dirty());
+
+        // Entry hash
+        let mut damaged = log;
+        *damaged.last_mut().unwrap() ^= 1;
+        assert_eq!(hive.apply_transaction_logs(&[damaged]).unwrap(), 0);
+        assert_eq!(hive.root_key().unwrap().name, "ROOT");
+    }
+
+    /// An old-format log of a hive with one bin, whose dirty vector marks
+    /// the bin's last 512-byte page, holding `fill`.
+    fn old_format_log(sequence: u32, fill: u8) -> Vec<u8> {
+        let mut log = base_block(FILE_TYPE_LOG_OLD, sequence, BASE_BLOCK_SIZE as u32);
+        log.extend(DIRTY_VECTOR_SIGNATURE);
+        log.push(0x80);
+        log.resize(LOG_BASE_BLOCK_SIZE + OLD_LOG_PAGE_SIZE, 0);
+        log.extend([fill; OLD_LOG_PAGE_SIZE]);
+        log
+    }
+
+    fn last_page(hive: &Hive) -> &[u8] {
+        &hive.data[hive.data.len() - OLD_LOG_PAGE_SIZE..]
+    }
+
+    #[test]
+    fn replays_old_format_logs_by_sequence_number() {
+        // Whatever the file order, the newer write (sequence 3) wins over
+        // the older one, and both are applied
+        for logs in [[old_format_log(2, 0x41), old_format_log(3, 0x42)],
+                     [old_format_log(3, 0x42), old_format_log(2, 0x41)]] {
+            let mut hive = dirty_hive();
+            assert_eq!(hive.apply_transaction_logs(&logs).unwrap(), 2);
+            assert!(!hive.is_dirty());
+            assert!(last_page(&hive).iter().all(|&byte| byte == 0x42));
+            assert_eq!(hive.root_key().unwrap().name, "ROOT");
+        }
+
+        // Logs older than the hive's last completed write are stale
+        let mut hive = dirty_hive();
+        assert_eq!(hive.apply_transaction_logs(&[old_format_log(0, 0x41)]).unwrap(), 0);
+        assert!(hive.is_dirty());
+    }
+
+    #[test]
+    fn skips_damaged_old_format_logs() {
+        let mut bad_checksum = old_format_log(3, 0x42);
+        bad_checksum[CHECKSUM_OFFSET] ^= 1;
+        let mut no_dirty_vector = old_format_log(3, 0x42);
+        no_dirty_vector[LOG_BASE_BLOCK_SIZE] = b'X';
+
+        let mut hive = dirty_hive();
+        let logs = [bad_checksum, no_dirty_vector, old_format_log(2, 0x41)];
+        assert_eq!(hive.apply_transaction_logs(&logs).unwrap(), 1);
+        assert!(last_page(&hive).iter().all(|&byte| byte == 0x41));
+    }
+
+    #[test]
+    fn refuses_to_grow_the_hive_past_what_the_logs_hold() {
+        let mut hive = dirty_hive();
+        let log = new_format_log(1, 1 << 30, 0, &[0x41; 512]);
+        assert!(hive.apply_transaction_logs(&[log]).is_err());
+        assert_eq!(hive.data.len(), 2 * BASE_BLOCK_SIZE);
+    }
+
+    /// Frees the cell at `offset`, as deleting its key or value does.
+    fn free_cell(hive: &mut [u8], offset: u32) {
+        let start = BASE_BLOCK_SIZE + offset as usize;
+        let size = LittleEndian::read_i32(&hive[start..start + 4]);
+        LittleEndian::write_i32(&mut hive[start..start + 4], size.abs());
+    }
+
+    fn set_parent(hive: &mut [u8], key: u32, parent: u32) {
+        let start = BASE_BLOCK_SIZE + key as usize + 4 + 16;
+        LittleEndian::write_u32(&mut hive[start..start + 4], parent);
+    }
+
+    /// A SOFTWARE hive whose live ROOT\Microsoft key lost its subkey Run,
+    /// with its value and own subkey Child; a key whose parent is gone; and
+    /// a value of a key that was overwritten.
+    fn hive_with_deleted_cells() -> (HiveFile, [u32; 5]) {
+        let mut builder = HiveBuilder::new();
+        let command: Vec<u8> = "C:\\Temp\\u.exe\0".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let data = builder.bins.len() as u32;
+        let updater = builder.value("Updater", REG_SZ, &command);
+        let value_list = builder.bins.len() as u32;
+        let run = builder.key("Run", 0, u32::MAX, &[updater]);
+        let child = builder.key("Child", 0, u32::MAX, &[]);
+        let lost = builder.key("Lost", 0, u32::MAX, &[]);
+        let dropped = builder.value("Dropped", REG_DWORD, &1u32.to_le_bytes());
+        let microsoft = builder.key("Microsoft", 0, u32::MAX, &[]);
+        let list = builder.list(b"lf", &[microsoft]);
+        let root = builder.key("ROOT", 1, list, &[]);
+
+        let mut hive = builder.build(root);
+        set_parent(&mut hive, run, microsoft);
+        set_parent(&mut hive, child, run);
+        set_parent(&mut hive, lost, 0x7FF8);
+        for cell in [data, updater, value_list, run, child, lost, dropped] {
+            free_cell(&mut hive, cell);
+        }
+
+        let hive_file = HiveFile {
+            path: r"Windows\System32\config\SOFTWARE".to_string(),
+            kind: HiveKind::Software,
+            user: None,
+            hive: Hive::new(hive).unwrap(),
+        };
+        (hive_file, [run, child, lost, updater, dropped])
+    }
+
+    fn details(event: &TimelineEvent) -> Vec<(&str, &[String])> {
+        event.details.iter().map(|detail| (detail.label.as_str(), detail.values.as_slice())).collect()
+    }
+
+    #[test]
+    fn finds_only_freed_key_and_value_cells() {
+        let (hive_file, [run, child, lost, updater, dropped]) = hive_with_deleted_cells();
+        let deleted = hive_file.hive.deleted_cells();
+
+        let keys: Vec<(u32, &str)> = deleted.keys.iter().map(|key| (key.offset, key.name.as_str())).collect();
+        assert_eq!(keys, [(run, "Run"), (child, "Child"), (lost, "Lost")]);
+        let values: Vec<(u32, &str)> = deleted.values.iter().map(|(offset, value)| (*offset, value.name.as_str())).collect();
+        assert_eq!(values, [(updater, "Updater"), (dropped, "Dropped")]);
+        assert_eq!(deleted.keys[0].values_with_offsets()[0].1.as_string().unwrap(), r"C:\Temp\u.exe");
+    }
+
+    #[test]
+    fn rebuilds_the_paths_of_deleted_keys() {
+        let (hive_file, [run, child, lost, _, dropped]) = hive_with_deleted_cells();
+        let mut live = Timeline::new();
+        let key_paths = add_key_events(&hive_file, &mut live);
+        assert_eq!(live.len(), 2);
+
+        let mut timeline = Timeline::new();
+        assert_eq!(add_deleted_key_events(&hive_file, &key_paths, &mut timeline), 3);
+        assert!(timeline.events.iter().all(|event| event.deleted));
+        let descriptions: Vec<&str> = timeline.events.iter().map(|event| event.description.as_str()).collect();
+        assert_eq!(descriptions, [
+            r"Registry key 'HKLM\SOFTWARE\Microsoft\Run' was last written.",
+            r"Registry key 'HKLM\SOFTWARE\Microsoft\Run\Child' was last written.",
+            r"Registry key 'HKLM\SOFTWARE\(unknown parent)\Lost' was last written.",
+            r"1 deleted values in HKLM\SOFTWARE could not be linked to their key; the time is the hive's last write.",
+        ]);
+
+        let recovered_from = |offset| [format!("Unallocated cell at offset 0x{:X}", offset)];
+        assert_eq!(details(&timeline.events[0]), [("Values", &[r"Updater (REG_SZ) = C:\Temp\u.exe".to_string()][..]),
+                                                  ("Recovered from", &recovered_from(run)[..])]);
+        assert_eq!(details(&timeline.events[1]), [("Recovered from", &recovered_from(child)[..])]);
+        assert_eq!(details(&timeline.events[2]), [("Recovered from", &recovered_from(lost)[..])]);
+        assert_eq!(details(&timeline.events[3]),
+                   [("Values", &[format!("Dropped (REG_DWORD) = 0x1 (1) [cell 0x{:X}]", dropped)][..])]);
+    }
+
+    #[test]
+    fn rejects_implausible_deleted_keys() {
+        let (mut hive_file, [run, child, ..]) = hive_with_deleted_cells();
+        // A 1975 timestamp and a name with control characters
+        let start = BASE_BLOCK_SIZE + run as usize + 8;
+        LittleEndian::write_u64(&mut hive_file.hive.data[start..start + 8], 118_024_416_000_000_000);
+        hive_file.hive.data[BASE_BLOCK_SIZE + child as usize + 4 + 76] = 0x07;
+
+        let names: Vec<String> = hive_file.hive.deleted_cells().keys.into_iter().map(|key| key.name).collect();
+        assert_eq!(names, ["Lost"]);
+    }
+}
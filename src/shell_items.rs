@@ -0,0 +1,467 @@
+use byteorder::{ByteOrder, LittleEndian};
+use chrono::{NaiveDate, NaiveDateTime};
+
+use crate::util::read_utf16_string;
+
+/// Shell item class types (the high nibble of the type byte, with the low
+/// nibble holding flags).
+const ROOT_FOLDER: u8 = 0x1F;
+const CONTROL_PANEL_CATEGORY: u8 = 0x01;
+const VOLUME_CLASS: u8 = 0x20;
+const FILE_ENTRY_CLASS: u8 = 0x30;
+const NETWORK_CLASS: u8 = 0x40;
+const COMPRESSED_FOLDER: u8 = 0x52;
+const CONTROL_PANEL_ITEM: u8 = 0x71;
+const DELEGATE_ITEM: u8 = 0x74;
+
+/// File entry flags: directory, and a UTF-16 rather than ANSI short name.
+const FILE_ENTRY_DIRECTORY: u8 = 0x01;
+const FILE_ENTRY_UNICODE: u8 = 0x04;
+
+const FILE_ENTRY_EXTENSION_SIGNATURE: u32 = 0xBEEF0004;
+const CONTROL_PANEL_CATEGORY_SIGNATURE: u32 = 0x39DE2184;
+/// Delegate items wrap a file entry after this signature.
+const DELEGATE_SIGNATURE: &[u8; 4] = b"CFSF";
+const DELEGATE_FILE_ENTRY_OFFSET: usize = 10;
+
+/// Root folders of an ID list by their shell folder GUID.
+const KNOWN_FOLDERS: &[(&str, &str)] = &[
+    ("20D04FE0-3AEA-1069-A2D8-08002B30309D", "My Computer"),
+    ("59031A47-3F72-44A7-89C5-5595FE6B30EE", "User Files"),
+    ("F02C1A0D-BE21-4350-88B0-7367FC96EF3C", "Network"),
+    ("208D2C60-3AEA-1069-A2D7-08002B30309D", "My Network Places"),
+    ("645FF040-5081-101B-9F08-00AA002F954E", "Recycle Bin"),
+    ("21EC2020-3AEA-1069-A2DD-08002B30309D", "Control Panel"),
+    ("26EE0668-A00A-44D7-9371-BEB064C98683", "Control Panel"),
+    ("031E4825-7B94-4DC3-B131-E946B44C8DD5", "Libraries"),
+    ("679F85CB-0220-4080-B29B-5540CC05AAB6", "Quick Access"),
+    ("F874310E-B6B7-47DC-BC84-B9E6B38F5903", "Home"),
+    ("B4BFCC3A-DB2C-424C-B029-7FE99A87C641", "Desktop"),
+    ("FDD39AD0-238F-46AF-ADB4-6C85480369C7", "Documents"),
+    ("450D8FBA-AD25-11D0-98A8-0800361B1103", "Documents"),
+    ("374DE290-123F-4565-9164-39C4925E467B", "Downloads"),
+    ("088E3905-0323-4B02-9826-5D99428E115F", "Downloads"),
+    ("33E28130-4E1E-4676-835A-98395C3BC3BB", "Pictures"),
+    ("4BD8D571-6D19-48D3-BE97-422220080E43", "Music"),
+    ("18989B1D-99B5-455B-841C-AB7C74E4DDFC", "Videos"),
+    ("1AC14E77-02E7-4E5D-B744-2EB1AE5198B7", "System32"),
+    ("F38BF404-1D43-42F2-9305-67DE0B28FC23", "Windows"),
+    ("6D809377-6AF0-444B-8957-A3773F02200E", "Program Files"),
+    ("7C5A40EF-A0FB-4BFC-874A-C0F2E0B9FA8E", "Program Files (x86)"),
+    ("BB06C0E4-D293-4F75-8A90-CB05B6477EEE", "System"),
+    ("7B81BE6A-CE2B-4676-A29E-EB907A5126C5", "Programs and Features"),
+    ("D20EA4E1-3957-11D2-A40B-0C5020524153", "Administrative Tools"),
+    ("A8A91A66-3A7D-4424-8D24-04E180695C7A", "Devices and Printers"),
+    ("7007ACC7-3202-11D1-AAD2-00805FC1270E", "Network Connections"),
+    ("E2E7934B-DCE5-43C4-9576-7FE4F75E7480", "Date and Time"),
+    ("60632754-C523-4B62-B45C-4172DA012619", "User Accounts"),
+    ("D555645E-D4F8-4C29-A827-D93C859C4F2A", "Ease of Access Center"),
+    ("BB64F8A7-BEE7-4E1A-AB8D-7D8273F7FDB6", "Security and Maintenance"),
+];
+
+/// Control Panel category view items by category number.
+const CONTROL_PANEL_CATEGORIES: &[&str] = &[
+    "All Control Panel Items",
+    "Appearance and Personalization",
+    "Hardware and Sound",
+    "Network and Internet",
+    "Sounds, Speech, and Audio Devices",
+    "System and Security",
+    "Clock, Language, and Region",
+    "Ease of Access",
+    "Programs",
+    "User Accounts",
+    "Security Center",
+    "Mobile PC",
+];
+
+/// One item of a shell ID list (PIDL).
+#[derive(Debug, Clone)]
+pub enum ShellItem {
+    /// A root shell folder such as My Computer or a known folder.
+    RootFolder(String),
+    /// A drive, e.g. `C:\`.
+    Volume(String),
+    /// A file or directory.
+    FileEntry(FileEntry),
+    /// A network share, server or domain, e.g. `\\server\share`.
+    Network(String),
+    /// A folder inside a zip file.
+    CompressedFolder(String),
+    /// A Control Panel category or applet.
+    ControlPanel(String),
+    /// An item of a type not decoded, by its type byte.
+    Unknown(u8),
+}
+
+/// A file entry item, with what its 0xBEEF0004 extension block adds.
+#[derive(Debug, Clone)]
+pub struct FileEntry {
+    /// The long name when the extension block records one, else the short
+    /// (8.3) name.
+    pub name: String,
+    pub is_directory: bool,
+    pub modified: Option<NaiveDateTime>,
+    pub created: Option<NaiveDateTime>,
+    pub accessed: Option<NaiveDateTime>,
+    /// MFT entry number and sequence number, recorded from Windows 7 on.
+    pub mft_reference: Option<(u64, u16)>,
+}
+
+impl ShellItem {
+    pub fn name(&self) -> String {
+        match self {
+            ShellItem::RootFolder(name) | ShellItem::Volume(name) | ShellItem::Network(name)
+            | ShellItem::CompressedFolder(name) | ShellItem::ControlPanel(name) => name.clone(),
+            ShellItem::FileEntry(entry) => entry.name.clone(),
+            ShellItem::Unknown(class) => format!("[shell item 0x{:02X}]", class),
+        }
+    }
+
+    /// What kind of item this is, for display.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ShellItem::RootFolder(_) => "Root folder",
+            ShellItem::Volume(_) => "Volume",
+            ShellItem::FileEntry(entry) if entry.is_directory => "Directory",
+            ShellItem::FileEntry(_) => "File",
+            ShellItem::Network(_) => "Network location",
+            ShellItem::CompressedFolder(_) => "Compressed folder",
+            ShellItem::ControlPanel(_) => "Control Panel",
+            ShellItem::Unknown(_) => "Unknown",
+        }
+    }
+}
+
+/// Splits a shell ID list into its items, stopping at the terminating
+/// empty item or the first truncated one.
+pub fn parse_id_list(data: &[u8]) -> Vec<ShellItem> {
+    let mut items = Vec::new();
+    let mut offset = 0;
+    while let Some(size) = data.get(offset..offset + 2).map(LittleEndian::read_u16) {
+        let size = size as usize;
+        let Some(item) = data.get(offset..offset + size).filter(|_| size >= 3) else {
+            break;
+        };
+        items.push(parse_item(item));
+        offset += size;
+    }
+    items
+}
+
+/// Decodes one shell item, starting with its size field.
+pub fn parse_item(item: &[u8]) -> ShellItem {
+    let class_type = item[2];
+    let decoded = match class_type {
+        ROOT_FOLDER => item.get(4..20).map(|guid| ShellItem::RootFolder(known_folder_name(guid))),
+        CONTROL_PANEL_CATEGORY => parse_control_panel_category(item),
+        COMPRESSED_FOLDER => parse_compressed_folder(item),
+        CONTROL_PANEL_ITEM => item.get(14..30).map(|guid| ShellItem::ControlPanel(known_folder_name(guid))),
+        DELEGATE_ITEM => parse_delegate_item(item),
+        _ => match class_type & 0x70 {
+            VOLUME_CLASS => item.get(3..).map(|name| ShellItem::Volume(read_ansi_string(name))),
+            FILE_ENTRY_CLASS => parse_file_entry(item, item).map(ShellItem::FileEntry),
+            NETWORK_CLASS => item.get(5..).map(|name| ShellItem::Network(read_ansi_string(name))),
+            _ => None,
+        },
+    };
+    decoded.unwrap_or(ShellItem::Unknown(class_type))
+}
+
+/// A file entry item: size, DOS timestamp and attributes, then the short
+/// name, then extension blocks of which 0xBEEF0004 holds the long name,
+/// the creation and access times and the MFT reference. `entry` is the
+/// file entry itself, `item` the shell item that contains it.
+fn parse_file_entry(entry: &[u8], item: &[u8]) -> Option<FileEntry> {
+    let flags = entry[2] & 0x0F;
+    let short_name = entry.get(14..)?;
+    let short_name = if flags & FILE_ENTRY_UNICODE != 0 {
+        read_utf16_string(short_name)
+    } else {
+        read_ansi_string(short_name)
+    };
+
+    let mut file_entry = FileEntry {
+        name: short_name,
+        is_directory: flags & FILE_ENTRY_DIRECTORY != 0,
+        modified: dos_datetime(entry.get(8..12)?),
+        created: None,
+        accessed: None,
+        mft_reference: None,
+    };
+
+    if let Some(block) = file_entry_extension(item) {
+        let version = LittleEndian::read_u16(&block[2..4]);
+        file_entry.created = block.get(8..12).and_then(dos_datetime);
+        file_entry.accessed = block.get(12..16).and_then(dos_datetime);
+        if version >= 7 {
+            file_entry.mft_reference = block.get(20..28)
+                .map(LittleEndian::read_u64)
+                .filter(|&reference| reference != 0)
+                .map(|reference| (reference & 0xFFFF_FFFF_FFFF, (reference >> 48) as u16));
+        }
+        if let Some(name) = extension_long_name(block, version).filter(|name| !name.is_empty()) {
+            file_entry.name = name;
+        }
+    }
+    Some(file_entry)
+}
+
+/// Finds the 0xBEEF0004 extension block by its signature, which follows
+/// the block's size and version. The block ends two bytes before the item,
+/// which repeat its offset.
+fn file_entry_extension(item: &[u8]) -> Option<&[u8]> {
+    let signature = FILE_ENTRY_EXTENSION_SIGNATURE.to_le_bytes();
+    let position = item.windows(4).position(|window| window == signature)?;
+    let block = item.get(position.checked_sub(4)?..item.len().checked_sub(2)?)?;
+    (block.len() >= 16).then_some(block)
+}
+
+/// The long name's offset in the extension block grows with its version:
+/// Windows 7 (8) and Windows 8+ (9) add fields before it.
+fn extension_long_name(block: &[u8], version: u16) -> Option<String> {
+    let offset = match version {
+        3..=6 => 20,
+        7 => 38,
+        8 => 42,
+        9.. => 46,
+        _ => return None,
+    };
+    Some(read_utf16_string(block.get(offset..)?))
+}
+
+/// Windows 7+ delegate items (e.g. folders under User Files) carry a
+/// regular file entry after the "CFSF" signature.
+fn parse_delegate_item(item: &[u8]) -> Option<ShellItem> {
+    if item.get(6..10)? != DELEGATE_SIGNATURE {
+        return None;
+    }
+    let entry = item.get(DELEGATE_FILE_ENTRY_OFFSET..)?;
+    if entry.len() < 3 || entry[2] & 0x70 != FILE_ENTRY_CLASS {
+        return None;
+    }
+    parse_file_entry(entry, item).map(ShellItem::FileEntry)
+}
+
+/// A Control Panel category view, e.g. "System and Security".
+fn parse_control_panel_category(item: &[u8]) -> Option<ShellItem> {
+    if LittleEndian::read_u32(item.get(4..8)?) != CONTROL_PANEL_CATEGORY_SIGNATURE {
+        return None;
+    }
+    let category = LittleEndian::read_u32(item.get(8..12)?) as usize;
+    let name = CONTROL_PANEL_CATEGORIES.get(category)
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| format!("Control Panel category {}", category));
+    Some(ShellItem::ControlPanel(name))
+}
+
+/// A folder browsed inside a zip file: the counts of UTF-16 characters of
+/// its name and of a second (usually empty) name at 0x54 and 0x58, and the
+/// names from 0x5C.
+fn parse_compressed_folder(item: &[u8]) -> Option<ShellItem> {
+    let length = LittleEndian::read_u32(item.get(0x54..0x58)?) as usize;
+    let name = item.get(0x5C..0x5C + length * 2)?;
+    let name = read_utf16_string(name);
+    (!name.is_empty()).then_some(ShellItem::CompressedFolder(name))
+}
+
+/// Joins the names of an ID list into a path, e.g. `C:\Users\alice`.
+/// A root folder such as "My Computer" or "Network" is left out before a
+/// drive or a network location.
+pub fn id_list_path(items: &[ShellItem]) -> String {
+    let mut path = String::new();
+    for (index, item) in items.iter().enumerate() {
+        if matches!(item, ShellItem::RootFolder(_))
+            && matches!(items.get(index + 1), Some(ShellItem::Volume(_) | ShellItem::Network(_))) {
+            continue;
+        }
+        let name = item.name();
+        if !path.is_empty() && !path.ends_with('\\') {
+            path.push('\\');
+        }
+        path.push_str(&name);
+    }
+    path
+}
+
+fn known_folder_name(guid: &[u8]) -> String {
+    let guid = format_guid(guid);
+    KNOWN_FOLDERS.iter()
+        .find(|(known, _)| *known == guid)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| format!("{{{}}}", guid))
+}
+
+/// Formats a little-endian GUID as `XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX`.
+pub fn format_guid(guid: &[u8]) -> String {
+    format!("{:08X}-{:04X}-{:04X}-{}-{}",
+            LittleEndian::read_u32(&guid[0..4]),
+            LittleEndian::read_u16(&guid[4..6]),
+            LittleEndian::read_u16(&guid[6..8]),
+            guid[8..10].iter().map(|byte| format!("{:02X}", byte)).collect::<String>(),
+            guid[10..16].iter().map(|byte| format!("{:02X}", byte)).collect::<String>())
+}
+
+/// Decodes a FAT date and time (date word first), as stored in shell items.
+/// Zero means not set.
+fn dos_datetime(data: &[u8]) -> Option<NaiveDateTime> {
+    let date = LittleEndian::read_u16(&data[0..2]);
+    let time = LittleEndian::read_u16(&data[2..4]);
+    if date == 0 {
+        return None;
+    }
+    NaiveDate::from_ymd_opt(1980 + (date >> 9) as i32, ((date >> 5) & 0x0F) as u32, (date & 0x1F) as u32)?
+        .and_hms_opt((time >> 11) as u32, ((time >> 5) & 0x3F) as u32, (time & 0x1F) as u32 * 2)
+}
+
+fn read_ansi_string(data: &[u8]) -> String {
+    data.iter()
+        .take_while(|&&byte| byte != 0)
+        .map(|&byte| byte as char)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-01-15 10:02:04, 2023-06-01 08:30:00 and 2024-02-29 23:59:58 as
+    /// FAT date and time pairs.
+    const MODIFIED: u32 = dos(2024, 1, 15, 10, 2, 4);
+    const CREATED: u32 = dos(2023, 6, 1, 8, 30, 0);
+    const ACCESSED: u32 = dos(2024, 2, 29, 23, 59, 58);
+
+    const fn dos(year: u32, month: u32, day: u32, hour: u32, minute: u32, second: u32) -> u32 {
+        let date = (year - 1980) << 9 | month << 5 | day;
+        let time = hour << 11 | minute << 5 | (second / 2);
+        date | time << 16
+    }
+
+    fn datetime(value: u32) -> Option<NaiveDateTime> {
+        dos_datetime(&value.to_le_bytes())
+    }
+
+    fn item(body: &[u8]) -> Vec<u8> {
+        let mut item = ((body.len() + 2) as u16).to_le_bytes().to_vec();
+        item.extend(body);
+        item
+    }
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().chain([0]).flat_map(u16::to_le_bytes).collect()
+    }
+
+    /// A directory entry with an 8.3 name and a 0xBEEF0004 block of the
+    /// given version holding `name`.
+    fn directory_entry(name: &str, version: u16, mft_reference: u64) -> Vec<u8> {
+        let mut body = vec![FILE_ENTRY_CLASS | FILE_ENTRY_DIRECTORY, 0];
+        body.extend(0u32.to_le_bytes());
+        body.extend(MODIFIED.to_le_bytes());
+        body.extend(0x10u16.to_le_bytes());
+        body.extend(b"LONGFO~1\0\0");
+        let extension_offset = body.len() + 2;
+
+        let mut extension = vec![0, 0];
+        extension.extend(version.to_le_bytes());
+        extension.extend(FILE_ENTRY_EXTENSION_SIGNATURE.to_le_bytes());
+        extension.extend(CREATED.to_le_bytes());
+        extension.extend(ACCESSED.to_le_bytes());
+        extension.extend(0x2Eu16.to_le_bytes());
+        if version >= 7 {
+            extension.extend([0; 2]);
+            extension.extend(mft_reference.to_le_bytes());
+            extension.extend([0; 8]);
+        }
+        extension.extend([0; 2]);
+        if version >= 8 {
+            extension.extend([0; 4]);
+        }
+        if version >= 9 {
+            extension.extend([0; 4]);
+        }
+        extension.extend(utf16(name));
+        extension.extend((extension_offset as u16).to_le_bytes());
+        let extension_size = extension.len() as u16;
+        extension[0..2].copy_from_slice(&extension_size.to_le_bytes());
+
+        body.extend(extension);
+        item(&body)
+    }
+
+    fn my_computer() -> Vec<u8> {
+        let mut body = vec![ROOT_FOLDER, 0x50];
+        body.extend([0xE0, 0x4F, 0xD0, 0x20, 0xEA, 0x3A, 0x69, 0x10, 0xA2, 0xD8, 0x08, 0x00, 0x2B, 0x30, 0x30, 0x9D]);
+        item(&body)
+    }
+
+    fn volume(letter: char) -> Vec<u8> {
+        let mut body = vec![VOLUME_CLASS | 0x0F];
+        body.extend(format!("{}:\\", letter).as_bytes());
+        body.resize(23, 0);
+        item(&body)
+    }
+
+    #[test]
+    fn decodes_dos_datetimes() {
+        assert_eq!(datetime(MODIFIED).unwrap().to_string(), "2024-01-15 10:02:04");
+        assert_eq!(datetime(ACCESSED).unwrap().to_string(), "2024-02-29 23:59:58");
+        assert_eq!(datetime(0), None);
+        // Month 13, and 2023-02-29
+        assert_eq!(datetime(dos(2024, 13, 1, 0, 0, 0)), None);
+        assert_eq!(datetime(dos(2023, 2, 29, 0, 0, 0)), None);
+    }
+
+    #[test]
+    fn splits_id_lists_into_items() {
+        let mut data = my_computer();
+        data.extend(volume('C'));
+        data.extend(directory_entry("Users", 9, 0));
+        data.extend(directory_entry("alice", 9, 0));
+        data.extend([0, 0]);
+        data.extend(volume('D'));
+
+        let items = parse_id_list(&data);
+        let names: Vec<String> = items.iter().map(ShellItem::name).collect();
+        assert_eq!(names, ["My Computer", r"C:\", "Users", "alice"]);
+        assert_eq!(id_list_path(&items), r"C:\Users\alice");
+        assert_eq!(items[2].kind(), "Directory");
+    }
+
+    #[test]
+    fn stops_at_a_truncated_item() {
+        let mut data = volume('C');
+        let mut truncated = directory_entry("Users", 9, 0);
+        truncated.truncate(truncated.len() - 1);
+        data.extend(truncated);
+        assert_eq!(parse_id_list(&data).len(), 1);
+        assert!(parse_id_list(&[0x02, 0x00, 0x31]).is_empty());
+    }
+
+    #[test]
+    fn reads_the_long_name_at_each_extension_version() {
+        let reference = 1234 | (5 << 48);
+        for version in [3, 7, 8, 9] {
+            let ShellItem::FileEntry(entry) = parse_item(&directory_entry("Long Folder Name", version, reference)) else {
+                panic!("version {} did not decode as a file entry", version);
+            };
+            assert_eq!(entry.name, "Long Folder Name", "version {}", version);
+            assert!(entry.is_directory);
+            assert_eq!(entry.modified, datetime(MODIFIED));
+            assert_eq!(entry.created, datetime(CREATED));
+            assert_eq!(entry.accessed, datetime(ACCESSED));
+            let expected_reference = (version >= 7).then_some((1234, 5));
+            assert_eq!(entry.mft_reference, expected_reference, "version {}", version);
+        }
+    }
+
+    #[test]
+    fn keeps_the_short_name_without_an_extension_block() {
+        let mut entry = directory_entry("Long Folder Name", 9, 0);
+        let signature = entry.windows(4).position(|window| window == FILE_ENTRY_EXTENSION_SIGNATURE.to_le_bytes()).unwrap();
+        entry.truncate(signature - 4);
+        let size = entry.len() as u16;
+        entry[0..2].copy_from_slice(&size.to_le_bytes());
+
+        assert_eq!(parse_item(&entry).name(), "LONGFO~1");
+    }
+}
@@ -0,0 +1,281 @@
+use byteorder::{ByteOrder, LittleEndian};
+use chrono::{DateTime, Utc};
+
+use crate::registry_parser::{HiveFile, HiveKind, Key};
+use crate::shell_items::{self, ShellItem};
+use crate::timeline::{EventType, Timeline, TimelineEvent};
+
+/// BagMRU roots: Windows 7+ keeps them in UsrClass.dat, XP and Vista (and
+/// the desktop on later versions) in NTUSER.DAT.
+const NTUSER_BAGMRU_KEYS: &[&str] = &[
+    r"Software\Microsoft\Windows\Shell\BagMRU",
+    r"Software\Microsoft\Windows\ShellNoRoam\BagMRU",
+];
+const USRCLASS_BAGMRU_KEYS: &[&str] = &[
+    r"Local Settings\Software\Microsoft\Windows\Shell\BagMRU",
+    r"Wow6432Node\Local Settings\Software\Microsoft\Windows\Shell\BagMRU",
+];
+
+/// Deeper BagMRU trees only come from corrupt hives.
+const MAX_BAGMRU_DEPTH: usize = 64;
+
+/// Adds a folder access event for the most recent folder of every BagMRU key
+/// in the user's ShellBags, with its path rebuilt from the shell items of its
+/// BagMRU ancestors and the key's other folders listed. Returns the number of
+/// events.
+pub fn add_shellbag_events(hive_file: &HiveFile, timeline: &mut Timeline) -> usize {
+    let roots = match hive_file.kind {
+        HiveKind::NtUser => NTUSER_BAGMRU_KEYS,
+        HiveKind::UsrClass => USRCLASS_BAGMRU_KEYS,
+        _ => return 0,
+    };
+    let mut shellbags = ShellBags {
+        user: hive_file.user.as_deref().unwrap_or("unknown"),
+        hive_path: &hive_file.path,
+        timeline,
+        event_count: 0,
+    };
+    for root in roots {
+        if let Some(key) = hive_file.hive.key(root) {
+            shellbags.walk(&key, "BagMRU", &[], 0);
+        }
+    }
+    shellbags.event_count
+}
+
+struct ShellBags<'a> {
+    user: &'a str,
+    hive_path: &'a str,
+    timeline: &'a mut Timeline,
+    event_count: usize,
+}
+
+/// A numbered value of a BagMRU key: the shell items of the folder's path
+/// and the subkey holding its own children.
+struct Entry<'h> {
+    number: u32,
+    items: Vec<ShellItem>,
+    label: String,
+    subkey: Option<Key<'h>>,
+}
+
+impl ShellBags<'_> {
+    /// Each numbered value of a BagMRU key holds one shell item, a child of
+    /// the folder the key stands for; the subkey of the same number holds
+    /// that child's own children.
+    fn walk(&mut self, key: &Key, key_label: &str, parents: &[ShellItem], depth: usize) {
+        if depth > MAX_BAGMRU_DEPTH {
+            return;
+        }
+        let order: Vec<u32> = key.value("MRUListEx")
+            .map(|list| {
+                list.data.chunks_exact(4)
+                    .map(LittleEndian::read_u32)
+                    .take_while(|&number| number != u32::MAX)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut entries = Vec::new();
+        for value in key.values() {
+            let Ok(number) = value.name.parse::<u32>() else {
+                continue;
+            };
+            let Some(item) = shell_items::parse_id_list(&value.data).into_iter().next() else {
+                continue;
+            };
+            let mut items = parents.to_vec();
+            items.push(item);
+            entries.push(Entry {
+                number,
+                items,
+                label: format!(r"{}\{}", key_label, number),
+                subkey: key.subkey(&value.name),
+            });
+        }
+        entries.sort_by_key(|entry| order.iter().position(|&number| number == entry.number).unwrap_or(usize::MAX));
+
+        if let Some(time) = key.last_written {
+            self.add_key_events(time, &entries, order.first().copied());
+        }
+        for entry in &entries {
+            if let Some(subkey) = &entry.subkey {
+                self.walk(subkey, &entry.label, &entry.items, depth + 1);
+            }
+        }
+    }
+
+    /// Only the most recently used entry is known to have been browsed at
+    /// the key's LastWrite time; the others were browsed at some earlier
+    /// time and are listed on its event. The LastWrite of an entry's own
+    /// subkey dates its most recent child, which gets its own event.
+    fn add_key_events(&mut self, time: DateTime<Utc>, entries: &[Entry], most_recent: Option<u32>) {
+        match entries.split_first().filter(|(entry, _)| Some(entry.number) == most_recent) {
+            Some((entry, earlier)) => {
+                let earlier = earlier.iter()
+                    .map(|entry| format!("{} ({})", shell_items::id_list_path(&entry.items), entry.label))
+                    .collect();
+                let event = self.add_event(time, "LastWrite of the parent BagMRU key (most recent entry)",
+                                           &entry.items, &entry.label);
+                event.add_detail("Browsed earlier (time unknown)", earlier);
+            }
+            // Without a most recent entry, the key's LastWrite is only an
+            // upper bound for every entry
+            None => {
+                for entry in entries {
+                    self.add_event(time, "LastWrite of the parent BagMRU key (browsed at or before this time)",
+                                   &entry.items, &entry.label);
+                }
+            }
+        }
+    }
+
+    fn add_event(&mut self, time: DateTime<Utc>, time_source: &str, items: &[ShellItem],
+                 label: &str) -> &mut TimelineEvent {
+        let path = shell_items::id_list_path(items);
+        let item = &items[items.len() - 1];
+        let description = format!("User '{}' browsed to '{}' (ShellBags).", self.user, path);
+        let event = self.timeline.add_registry_event(time, EventType::FolderAccess, description, self.hive_path);
+        event.add_detail("Time source", vec![time_source.to_string()]);
+        event.add_detail("BagMRU key", vec![label.to_string()]);
+        event.add_detail("Shell item", vec![item.kind().to_string()]);
+
+        if let ShellItem::FileEntry(entry) = item {
+            let timestamps: Vec<String> = [("Created", entry.created), ("Modified", entry.modified),
+                                           ("Accessed", entry.accessed)]
+                .iter()
+                .filter_map(|(label, time)| {
+                    time.map(|time| format!("{} {}", label, time.format("%Y-%m-%d %H:%M:%S")))
+                })
+                .collect();
+            if !timestamps.is_empty() {
+                event.add_detail("Folder timestamps", timestamps);
+            }
+            if let Some((entry_number, sequence)) = entry.mft_reference {
+                event.add_detail("MFT reference", vec![format!("Entry {}, sequence {}", entry_number, sequence)]);
+            }
+        }
+        self.event_count += 1;
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry_parser::tests::HiveBuilder;
+    use crate::registry_parser::{Hive, REG_BINARY};
+
+    const HIVE_PATH: &str = r"Users\alice\AppData\Local\Microsoft\Windows\UsrClass.dat";
+
+    /// A one-item ID list.
+    fn id_list(class_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut list = ((body.len() + 3) as u16).to_le_bytes().to_vec();
+        list.push(class_type);
+        list.extend(body);
+        list.extend([0, 0]);
+        list
+    }
+
+    fn volume(name: &str) -> Vec<u8> {
+        id_list(0x2F, format!("{}\0", name).as_bytes())
+    }
+
+    /// A directory entry with an ANSI name and no extension block.
+    fn directory(name: &str) -> Vec<u8> {
+        let mut body = vec![0u8; 11];
+        body[9..11].copy_from_slice(&0x10u16.to_le_bytes());
+        body.extend(format!("{}\0", name).as_bytes());
+        id_list(0x31, &body)
+    }
+
+    fn mru_list_ex(numbers: &[u32]) -> Vec<u8> {
+        numbers.iter().chain(&[u32::MAX]).flat_map(|number| number.to_le_bytes()).collect()
+    }
+
+    fn walk(hive: &Hive) -> Timeline {
+        let mut timeline = Timeline::new();
+        let mut shellbags = ShellBags { user: "alice", hive_path: HIVE_PATH, timeline: &mut timeline, event_count: 0 };
+        shellbags.walk(&hive.root_key().unwrap(), "BagMRU", &[], 0);
+        let event_count = shellbags.event_count;
+        assert_eq!(event_count, timeline.events.len());
+        timeline
+    }
+
+    fn detail<'e>(event: &'e TimelineEvent, label: &str) -> Option<&'e [String]> {
+        event.details.iter().find(|detail| detail.label == label).map(|detail| detail.values.as_slice())
+    }
+
+    /// BagMRU holds C:\ (0) and D:\ (1, most recent); C:\ holds Users (0,
+    /// most recent), Temp (1) and Windows (2), of which Users and Windows
+    /// have subkeys; Users holds alice.
+    fn sample_hive() -> Hive {
+        let mut builder = HiveBuilder::new();
+        let users_values = [
+            builder.value("0", REG_BINARY, &directory("alice")),
+            builder.value("MRUListEx", REG_BINARY, &mru_list_ex(&[0])),
+        ];
+        let users = builder.key("0", 0, u32::MAX, &users_values);
+        let windows = builder.key("2", 0, u32::MAX, &[]);
+
+        let drive_values = [
+            builder.value("0", REG_BINARY, &directory("Users")),
+            builder.value("1", REG_BINARY, &directory("Temp")),
+            builder.value("2", REG_BINARY, &directory("Windows")),
+            builder.value("MRUListEx", REG_BINARY, &mru_list_ex(&[0, 2, 1])),
+        ];
+        let drive_subkeys = builder.list(b"lh", &[users, windows]);
+        let drive = builder.key("0", 2, drive_subkeys, &drive_values);
+
+        let root_values = [
+            builder.value("0", REG_BINARY, &volume(r"C:\")),
+            builder.value("1", REG_BINARY, &volume(r"D:\")),
+            builder.value("MRUListEx", REG_BINARY, &mru_list_ex(&[1, 0])),
+        ];
+        let root_subkeys = builder.list(b"lh", &[drive]);
+        let root = builder.key("BagMRU", 1, root_subkeys, &root_values);
+        Hive::new(builder.build(root)).unwrap()
+    }
+
+    #[test]
+    fn lists_earlier_entries_on_the_most_recent_entry() {
+        let timeline = walk(&sample_hive());
+        let descriptions: Vec<&str> = timeline.events.iter().map(|event| event.description.as_str()).collect();
+        assert_eq!(descriptions, [
+            r"User 'alice' browsed to 'D:\' (ShellBags).",
+            r"User 'alice' browsed to 'C:\Users' (ShellBags).",
+            r"User 'alice' browsed to 'C:\Users\alice' (ShellBags).",
+        ]);
+
+        let [drives, users, alice] = &timeline.events[..] else {
+            unreachable!();
+        };
+        assert!(timeline.events.iter().all(|event| matches!(event.event_type, EventType::FolderAccess)
+            && event.timestamp.to_rfc3339() == "2024-01-15T10:02:03.456789+00:00"
+            && detail(event, "Time source") == Some(&["LastWrite of the parent BagMRU key (most recent entry)".to_string()])));
+        assert_eq!(detail(drives, "BagMRU key"), Some(&[r"BagMRU\1".to_string()][..]));
+        assert_eq!(detail(drives, "Browsed earlier (time unknown)"), Some(&[r"C:\ (BagMRU\0)".to_string()][..]));
+        // Earlier entries in MRU order, whether or not they have a subkey
+        assert_eq!(detail(users, "Browsed earlier (time unknown)"),
+                   Some(&[r"C:\Windows (BagMRU\0\2)".to_string(), r"C:\Temp (BagMRU\0\1)".to_string()][..]));
+        assert_eq!(detail(users, "Shell item"), Some(&["Directory".to_string()][..]));
+        assert_eq!(detail(alice, "BagMRU key"), Some(&[r"BagMRU\0\0\0".to_string()][..]));
+        assert_eq!(detail(alice, "Browsed earlier (time unknown)"), None);
+    }
+
+    #[test]
+    fn dates_every_entry_by_its_key_without_a_most_recent_entry() {
+        let mut builder = HiveBuilder::new();
+        let values = [
+            builder.value("0", REG_BINARY, &volume(r"C:\")),
+            builder.value("1", REG_BINARY, &volume(r"D:\")),
+        ];
+        let root = builder.key("BagMRU", 0, u32::MAX, &values);
+        let timeline = walk(&Hive::new(builder.build(root)).unwrap());
+
+        let descriptions: Vec<&str> = timeline.events.iter().map(|event| event.description.as_str()).collect();
+        assert_eq!(descriptions, [r"User 'alice' browsed to 'C:\' (ShellBags).", r"User 'alice' browsed to 'D:\' (ShellBags)."]);
+        assert!(timeline.events.iter().all(|event| detail(event, "Time source")
+            == Some(&["LastWrite of the parent BagMRU key (browsed at or before this time)".to_string()])));
+    }
+}
@@ -7,6 +7,99 @@ pub struct TimelineEvent {
     pub event_type: EventType,
     pub description: String,
     pub source_artifact: String,
+    /// Volume the artifact was found on, e.g. `Partition 3 (NTFS)`.
+    pub volume: Option<String>,
+    /// For MFT events, the attribute the timestamp was read from.
+    pub timestamp_source: Option<TimestampSource>,
+    /// Set when an analyzer considers the event suspicious.
+    pub alert: Option<Alert>,
+    /// The event comes from a deleted file, directory or registry key.
+    pub deleted: bool,
+    /// The event was carved from unallocated space rather than read from a
+    /// live artifact.
+    pub recovered: bool,
+    /// Supporting evidence listed under the description, e.g. the DOS short
+    /// name of a file.
+    pub details: Vec<EventDetail>,
+    /// Name/value pairs of the source record, e.g. every field of an event
+    /// log record in passthrough mode.
+    pub fields: Vec<(String, String)>,
+}
+
+/// A labelled list of values attached to an event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventDetail {
+    pub label: String,
+    pub values: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Low => write!(f, "Low"),
+            Severity::Medium => write!(f, "Medium"),
+            Severity::High => write!(f, "High"),
+        }
+    }
+}
+
+/// An analyzer finding attached to an event, e.g. a timestomping indicator.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    pub severity: Severity,
+    pub explanation: String,
+}
+
+/// The two sets of MACB timestamps every MFT record carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimestampSource {
+    StandardInformation,
+    FileName,
+}
+
+impl std::fmt::Display for TimestampSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimestampSource::StandardInformation => write!(f, "$SI"),
+            TimestampSource::FileName => write!(f, "$FN"),
+        }
+    }
+}
+
+impl TimelineEvent {
+    pub fn new(timestamp: DateTime<Utc>, event_type: EventType, description: String,
+               source_artifact: &str) -> Self {
+        TimelineEvent {
+            timestamp,
+            event_type,
+            description,
+            source_artifact: source_artifact.to_string(),
+            volume: None,
+            timestamp_source: None,
+            alert: None,
+            deleted: false,
+            recovered: false,
+            details: Vec::new(),
+            fields: Vec::new(),
+        }
+    }
+    
+    /// Attaches a list of values, unless it is empty.
+    pub fn add_detail(&mut self, label: &str, values: Vec<String>) {
+        if !values.is_empty() {
+            self.details.push(EventDetail {
+                label: label.to_string(),
+                values,
+            });
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,8 +109,37 @@ pub enum EventType {
     FileAccess,
     FileMftChange,
     UserLogon,
+    FailedLogon,
+    UserLogoff,
+    ExplicitCredentialLogon,
+    SpecialPrivilegeLogon,
+    ProcessCreation,
+    AccountCreation,
+    AccountEnabled,
+    PasswordReset,
+    GroupMembershipChange,
+    AccountChange,
+    ScheduledTaskCreation,
+    AuditLogCleared,
+    KerberosTgtRequest,
+    KerberosServiceTicket,
+    NtlmAuthentication,
+    RemoteDesktop,
+    PowerShellExecution,
+    ScheduledTaskChange,
+    ScheduledTaskExecution,
+    MalwareDetection,
+    NetworkConnection,
+    RegistryModification,
+    RegistryKeyLastWrite,
+    DnsQuery,
+    WmiPersistence,
+    BitsTransfer,
     ServiceInstallation,
     ProgramExecution,
+    FileOpened,
+    FolderAccess,
+    LogEntry,
 }
 
 impl std::fmt::Display for EventType {
@@ -28,29 +150,69 @@ impl std::fmt::Display for EventType {
             EventType::FileAccess => write!(f, "File Access"),
             EventType::FileMftChange => write!(f, "MFT Entry Changed"),
             EventType::UserLogon => write!(f, "User Logon"),
+            EventType::FailedLogon => write!(f, "Failed Logon"),
+            EventType::UserLogoff => write!(f, "User Logoff"),
+            EventType::ExplicitCredentialLogon => write!(f, "Explicit Credential Logon"),
+            EventType::SpecialPrivilegeLogon => write!(f, "Special Privilege Logon"),
+            EventType::ProcessCreation => write!(f, "Process Creation"),
+            EventType::AccountCreation => write!(f, "Account Creation"),
+            EventType::AccountEnabled => write!(f, "Account Enabled"),
+            EventType::PasswordReset => write!(f, "Password Reset"),
+            EventType::GroupMembershipChange => write!(f, "Group Membership Change"),
+            EventType::AccountChange => write!(f, "Account Change"),
+            EventType::ScheduledTaskCreation => write!(f, "Scheduled Task Creation"),
+            EventType::AuditLogCleared => write!(f, "Audit Log Cleared"),
+            EventType::KerberosTgtRequest => write!(f, "Kerberos TGT Request"),
+            EventType::KerberosServiceTicket => write!(f, "Kerberos Service Ticket"),
+            EventType::NtlmAuthentication => write!(f, "NTLM Authentication"),
+            EventType::RemoteDesktop => write!(f, "Remote Desktop"),
+            EventType::PowerShellExecution => write!(f, "PowerShell Execution"),
+            EventType::ScheduledTaskChange => write!(f, "Scheduled Task Change"),
+            EventType::ScheduledTaskExecution => write!(f, "Scheduled Task Execution"),
+            EventType::MalwareDetection => write!(f, "Malware Detection"),
+            EventType::NetworkConnection => write!(f, "Network Connection"),
+            EventType::RegistryModification => write!(f, "Registry Modification"),
+            EventType::RegistryKeyLastWrite => write!(f, "Registry Key Last Write"),
+            EventType::DnsQuery => write!(f, "DNS Query"),
+            EventType::WmiPersistence => write!(f, "WMI Persistence"),
+            EventType::BitsTransfer => write!(f, "BITS Transfer"),
             EventType::ServiceInstallation => write!(f, "Service Installation"),
             EventType::ProgramExecution => write!(f, "Program Execution"),
+            EventType::FileOpened => write!(f, "File Opened"),
+            EventType::FolderAccess => write!(f, "Folder Access"),
+            EventType::LogEntry => write!(f, "Event Log Entry"),
         }
     }
 }
 
 pub struct Timeline {
     pub events: Vec<TimelineEvent>,
+    current_volume: Option<String>,
 }
 
 impl Timeline {
     pub fn new() -> Self {
         Timeline {
             events: Vec::new(),
+            current_volume: None,
         }
     }
     
-    pub fn add_event(&mut self, event: TimelineEvent) {
+    /// Sets the volume that subsequently added events are tagged with.
+    pub fn set_volume(&mut self, volume: Option<String>) {
+        self.current_volume = volume;
+    }
+    
+    pub fn add_event(&mut self, mut event: TimelineEvent) {
+        if event.volume.is_none() {
+            event.volume = self.current_volume.clone();
+        }
         self.events.push(event);
     }
     
     pub fn add_file_event(&mut self, timestamp: DateTime<Utc>, event_type: EventType, 
-                         file_path: &str, source: &str) {
+                         file_path: &str, source: &str,
+                         timestamp_source: Option<TimestampSource>) -> &mut TimelineEvent {
         let description = match event_type {
             EventType::FileCreation => format!("File '{}' was created.", file_path),
             EventType::FileModification => format!("File '{}' was modified.", file_path),
@@ -59,42 +221,56 @@ impl Timeline {
             _ => format!("File '{}' event occurred.", file_path),
         };
         
-        self.events.push(TimelineEvent {
+        let mut event = TimelineEvent::new(timestamp, event_type, description, source);
+        event.timestamp_source = timestamp_source;
+        self.add_event(event);
+        self.events.last_mut().unwrap()
+    }
+    
+    /// Adds an event log record whose description the caller has built;
+    /// `log_file` is the .evtx file it was read from.
+    pub fn add_log_event(&mut self, timestamp: DateTime<Utc>, event_type: EventType,
+                         description: &str, log_file: &str, record_id: u64) -> &mut TimelineEvent {
+        self.add_event(TimelineEvent::new(
             timestamp,
             event_type,
-            description,
-            source_artifact: source.to_string(),
-        });
+            format!("{} [record {}]", description, record_id),
+            log_file,
+        ));
+        self.events.last_mut().unwrap()
     }
     
-    pub fn add_user_logon(&mut self, timestamp: DateTime<Utc>, username: &str, 
-                          source_ip: &str) {
-        self.events.push(TimelineEvent {
+    /// Adds the LastWrite time of a registry key; `hive_file` is the hive
+    /// it was read from.
+    pub fn add_registry_key_event(&mut self, timestamp: DateTime<Utc>, key_path: &str,
+                                  hive_file: &str) -> &mut TimelineEvent {
+        self.add_event(TimelineEvent::new(
             timestamp,
-            event_type: EventType::UserLogon,
-            description: format!("User '{}' logged on from source IP {}", username, source_ip),
-            source_artifact: "Security.evtx".to_string(),
-        });
+            EventType::RegistryKeyLastWrite,
+            format!("Registry key '{}' was last written.", key_path),
+            hive_file,
+        ));
+        self.events.last_mut().unwrap()
     }
     
-    pub fn add_service_installation(&mut self, timestamp: DateTime<Utc>, 
-                                   service_name: &str) {
-        self.events.push(TimelineEvent {
-            timestamp,
-            event_type: EventType::ServiceInstallation,
-            description: format!("Service '{}' was installed.", service_name),
-            source_artifact: "System.evtx".to_string(),
-        });
+    /// Adds an event read from a registry value, e.g. a user's recently
+    /// opened files; `hive_file` is the hive it was read from.
+    pub fn add_registry_event(&mut self, timestamp: DateTime<Utc>, event_type: EventType,
+                              description: String, hive_file: &str) -> &mut TimelineEvent {
+        self.add_event(TimelineEvent::new(timestamp, event_type, description, hive_file));
+        self.events.last_mut().unwrap()
     }
     
     pub fn add_program_execution(&mut self, timestamp: DateTime<Utc>, 
-                                executable_name: &str, prefetch_file: &str) {
-        self.events.push(TimelineEvent {
+                                executable_name: &str, run_count: u32,
+                                prefetch_file: &str) -> &mut TimelineEvent {
+        self.add_event(TimelineEvent::new(
             timestamp,
-            event_type: EventType::ProgramExecution,
-            description: format!("Executable '{}' was run.", executable_name),
-            source_artifact: prefetch_file.to_string(),
-        });
+            EventType::ProgramExecution,
+            format!("Executable '{}' was run (run count {}).", executable_name, run_count),
+            prefetch_file,
+        ));
+        self.events.last_mut().unwrap()
     }
     
     pub fn sort(&mut self) {
@@ -110,4 +286,4 @@ impl Timeline {
     pub fn is_empty(&self) -> bool {
         self.events.is_empty()
     }
-} 
\ No newline at end of file
+}
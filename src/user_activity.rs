@@ -0,0 +1,393 @@
+use byteorder::{ByteOrder, LittleEndian};
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+
+use crate::registry_parser::{HiveFile, Key, RegistryValue};
+use crate::shell_items;
+use crate::timeline::{EventType, Timeline};
+use crate::util::{read_utf16_string, windows_time_to_utc};
+
+const EXPLORER_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Explorer";
+
+/// UserAssist entries: 72 bytes from Windows 7 on, 16 bytes before.
+const USERASSIST_WIN7_SIZE: usize = 72;
+const USERASSIST_XP_SIZE: usize = 16;
+/// Windows XP starts counting runs at 5.
+const USERASSIST_XP_RUN_OFFSET: u32 = 5;
+
+/// Known folder GUIDs UserAssist uses in place of a directory.
+const USERASSIST_FOLDERS: &[(&str, &str)] = &[
+    ("{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}", r"%SystemRoot%\System32"),
+    ("{D65231B0-B2F1-4857-A4CE-A8E7C6EA7D27}", r"%SystemRoot%\SysWOW64"),
+    ("{F38BF404-1D43-42F2-9305-67DE0B28FC23}", "%SystemRoot%"),
+    ("{6D809377-6AF0-444B-8957-A3773F02200E}", "%ProgramFiles%"),
+    ("{7C5A40EF-A0FB-4BFC-874A-C0F2E0B9FA8E}", "%ProgramFiles(x86)%"),
+    ("{F7F1ED05-9F6D-47A2-AAAE-29D317C6F066}", "%CommonProgramFiles%"),
+    ("{0139D44E-6AFE-49F2-8690-3DAFCAE6FFB8}", r"%ProgramData%\Microsoft\Windows\Start Menu\Programs"),
+    ("{A77F5D77-2E2B-44C3-A6A2-ABA601054A51}", r"%AppData%\Microsoft\Windows\Start Menu\Programs"),
+    ("{9E3995AB-1F9C-4F13-B827-48B24B6C7174}", r"%AppData%\Microsoft\Internet Explorer\Quick Launch\User Pinned"),
+];
+
+/// Adds the Explorer activity recorded in a user's NTUSER.DAT: programs run
+/// (UserAssist, RunMRU), files opened (RecentDocs, OpenSave MRUs) and folders
+/// browsed (LastVisited MRUs, TypedPaths). Returns the number of events.
+pub fn add_user_activity_events(hive_file: &HiveFile, timeline: &mut Timeline) -> usize {
+    let Some(explorer) = hive_file.hive.key(EXPLORER_KEY) else {
+        return 0;
+    };
+    let user = hive_file.user.as_deref().unwrap_or("unknown");
+    let mut activity = UserActivity {
+        user,
+        hive_path: &hive_file.path,
+        timeline,
+        seen: HashSet::new(),
+        event_count: 0,
+    };
+
+    activity.add_userassist(&explorer);
+    if let Some(key) = explorer.subkey("RecentDocs") {
+        activity.add_recent_docs(&key, "RecentDocs");
+    }
+    if let Some(comdlg) = explorer.subkey("ComDlg32") {
+        for name in ["OpenSavePidlMRU", "OpenSaveMRU"] {
+            if let Some(key) = comdlg.subkey(name) {
+                activity.add_open_save_mru(&key, name);
+            }
+        }
+        for name in ["LastVisitedPidlMRU", "LastVisitedMRU"] {
+            if let Some(key) = comdlg.subkey(name) {
+                activity.add_last_visited_mru(&key, name);
+            }
+        }
+    }
+    if let Some(key) = explorer.subkey("RunMRU") {
+        activity.add_run_mru(&key);
+    }
+    if let Some(key) = explorer.subkey("TypedPaths") {
+        activity.add_typed_paths(&key);
+    }
+
+    activity.event_count
+}
+
+struct UserActivity<'a> {
+    user: &'a str,
+    hive_path: &'a str,
+    timeline: &'a mut Timeline,
+    /// Items already reported at a given time; RecentDocs and OpenSave MRUs
+    /// list the latest item both under the extension and overall.
+    seen: HashSet<(DateTime<Utc>, String)>,
+    event_count: usize,
+}
+
+impl UserActivity<'_> {
+    /// Adds an MRU event: only the most recent item is known to be used at
+    /// the key's LastWrite time, the others are listed as detail.
+    fn add_mru_event(&mut self, key: &Key, event_type: EventType, items: Vec<String>,
+                     describe: impl Fn(&str) -> String) {
+        let (Some(last_written), Some(latest)) = (key.last_written, items.first()) else {
+            return;
+        };
+        if !self.seen.insert((last_written, latest.clone())) {
+            return;
+        }
+        let description = describe(latest);
+        self.timeline.add_registry_event(last_written, event_type, description, self.hive_path)
+            .add_detail("Most recent first", items);
+        self.event_count += 1;
+    }
+
+    fn add_userassist(&mut self, explorer: &Key) {
+        let Some(userassist) = explorer.subkey("UserAssist") else {
+            return;
+        };
+        for guid in userassist.subkeys() {
+            let Some(count) = guid.subkey("Count") else {
+                continue;
+            };
+            for value in count.values() {
+                let Some(entry) = UserAssistEntry::parse(&value) else {
+                    continue;
+                };
+                let description = format!(
+                    "User '{}' ran '{}' (UserAssist: run count {}{}).",
+                    self.user, entry.program, entry.run_count,
+                    entry.focus.map(|(count, time)| format!(", focus count {}, focus time {}",
+                                                             count, format_duration(time)))
+                        .unwrap_or_default(),
+                );
+                self.timeline.add_registry_event(entry.last_run, EventType::ProgramExecution,
+                                                 description, self.hive_path);
+                self.event_count += 1;
+            }
+        }
+    }
+
+    /// RecentDocs values hold the file name followed by a shell item; each
+    /// extension has a subkey with its own list.
+    fn add_recent_docs(&mut self, key: &Key, label: &str) {
+        let items = mru_values(key).iter()
+            .map(|value| read_utf16_string(&value.data))
+            .collect();
+        let user = self.user;
+        self.add_mru_event(key, EventType::FileOpened, items, |latest| {
+            format!("User '{}' opened '{}' ({}).", user, latest, label)
+        });
+
+        for subkey in key.subkeys() {
+            let label = format!(r"{}\{}", label, subkey.name);
+            self.add_recent_docs(&subkey, &label);
+        }
+    }
+
+    /// OpenSavePidlMRU values are ID lists of the files chosen in open/save
+    /// dialogs; the XP OpenSaveMRU holds paths as strings.
+    fn add_open_save_mru(&mut self, key: &Key, label: &str) {
+        let items = mru_values(key).iter()
+            .map(|value| value.as_string().unwrap_or_else(|| {
+                shell_items::id_list_path(&shell_items::parse_id_list(&value.data))
+            }))
+            .collect();
+        let user = self.user;
+        self.add_mru_event(key, EventType::FileOpened, items, |latest| {
+            format!("User '{}' opened or saved '{}' in a file dialog ({}).", user, latest, label)
+        });
+
+        for subkey in key.subkeys() {
+            let label = format!(r"{}\{}", label, subkey.name);
+            self.add_open_save_mru(&subkey, &label);
+        }
+    }
+
+    /// LastVisited MRU values hold the program name followed by the folder
+    /// it last used in a file dialog, as an ID list (Vista and later) or a
+    /// string (XP).
+    fn add_last_visited_mru(&mut self, key: &Key, label: &str) {
+        let items: Vec<String> = mru_values(key).iter()
+            .filter_map(|value| {
+                let program = read_utf16_string(&value.data);
+                let rest = value.data.get((program.encode_utf16().count() + 1) * 2..)?;
+                let folder = if label == "LastVisitedPidlMRU" {
+                    shell_items::id_list_path(&shell_items::parse_id_list(rest))
+                } else {
+                    read_utf16_string(rest)
+                };
+                Some(format!("{}: {}", program, folder))
+            })
+            .collect();
+        let user = self.user;
+        self.add_mru_event(key, EventType::FolderAccess, items, |latest| {
+            let (program, folder) = latest.split_once(": ").unwrap_or((latest, ""));
+            format!("User '{}' browsed to '{}' in a file dialog of '{}' ({}).", user, folder, program, label)
+        });
+    }
+
+    /// RunMRU values are the commands typed into the Run dialog, with a
+    /// trailing `\1`.
+    fn add_run_mru(&mut self, key: &Key) {
+        let items = mru_values(key).iter()
+            .filter_map(RegistryValue::as_string)
+            .map(|command| command.strip_suffix(r"\1").map(str::to_string).unwrap_or(command))
+            .collect();
+        let user = self.user;
+        self.add_mru_event(key, EventType::ProgramExecution, items, |latest| {
+            format!("User '{}' ran '{}' from the Run dialog (RunMRU).", user, latest)
+        });
+    }
+
+    /// TypedPaths holds the paths typed into the Explorer address bar,
+    /// `url1` being the most recent.
+    fn add_typed_paths(&mut self, key: &Key) {
+        let mut values: Vec<(u32, String)> = key.values().iter()
+            .filter_map(|value| {
+                let index = value.name.to_lowercase().strip_prefix("url")?.parse().ok()?;
+                Some((index, value.as_string()?))
+            })
+            .collect();
+        values.sort();
+        let items = values.into_iter().map(|(_, path)| path).collect();
+        let user = self.user;
+        self.add_mru_event(key, EventType::FolderAccess, items, |latest| {
+            format!("User '{}' typed '{}' into the Explorer address bar (TypedPaths).", user, latest)
+        });
+    }
+}
+
+/// A decoded UserAssist value.
+struct UserAssistEntry {
+    program: String,
+    run_count: u32,
+    /// Focus count and time in milliseconds, recorded from Windows 7 on.
+    focus: Option<(u32, u32)>,
+    last_run: DateTime<Utc>,
+}
+
+impl UserAssistEntry {
+    /// Decodes the ROT13-encoded value name and the counters. Entries that
+    /// were never run (no last run time) are skipped.
+    fn parse(value: &RegistryValue) -> Option<Self> {
+        let program = expand_known_folder(&rot13(&value.name));
+        let data = &value.data;
+        let (run_count, focus, last_run) = match data.len() {
+            USERASSIST_WIN7_SIZE => (
+                LittleEndian::read_u32(&data[4..8]),
+                Some((LittleEndian::read_u32(&data[8..12]), LittleEndian::read_u32(&data[12..16]))),
+                LittleEndian::read_u64(&data[60..68]),
+            ),
+            USERASSIST_XP_SIZE => (
+                LittleEndian::read_u32(&data[4..8]).saturating_sub(USERASSIST_XP_RUN_OFFSET),
+                None,
+                LittleEndian::read_u64(&data[8..16]),
+            ),
+            _ => return None,
+        };
+
+        Some(UserAssistEntry {
+            program,
+            run_count,
+            focus,
+            last_run: windows_time_to_utc(last_run)?,
+        })
+    }
+}
+
+fn rot13(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'a'..='z' => (((c as u8 - b'a') + 13) % 26 + b'a') as char,
+            'A'..='Z' => (((c as u8 - b'A') + 13) % 26 + b'A') as char,
+            _ => c,
+        })
+        .collect()
+}
+
+/// Replaces a leading known folder GUID with the folder it stands for.
+fn expand_known_folder(path: &str) -> String {
+    USERASSIST_FOLDERS.iter()
+        .find_map(|(guid, folder)| {
+            let rest = path.get(..guid.len()).filter(|prefix| prefix.eq_ignore_ascii_case(guid))
+                .map(|_| &path[guid.len()..])?;
+            Some(format!("{}{}", folder, rest))
+        })
+        .unwrap_or_else(|| path.to_string())
+}
+
+/// The values of an MRU key, most recent first, in the order given by its
+/// MRUListEx (value numbers as dwords) or MRUList (value letters).
+fn mru_values(key: &Key) -> Vec<RegistryValue> {
+    let order: Vec<String> = if let Some(list) = key.value("MRUListEx") {
+        list.data.chunks_exact(4)
+            .map(LittleEndian::read_u32)
+            .take_while(|&index| index != u32::MAX)
+            .map(|index| index.to_string())
+            .collect()
+    } else if let Some(list) = key.value("MRUList") {
+        list.as_string().unwrap_or_default().chars().map(String::from).collect()
+    } else {
+        return Vec::new();
+    };
+
+    let values = key.values();
+    order.iter()
+        .filter_map(|name| values.iter().find(|value| value.name.eq_ignore_ascii_case(name)).cloned())
+        .collect()
+}
+
+fn format_duration(milliseconds: u32) -> String {
+    let seconds = milliseconds / 1000;
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry_parser::tests::HiveBuilder;
+    use crate::registry_parser::{Hive, REG_BINARY, REG_SZ};
+
+    /// 2024-01-15 10:02:03 UTC.
+    const RUN_TIME: u64 = 133497865234567890;
+
+    fn userassist_value(name: &str, data: Vec<u8>) -> RegistryValue {
+        RegistryValue { name: rot13(name), value_type: REG_BINARY, data }
+    }
+
+    #[test]
+    fn rot13_decodes_userassist_names() {
+        assert_eq!(rot13("Zvpebfbsg.Jvaqbjf.Rkcybere"), "Microsoft.Windows.Explorer");
+        assert_eq!(rot13(r"P:\Hfref\nyvpr\1.rkr"), r"C:\Users\alice\1.exe");
+        assert_eq!(rot13(&rot13("Any Text {42}")), "Any Text {42}");
+    }
+
+    #[test]
+    fn parses_windows_7_userassist_entries() {
+        let mut data = vec![0u8; USERASSIST_WIN7_SIZE];
+        LittleEndian::write_u32(&mut data[4..8], 3);
+        LittleEndian::write_u32(&mut data[8..12], 2);
+        LittleEndian::write_u32(&mut data[12..16], 3_723_000);
+        LittleEndian::write_u64(&mut data[60..68], RUN_TIME);
+        let value = userassist_value(r"{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\cmd.exe", data);
+
+        let entry = UserAssistEntry::parse(&value).unwrap();
+        assert_eq!(entry.program, r"%SystemRoot%\System32\cmd.exe");
+        assert_eq!(entry.run_count, 3);
+        assert_eq!(entry.focus, Some((2, 3_723_000)));
+        assert_eq!(entry.last_run.to_rfc3339(), "2024-01-15T10:02:03.456789+00:00");
+        assert_eq!(format_duration(3_723_000), "1:02:03");
+    }
+
+    #[test]
+    fn parses_windows_xp_userassist_entries() {
+        let mut data = vec![0u8; USERASSIST_XP_SIZE];
+        LittleEndian::write_u32(&mut data[4..8], 7);
+        LittleEndian::write_u64(&mut data[8..16], RUN_TIME);
+        let entry = UserAssistEntry::parse(&userassist_value(r"UEME_RUNPATH:C:\tool.exe", data)).unwrap();
+        assert_eq!(entry.program, r"UEME_RUNPATH:C:\tool.exe");
+        assert_eq!(entry.run_count, 2);
+        assert_eq!(entry.focus, None);
+    }
+
+    #[test]
+    fn skips_userassist_entries_never_run_or_of_unknown_size() {
+        let never_run = userassist_value("cmd.exe", vec![0u8; USERASSIST_WIN7_SIZE]);
+        assert!(UserAssistEntry::parse(&never_run).is_none());
+        let unknown = userassist_value("cmd.exe", vec![0u8; 24]);
+        assert!(UserAssistEntry::parse(&unknown).is_none());
+    }
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().chain([0]).flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn orders_values_by_mru_list_ex() {
+        let mut builder = HiveBuilder::new();
+        let order: Vec<u8> = [2u32, 0, 1, u32::MAX, 0].iter().flat_map(|index| index.to_le_bytes()).collect();
+        let values = [
+            builder.value("0", REG_BINARY, &utf16("first.txt")),
+            builder.value("1", REG_BINARY, &utf16("second.txt")),
+            builder.value("2", REG_BINARY, &utf16("third.txt")),
+            builder.value("MRUListEx", REG_BINARY, &order),
+        ];
+        let root = builder.key("RecentDocs", 0, u32::MAX, &values);
+        let hive = Hive::new(builder.build(root)).unwrap();
+
+        let names: Vec<String> = mru_values(&hive.root_key().unwrap()).into_iter().map(|value| value.name).collect();
+        assert_eq!(names, ["2", "0", "1"]);
+    }
+
+    #[test]
+    fn orders_values_by_mru_list() {
+        let mut builder = HiveBuilder::new();
+        let values = [
+            builder.value("a", REG_SZ, &utf16("notepad")),
+            builder.value("b", REG_SZ, &utf16("cmd")),
+            builder.value("MRUList", REG_SZ, &utf16("bxa")),
+        ];
+        let root = builder.key("RunMRU", 0, u32::MAX, &values);
+        let hive = Hive::new(builder.build(root)).unwrap();
+
+        // Letters without a value are skipped
+        let names: Vec<String> = mru_values(&hive.root_key().unwrap()).into_iter().map(|value| value.name).collect();
+        assert_eq!(names, ["b", "a"]);
+    }
+}
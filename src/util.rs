@@ -0,0 +1,86 @@
+use byteorder::{ByteOrder, LittleEndian};
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+
+/// 100-nanosecond intervals between 1601-01-01 (the FILETIME epoch) and
+/// 1970-01-01.
+const FILETIME_UNIX_EPOCH: i64 = 116444736000000000;
+const FILETIME_TICKS_PER_SECOND: i64 = 10000000;
+/// Structures recovered by scanning for their signature are taken for false
+/// matches when their timestamp falls outside 1990-2100.
+const EARLIEST_PLAUSIBLE_YEAR: i32 = 1990;
+const LATEST_PLAUSIBLE_YEAR: i32 = 2100;
+
+/// Converts a Windows FILETIME (100-nanosecond intervals since 1601-01-01)
+/// to UTC. Zero means "not set"; values past what a FILETIME can hold come
+/// from corrupt data. Both give `None`.
+pub fn windows_time_to_utc(windows_time: u64) -> Option<DateTime<Utc>> {
+    if windows_time == 0 {
+        return None;
+    }
+    let intervals = i64::try_from(windows_time).ok()?.checked_sub(FILETIME_UNIX_EPOCH)?;
+    let unix_seconds = intervals.div_euclid(FILETIME_TICKS_PER_SECOND);
+    let nanoseconds = intervals.rem_euclid(FILETIME_TICKS_PER_SECOND) as u32 * 100;
+    match Utc.timestamp_opt(unix_seconds, nanoseconds) {
+        chrono::LocalResult::Single(dt) => Some(dt),
+        _ => None,
+    }
+}
+
+/// Whether a timestamp of a carved structure is in the range real data
+/// falls in.
+pub fn is_plausible_time(time: &DateTime<Utc>) -> bool {
+    (EARLIEST_PLAUSIBLE_YEAR..LATEST_PLAUSIBLE_YEAR).contains(&time.year())
+}
+
+/// Reads a little-endian UTF-16 string up to its NUL terminator or the end
+/// of `data`.
+pub fn read_utf16_string(data: &[u8]) -> String {
+    let units: Vec<u16> = data.chunks_exact(2)
+        .map(LittleEndian::read_u16)
+        .take_while(|&unit| unit != 0)
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_filetimes() {
+        assert_eq!(windows_time_to_utc(116444736000000000),
+                   Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).single());
+        let time = windows_time_to_utc(133497865234567890).unwrap();
+        assert_eq!(time.to_rfc3339(), "2024-01-15T10:02:03.456789+00:00");
+        // Before the Unix epoch
+        assert_eq!(windows_time_to_utc(1),
+                   Utc.with_ymd_and_hms(1601, 1, 1, 0, 0, 0).single()
+                       .map(|time| time + chrono::Duration::nanoseconds(100)));
+    }
+
+    #[test]
+    fn rejects_unset_and_out_of_range_filetimes() {
+        assert_eq!(windows_time_to_utc(0), None);
+        assert_eq!(windows_time_to_utc(1 << 63), None);
+        assert_eq!(windows_time_to_utc((1 << 63) + 100000000000000000), None);
+        assert_eq!(windows_time_to_utc(u64::MAX), None);
+    }
+
+    #[test]
+    fn bounds_plausible_times() {
+        let time = |year| Utc.with_ymd_and_hms(year, 1, 1, 0, 0, 0).unwrap();
+        assert!(is_plausible_time(&time(1990)));
+        assert!(is_plausible_time(&time(2099)));
+        assert!(!is_plausible_time(&time(1989)));
+        assert!(!is_plausible_time(&time(2100)));
+    }
+
+    #[test]
+    fn reads_utf16_strings() {
+        let data: Vec<u8> = "cmd.exe\0junk".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert_eq!(read_utf16_string(&data), "cmd.exe");
+        // Unterminated, with an odd trailing byte
+        assert_eq!(read_utf16_string(&[b'a', 0, b'b', 0, b'c']), "ab");
+        assert_eq!(read_utf16_string(&[]), "");
+    }
+}
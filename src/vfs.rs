@@ -0,0 +1,394 @@
+use anyhow::{Context, Result};
+use byteorder::{ByteOrder, LittleEndian};
+use std::io::{self, Read, Seek, SeekFrom};
+use tracing::warn;
+
+use crate::image_source::ImageSource;
+use crate::mft_parser::{self, FileNameNamespace, MftAttribute, MftEntry};
+use crate::ntfs_volume::{apply_fixups, FixupStatus, NtfsVolume};
+use crate::path_resolver::{split_file_reference, ROOT_RECORD};
+
+const ATTRIBUTE_DATA: u32 = 0x80;
+const ATTRIBUTE_INDEX_ROOT: u32 = 0x90;
+const ATTRIBUTE_INDEX_ALLOCATION: u32 = 0xA0;
+const ATTRIBUTE_BITMAP: u32 = 0xB0;
+/// Name of the directory index, keyed by $FILE_NAME.
+const DIRECTORY_INDEX: &str = "$I30";
+const INDEX_ENTRY_LAST: u32 = 0x02;
+const FILE_NAME_IS_DIRECTORY: u32 = 0x1000_0000;
+
+/// A directory entry as recorded in its parent's index.
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    pub record_number: u64,
+    pub is_directory: bool,
+    namespace: FileNameNamespace,
+}
+
+/// Read access to the files of an NTFS volume by path, through the
+/// directory indexes ($I30) starting from the root directory.
+///
+/// Paths are case-insensitive and may use `\` or `/`, with an optional drive
+/// letter (`C:\Windows\System32\winevt\Logs\Security.evtx`). An alternate
+/// data stream is addressed as `file:stream`.
+pub struct Vfs<'a> {
+    volume: NtfsVolume<'a>,
+}
+
+impl<'a> Vfs<'a> {
+    pub fn open(image: &'a dyn ImageSource) -> Result<Self> {
+        Ok(Vfs {
+            volume: NtfsVolume::open(image)?,
+        })
+    }
+
+    pub fn volume(&self) -> &NtfsVolume<'a> {
+        &self.volume
+    }
+
+    /// Resolves `path` to its MFT record number.
+    pub fn lookup(&self, path: &str) -> Result<u64> {
+        let mut record_number = ROOT_RECORD;
+
+        for component in path_components(path) {
+            let entries = self.read_index(record_number)
+                .with_context(|| format!("Failed to read directory containing '{}'", component))?;
+            record_number = entries.iter()
+                .find(|entry| names_equal(&entry.name, component))
+                .map(|entry| entry.record_number)
+                .with_context(|| format!("'{}' not found", path))?;
+        }
+
+        Ok(record_number)
+    }
+
+    /// Lists a directory. DOS 8.3 aliases of long names are left out.
+    pub fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>> {
+        let record_number = self.lookup(path)?;
+        let mut entries: Vec<DirEntry> = self.read_index(record_number)?
+            .into_iter()
+            .filter(|entry| entry.namespace != FileNameNamespace::Dos)
+            .collect();
+        entries.sort_by_key(|entry| entry.name.to_uppercase());
+        entries.dedup_by(|a, b| a.record_number == b.record_number && a.name == b.name);
+        Ok(entries)
+    }
+
+    /// Returns the paths matching `pattern`, where `*` and `?` wildcards may
+    /// appear in any component, e.g. `Windows/Prefetch/*.pf`.
+    pub fn glob(&self, pattern: &str) -> Result<Vec<String>> {
+        let mut matches = vec![String::new()];
+
+        let components: Vec<&str> = path_components(pattern).collect();
+        for (depth, component) in components.iter().enumerate() {
+            let is_last = depth + 1 == components.len();
+            let mut next = Vec::new();
+            for parent in &matches {
+                let entries = match self.read_dir(parent) {
+                    Ok(entries) => entries,
+                    Err(_) => continue,
+                };
+                for entry in entries {
+                    if (is_last || entry.is_directory) && wildcard_match(component, &entry.name) {
+                        next.push(format!("{}\\{}", parent, entry.name));
+                    }
+                }
+            }
+            matches = next;
+        }
+
+        matches.retain(|path| !path.is_empty());
+        Ok(matches)
+    }
+
+    /// Opens the default data stream of a file, or the named stream given as
+    /// `file:stream`.
+    pub fn open_file(&self, path: &str) -> Result<FileStream<'_, 'a>> {
+        let (file_path, stream) = split_stream_name(path);
+        let record_number = self.lookup(file_path)?;
+        let entry = self.volume.read_file_record(record_number)?;
+        let attribute = find_data_attribute(entry, stream)
+            .with_context(|| match stream {
+                "" => format!("'{}' has no data stream", file_path),
+                stream => format!("'{}' has no stream named '{}'", file_path, stream),
+            })?;
+
+        Ok(FileStream {
+            volume: &self.volume,
+            attribute,
+            position: 0,
+            decompressed: None,
+        })
+    }
+
+    /// Reads the whole content of a file or stream.
+    pub fn read_file(&self, path: &str) -> Result<Vec<u8>> {
+        let mut content = Vec::new();
+        self.open_file(path)?
+            .read_to_end(&mut content)
+            .with_context(|| format!("Failed to read '{}'", path))?;
+        Ok(content)
+    }
+
+    /// Reads every entry of the $I30 index of directory `record_number`:
+    /// those in $INDEX_ROOT and those in the in-use index buffers of
+    /// $INDEX_ALLOCATION.
+    fn read_index(&self, record_number: u64) -> Result<Vec<DirEntry>> {
+        let entry = self.volume.read_file_record(record_number)?;
+        let index_attribute = |attribute_type| entry.attributes.iter()
+            .find(|attr| attr.attribute_type == attribute_type && attr.name == DIRECTORY_INDEX);
+
+        let root = index_attribute(ATTRIBUTE_INDEX_ROOT)
+            .context("Not a directory")?;
+        if root.content.len() < 32 {
+            anyhow::bail!("$INDEX_ROOT too short");
+        }
+        let block_size = LittleEndian::read_u32(&root.content[8..12]) as usize;
+
+        let mut entries = Vec::new();
+        parse_index_node(&root.content[16..], &mut entries);
+
+        if let Some(allocation) = index_attribute(ATTRIBUTE_INDEX_ALLOCATION) {
+            let buffers = self.volume.read_attribute(allocation)?;
+            let bitmap = index_attribute(ATTRIBUTE_BITMAP)
+                .and_then(|bitmap| self.volume.read_attribute(bitmap).ok());
+
+            for (block, buffer) in buffers.chunks_exact(block_size.max(512)).enumerate() {
+                let in_use = bitmap.as_ref()
+                    .is_none_or(|bitmap| bitmap.get(block / 8).is_some_and(|byte| byte & (1 << (block % 8)) != 0));
+                if !in_use || &buffer[..4] != b"INDX" {
+                    continue;
+                }
+
+                // A damaged buffer loses its own entries, not the listing
+                let mut buffer = buffer.to_vec();
+                match apply_fixups(&mut buffer) {
+                    Ok(FixupStatus::Valid) => {}
+                    Ok(FixupStatus::Torn) => warn!("Index buffer {} of directory {} is torn", block, record_number),
+                    Err(e) => {
+                        warn!("Skipping index buffer {} of directory {}: {}", block, record_number, e);
+                        continue;
+                    }
+                }
+                parse_index_node(&buffer[24..], &mut entries);
+            }
+        }
+
+        // The root directory lists itself as "."
+        entries.retain(|entry| entry.record_number != record_number);
+        Ok(entries)
+    }
+}
+
+/// A readable, seekable view of one data stream of a file.
+pub struct FileStream<'v, 'a> {
+    volume: &'v NtfsVolume<'a>,
+    attribute: MftAttribute,
+    position: u64,
+    /// Compressed streams are decompressed whole on first read.
+    decompressed: Option<Vec<u8>>,
+}
+
+impl FileStream<'_, '_> {
+    pub fn len(&self) -> u64 {
+        if self.attribute.non_resident {
+            self.attribute.data_size
+        } else {
+            self.attribute.content.len() as u64
+        }
+    }
+}
+
+impl Read for FileStream<'_, '_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.len().saturating_sub(self.position);
+        let length = (buf.len() as u64).min(remaining) as usize;
+        if length == 0 {
+            return Ok(0);
+        }
+        let start = self.position as usize;
+
+        if !self.attribute.non_resident {
+            buf[..length].copy_from_slice(&self.attribute.content[start..start + length]);
+        } else if self.attribute.is_compressed() {
+            if self.decompressed.is_none() {
+                let content = self.volume.read_attribute(&self.attribute).map_err(io::Error::other)?;
+                self.decompressed = Some(content);
+            }
+            let content = self.decompressed.as_deref().unwrap_or_default();
+            buf[..length].copy_from_slice(&content[start..start + length]);
+        } else {
+            let data = self.volume.read_runs(&self.attribute.data_runs, self.position, length)
+                .map_err(io::Error::other)?;
+            buf[..length].copy_from_slice(&data);
+            // Data past the initialized size is undefined on disk
+            let initialized = self.attribute.initialized_size.saturating_sub(self.position) as usize;
+            if initialized < length {
+                buf[initialized..length].fill(0);
+            }
+        }
+
+        self.position += length as u64;
+        Ok(length)
+    }
+}
+
+impl Seek for FileStream<'_, '_> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let target = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek before start of stream"))?;
+        Ok(self.position)
+    }
+}
+
+/// Collects the entries of an index node, starting at its node header.
+fn parse_index_node(node: &[u8], entries: &mut Vec<DirEntry>) {
+    if node.len() < 16 {
+        return;
+    }
+    let mut position = LittleEndian::read_u32(&node[0..4]) as usize;
+    let end = (LittleEndian::read_u32(&node[4..8]) as usize).min(node.len());
+
+    while position + 16 <= end {
+        let reference = LittleEndian::read_u64(&node[position..position + 8]);
+        let length = LittleEndian::read_u16(&node[position + 8..position + 10]) as usize;
+        let key_length = LittleEndian::read_u16(&node[position + 10..position + 12]) as usize;
+        let flags = LittleEndian::read_u32(&node[position + 12..position + 16]);
+        if flags & INDEX_ENTRY_LAST != 0 || length < 16 || position + length > end {
+            break;
+        }
+
+        let key_end = (position + 16 + key_length).min(position + length);
+        if let Ok(file_name) = mft_parser::parse_filename_attribute(&node[position + 16..key_end]) {
+            entries.push(DirEntry {
+                name: file_name.filename,
+                record_number: split_file_reference(reference).0,
+                is_directory: file_name.file_flags & FILE_NAME_IS_DIRECTORY != 0,
+                namespace: file_name.namespace,
+            });
+        }
+
+        // Sub-node entries are collected from their own index buffers
+        position += length;
+    }
+}
+
+fn find_data_attribute(entry: MftEntry, stream: &str) -> Option<MftAttribute> {
+    entry.attributes.into_iter()
+        .find(|attr| attr.attribute_type == ATTRIBUTE_DATA && names_equal(&attr.name, stream))
+}
+
+/// Splits a path into its components, dropping any drive letter.
+fn path_components(path: &str) -> impl Iterator<Item = &str> {
+    let path = match path.as_bytes() {
+        [drive, b':', ..] if drive.is_ascii_alphabetic() => &path[2..],
+        _ => path,
+    };
+    path.split(['\\', '/']).filter(|component| !component.is_empty() && *component != ".")
+}
+
+/// Splits `file:stream` into the file path and stream name (empty for the
+/// default stream). A drive letter's colon is not a stream separator.
+fn split_stream_name(path: &str) -> (&str, &str) {
+    let last_component = path.rfind(['\\', '/']).map_or(0, |index| index + 1);
+    match path[last_component..].find(':') {
+        Some(colon) if last_component > 0 || colon != 1 => {
+            let colon = last_component + colon;
+            (&path[..colon], &path[colon + 1..])
+        }
+        _ => (path, ""),
+    }
+}
+
+fn names_equal(a: &str, b: &str) -> bool {
+    a.chars().flat_map(char::to_uppercase).eq(b.chars().flat_map(char::to_uppercase))
+}
+
+/// Case-insensitive match of `name` against a pattern with `*` and `?`.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().flat_map(char::to_uppercase).collect();
+    let name: Vec<char> = name.chars().flat_map(char::to_uppercase).collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_wildcards_ignoring_case() {
+        assert!(wildcard_match("*.pf", "CMD.EXE-4A81B364.pf"));
+        assert!(wildcard_match("*.PF", "cmd.exe-4a81b364.pf"));
+        assert!(!wildcard_match("*.pf", "cmd.pfx"));
+        assert!(wildcard_match("?md.exe", "CMD.EXE"));
+        assert!(!wildcard_match("?md.exe", "md.exe"));
+        assert!(wildcard_match("Security.evtx", "SECURITY.EVTX"));
+        assert!(!wildcard_match("Security.evtx", "Security.evtx.bak"));
+    }
+
+    #[test]
+    fn wildcards_backtrack_and_match_empty_names() {
+        assert!(wildcard_match("a*b*c", "aXbYbZc"));
+        assert!(!wildcard_match("a*b*c", "aXbYbZ"));
+        assert!(wildcard_match("**x", "x"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("", ""));
+        assert!(!wildcard_match("", "a"));
+        assert!(!wildcard_match("?", ""));
+    }
+
+    #[test]
+    fn splits_stream_names_from_paths() {
+        assert_eq!(split_stream_name(r"C:\Users\alice\dl.exe:Zone.Identifier"),
+                   (r"C:\Users\alice\dl.exe", "Zone.Identifier"));
+        assert_eq!(split_stream_name("dl.exe:Zone.Identifier"), ("dl.exe", "Zone.Identifier"));
+        assert_eq!(split_stream_name("Windows/notes.txt:hidden"), ("Windows/notes.txt", "hidden"));
+        // An empty stream name is the default stream
+        assert_eq!(split_stream_name(r"C:\dl.exe:"), (r"C:\dl.exe", ""));
+    }
+
+    #[test]
+    fn drive_letters_are_not_stream_separators() {
+        assert_eq!(split_stream_name(r"C:\Windows\notepad.exe"), (r"C:\Windows\notepad.exe", ""));
+        assert_eq!(split_stream_name("C:"), ("C:", ""));
+        assert_eq!(split_stream_name(r"C:\"), (r"C:\", ""));
+        let components: Vec<&str> = path_components(r"C:\Windows\.\System32//cmd.exe").collect();
+        assert_eq!(components, ["Windows", "System32", "cmd.exe"]);
+    }
+
+    #[test]
+    fn names_compare_with_full_case_folding() {
+        assert!(names_equal("Zone.Identifier", "ZONE.IDENTIFIER"));
+        assert!(names_equal("straße", "STRASSE"));
+        assert!(!names_equal("file", "files"));
+    }
+}
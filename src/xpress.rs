@@ -0,0 +1,327 @@
+use anyhow::Result;
+
+/// Every block of compressed data decodes to at most this many bytes and
+/// starts with its own Huffman table.
+const BLOCK_SIZE: usize = 65536;
+/// Symbols 0-255 are literals and 256-511 encode a match length and offset.
+const SYMBOL_COUNT: usize = 512;
+/// The table stores each symbol's code length in a nibble.
+const TABLE_SIZE: usize = SYMBOL_COUNT / 2;
+const MAX_CODE_LENGTH: u32 = 15;
+
+/// Decompresses Xpress Huffman ([MS-XCA] LZ77+Huffman) data into
+/// `output_size` bytes.
+pub fn decompress_xpress_huffman(data: &[u8], output_size: usize) -> Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(output_size);
+    let mut position = 0;
+
+    while output.len() < output_size {
+        if position + TABLE_SIZE > data.len() {
+            anyhow::bail!("Xpress Huffman data ends before {} of {} bytes were decoded",
+                          output.len(), output_size);
+        }
+        let decode_table = build_decode_table(&data[position..position + TABLE_SIZE])?;
+        position += TABLE_SIZE;
+
+        let mut reader = BitReader::new(data, position);
+        let block_end = (output.len() + BLOCK_SIZE).min(output_size);
+
+        while output.len() < block_end {
+            let (symbol, length) = decode_table[reader.peek(MAX_CODE_LENGTH) as usize];
+            if length == 0 {
+                anyhow::bail!("Invalid Huffman code at byte {} of Xpress Huffman data", reader.position);
+            }
+            reader.consume(length as u32);
+
+            if symbol < 256 {
+                output.push(symbol as u8);
+                continue;
+            }
+
+            let symbol = symbol as usize - 256;
+            let offset_bits = (symbol >> 4) as u32;
+
+            // Lengths that do not fit the symbol continue in the byte stream
+            let mut match_length = symbol & 0xF;
+            if match_length == 0xF {
+                match_length = reader.read_byte()? as usize;
+                if match_length == 0xFF {
+                    match_length = reader.read_u16()? as usize;
+                    if match_length == 0 {
+                        match_length = reader.read_u32()? as usize;
+                    }
+                    if match_length < 0xF {
+                        anyhow::bail!("Invalid match length in Xpress Huffman data");
+                    }
+                    match_length -= 0xF;
+                }
+                match_length += 0xF;
+            }
+            match_length += 3;
+
+            let match_offset = (reader.peek(offset_bits) as usize) + (1 << offset_bits);
+            reader.consume(offset_bits);
+
+            if match_offset > output.len() {
+                anyhow::bail!("Xpress Huffman match reaches before the start of the data");
+            }
+            for _ in 0..match_length.min(output_size - output.len()) {
+                output.push(output[output.len() - match_offset]);
+            }
+        }
+
+        position = reader.position;
+    }
+
+    Ok(output)
+}
+
+/// Maps every 15-bit prefix to the symbol whose canonical code it starts
+/// with, and that code's length (zero for prefixes no code covers).
+fn build_decode_table(table: &[u8]) -> Result<Vec<(u16, u8)>> {
+    let lengths: Vec<u8> = table.iter()
+        .flat_map(|&byte| [byte & 0xF, byte >> 4])
+        .collect();
+
+    let mut decode_table = vec![(0u16, 0u8); 1 << MAX_CODE_LENGTH];
+    let mut next_code = 0;
+    for length in 1..=MAX_CODE_LENGTH as u8 {
+        let span = 1 << (MAX_CODE_LENGTH - length as u32);
+        for (symbol, _) in lengths.iter().enumerate().filter(|(_, &l)| l == length) {
+            if next_code + span > decode_table.len() {
+                anyhow::bail!("Xpress Huffman table is oversubscribed");
+            }
+            decode_table[next_code..next_code + span].fill((symbol as u16, length));
+            next_code += span;
+        }
+    }
+
+    Ok(decode_table)
+}
+
+/// Reads the bit stream, which is made of little-endian 16-bit words filled
+/// from their most significant bit. Extra match length bytes are interleaved
+/// with the words, at the position the next word would be read from.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bits: u32,
+    available: i32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8], position: usize) -> Self {
+        let mut reader = BitReader {
+            data,
+            position,
+            bits: 0,
+            available: 16,
+        };
+        reader.bits = (reader.next_word() << 16) | reader.next_word();
+        reader
+    }
+
+    /// Words past the end of the data read as zero; the final codes of a
+    /// stream may sit in the last word.
+    fn next_word(&mut self) -> u32 {
+        let word = match self.data.get(self.position..self.position + 2) {
+            Some(bytes) => u16::from_le_bytes([bytes[0], bytes[1]]) as u32,
+            None => 0,
+        };
+        self.position += 2;
+        word
+    }
+
+    fn peek(&self, count: u32) -> u32 {
+        if count == 0 {
+            0
+        } else {
+            self.bits >> (32 - count)
+        }
+    }
+
+    fn consume(&mut self, count: u32) {
+        self.bits <<= count;
+        self.available -= count as i32;
+        if self.available < 0 {
+            self.bits |= self.next_word() << -self.available;
+            self.available += 16;
+        }
+    }
+
+    fn read_bytes<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let bytes = self.data.get(self.position..self.position + N)
+            .ok_or_else(|| anyhow::anyhow!("Truncated match length in Xpress Huffman data"))?;
+        self.position += N;
+        Ok(bytes.try_into()?)
+    }
+
+    fn read_byte(&mut self) -> Result<u8> {
+        Ok(self.read_bytes::<1>()?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.read_bytes()?))
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.read_bytes()?))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) enum Token {
+        Literal(u8),
+        Match { length: usize, offset: usize },
+    }
+
+    /// Packs bits into the 16-bit words the decoder reads, reserving each
+    /// word before the extra bytes that follow it, as the encoder does.
+    struct BitWriter {
+        block: Vec<u8>,
+        words: Vec<usize>,
+        bits: Vec<bool>,
+        available: i32,
+    }
+
+    impl BitWriter {
+        fn write_bits(&mut self, count: u32, value: u32) {
+            self.bits.extend((0..count).rev().map(|bit| value >> bit & 1 != 0));
+            self.available -= count as i32;
+            if self.available < 0 {
+                self.reserve_word();
+                self.available += 16;
+            }
+        }
+
+        fn reserve_word(&mut self) {
+            self.words.push(self.block.len());
+            self.block.extend([0, 0]);
+        }
+
+        fn finish(mut self) -> Vec<u8> {
+            self.bits.resize(self.words.len() * 16, false);
+            for (word, bits) in self.words.iter().zip(self.bits.chunks(16)) {
+                let value = bits.iter().fold(0u16, |value, &bit| value << 1 | bit as u16);
+                self.block[*word..*word + 2].copy_from_slice(&value.to_le_bytes());
+            }
+            self.block
+        }
+    }
+
+    /// Encodes one block in which all 512 symbols have 9-bit codes, so each
+    /// symbol's code is its own number.
+    pub(crate) fn encode_block(tokens: &[Token]) -> Vec<u8> {
+        let mut writer = BitWriter { block: vec![0x99; TABLE_SIZE], words: Vec::new(), bits: Vec::new(), available: 16 };
+        writer.reserve_word();
+        writer.reserve_word();
+
+        for token in tokens {
+            match *token {
+                Token::Literal(byte) => writer.write_bits(9, byte as u32),
+                Token::Match { length, offset } => {
+                    let offset_bits = offset.ilog2();
+                    let length = length - 3;
+                    writer.write_bits(9, 256 + (offset_bits << 4) + length.min(15) as u32);
+                    if length >= 15 {
+                        if length - 15 < 255 {
+                            writer.block.push((length - 15) as u8);
+                        } else {
+                            writer.block.push(0xFF);
+                            writer.block.extend((length as u16).to_le_bytes());
+                        }
+                    }
+                    writer.write_bits(offset_bits, (offset - (1 << offset_bits)) as u32);
+                }
+            }
+        }
+        writer.finish()
+    }
+
+    fn literals(text: &[u8]) -> Vec<Token> {
+        text.iter().map(|&byte| Token::Literal(byte)).collect()
+    }
+
+    #[test]
+    fn decodes_literals() {
+        let text = b"abcdefghijklmnopqrstuvwxyz";
+        let data = encode_block(&literals(text));
+        assert_eq!(decompress_xpress_huffman(&data, text.len()).unwrap(), text);
+    }
+
+    #[test]
+    fn decodes_overlapping_and_long_matches() {
+        let mut tokens = literals(b"abc");
+        tokens.push(Token::Match { length: 30, offset: 3 });
+        // Length in an extra byte, then in an extra byte and a word
+        tokens.push(Token::Match { length: 100, offset: 33 });
+        tokens.push(Token::Match { length: 1000, offset: 1 });
+        tokens.extend(literals(b"end"));
+        let data = encode_block(&tokens);
+
+        let output = decompress_xpress_huffman(&data, 1136).unwrap();
+        let mut expected = b"abc".repeat(44);
+        expected.extend([b'a'; 1001]);
+        expected.extend(b"end");
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn each_block_starts_with_its_own_table() {
+        let mut data = encode_block(&[Token::Literal(b'x'), Token::Match { length: 65535, offset: 1 }]);
+        data.extend(encode_block(&literals(b"yz")));
+
+        let output = decompress_xpress_huffman(&data, BLOCK_SIZE + 2).unwrap();
+        assert!(output[..BLOCK_SIZE].iter().all(|&byte| byte == b'x'));
+        assert_eq!(&output[BLOCK_SIZE..], b"yz");
+    }
+
+    /// The compressed outputs of the LZ77+Huffman examples in [MS-XCA]
+    /// section 3.2, as produced by the Windows implementation: a 256-byte
+    /// table of 4-bit code lengths, of which only these bytes are non-zero,
+    /// then the bit stream.
+    fn ms_xca_example(lengths: &[(usize, &[u8])], stream: &[u8]) -> Vec<u8> {
+        let mut data = vec![0u8; TABLE_SIZE];
+        for &(offset, bytes) in lengths {
+            data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        }
+        data.extend(stream);
+        data
+    }
+
+    #[test]
+    fn decodes_the_ms_xca_examples() {
+        let alphabet = ms_xca_example(
+            &[(0x30, &[0x50, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x45, 0x44, 0x04]),
+              (0x80, &[0x04])],
+            &[0xD8, 0x52, 0x3E, 0xD7, 0x94, 0x11, 0x5B, 0xE9, 0x19, 0x5F, 0xF9, 0xD6, 0x7C, 0xDF, 0x8D, 0x04,
+              0x00, 0x00, 0x00, 0x00],
+        );
+        assert_eq!(decompress_xpress_huffman(&alphabet, 26).unwrap(), b"abcdefghijklmnopqrstuvwxyz");
+
+        // "abc" followed by a 297-byte match at offset 3, whose length is
+        // stored in an extra byte and word after the first two words
+        let repeated = ms_xca_example(
+            &[(0x30, &[0x30, 0x23]), (0x80, &[0x02]), (0x8F, &[0x20])],
+            &[0xA8, 0xDC, 0x00, 0x00, 0xFF, 0x26, 0x01],
+        );
+        assert_eq!(decompress_xpress_huffman(&repeated, 300).unwrap(), b"abc".repeat(100));
+    }
+
+    #[test]
+    fn rejects_corrupt_data() {
+        // Too short for the table
+        assert!(decompress_xpress_huffman(&[0x99; 100], 10).is_err());
+        // Match reaching before the start of the output
+        let data = encode_block(&[Token::Literal(b'a'), Token::Match { length: 3, offset: 2 }]);
+        assert!(decompress_xpress_huffman(&data, 4).is_err());
+        // Every symbol with a 1-bit code
+        assert!(decompress_xpress_huffman(&[0x11; TABLE_SIZE + 4], 1).is_err());
+        // Ends before the declared size
+        let data = encode_block(&literals(b"abc"));
+        assert!(decompress_xpress_huffman(&data, BLOCK_SIZE + 1).is_err());
+    }
+}